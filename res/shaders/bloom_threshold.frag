@@ -0,0 +1,44 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0) uniform sampler inputSampler;
+layout(binding = 1) uniform texture2D inputImage;
+
+// must match PostConstants in post.rs
+layout(push_constant) uniform PostConstants {
+    vec2 texelSize;
+    vec2 direction;
+    float exposure;
+    float gamma;
+    float bloomThreshold;
+    float bloomIntensity;
+    uint tonemapper;
+    uint flags;
+    float paperWhite;
+    float maxLuminance;
+} constants;
+
+// stops single very bright pixels flickering as the mesh moves
+const float MAX_BRIGHTNESS = 64.0;
+
+// half resolution box filtered bright parts of the scene
+void main() {
+    vec2 offset = constants.texelSize * 0.5;
+
+    vec3 color = vec3(0.0);
+    color += texture(sampler2D(inputImage, inputSampler), fragUv + vec2(-offset.x, -offset.y)).rgb;
+    color += texture(sampler2D(inputImage, inputSampler), fragUv + vec2(offset.x, -offset.y)).rgb;
+    color += texture(sampler2D(inputImage, inputSampler), fragUv + vec2(-offset.x, offset.y)).rgb;
+    color += texture(sampler2D(inputImage, inputSampler), fragUv + vec2(offset.x, offset.y)).rgb;
+    color = min(color * 0.25, vec3(MAX_BRIGHTNESS));
+
+    // keep the part of the color above the threshold
+    float brightness = max(color.r, max(color.g, color.b));
+    float contribution = max(brightness - constants.bloomThreshold, 0.0) / max(brightness, 0.0001);
+
+    outColor = vec4(color * contribution, 1.0);
+}
@@ -0,0 +1,40 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0) uniform sampler inputSampler;
+layout(binding = 1) uniform texture2D inputImage;
+
+// must match PostConstants in post.rs
+layout(push_constant) uniform PostConstants {
+    vec2 texelSize;
+    vec2 direction;
+    float exposure;
+    float gamma;
+    float bloomThreshold;
+    float bloomIntensity;
+    uint tonemapper;
+    uint flags;
+    float paperWhite;
+    float maxLuminance;
+} constants;
+
+// 9 tap gaussian in 5 samples using linear filtering between texels
+const float OFFSETS[3] = float[](0.0, 1.3846153846, 3.2307692308);
+const float WEIGHTS[3] = float[](0.2270270270, 0.3162162162, 0.0702702703);
+
+// one direction of a separable blur
+void main() {
+    vec2 step = constants.direction * constants.texelSize;
+
+    vec3 color = texture(sampler2D(inputImage, inputSampler), fragUv).rgb * WEIGHTS[0];
+    for (int i = 1; i < 3; i++) {
+        color += texture(sampler2D(inputImage, inputSampler), fragUv + step * OFFSETS[i]).rgb * WEIGHTS[i];
+        color += texture(sampler2D(inputImage, inputSampler), fragUv - step * OFFSETS[i]).rgb * WEIGHTS[i];
+    }
+
+    outColor = vec4(color, 1.0);
+}
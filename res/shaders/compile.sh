@@ -2,3 +2,17 @@
 
 glslc shader.vert -o vert.spv
 glslc shader.frag -o frag.spv
+glslc wire.frag -o wire.spv
+glslc skybox.vert -o skybox_vert.spv
+glslc skybox.frag -o skybox_frag.spv
+glslc equirect_to_cube.comp -o equirect_to_cube.spv
+glslc irradiance.comp -o irradiance.spv
+glslc prefilter.comp -o prefilter.spv
+glslc brdf_lut.comp -o brdf_lut.spv
+glslc shadow.vert -o shadow.spv
+glslc post.vert -o post_vert.spv
+glslc bloom_threshold.frag -o bloom_threshold.spv
+glslc blur.frag -o blur.spv
+glslc tonemap.frag -o tonemap.spv
+glslc fxaa.frag -o fxaa.spv
+glslc cull.comp -o cull.spv
@@ -0,0 +1,74 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0) uniform sampler inputSampler;
+layout(binding = 1) uniform texture2D inputImage;
+
+// must match PostConstants in post.rs
+layout(push_constant) uniform PostConstants {
+    vec2 texelSize;
+    vec2 direction;
+    float exposure;
+    float gamma;
+    float bloomThreshold;
+    float bloomIntensity;
+    uint tonemapper;
+    uint flags;
+    float paperWhite;
+    float maxLuminance;
+} constants;
+
+// must match the FLAG_* constants in post.rs
+const uint FLAG_LINEAR_OUTPUT = 4;
+
+const float FXAA_REDUCE_MIN = 1.0 / 128.0;
+const float FXAA_REDUCE_MUL = 1.0 / 8.0;
+const float FXAA_SPAN_MAX = 8.0;
+
+vec3 sampleInput(vec2 uv) {
+    return texture(sampler2D(inputImage, inputSampler), uv).rgb;
+}
+
+float luma(vec3 color) {
+    return dot(color, vec3(0.299, 0.587, 0.114));
+}
+
+// input is display encoded so edges are found where they're visible
+void main() {
+    vec2 texel = constants.texelSize;
+
+    vec3 colorM = sampleInput(fragUv);
+    float lumaNW = luma(sampleInput(fragUv + vec2(-1.0, -1.0) * texel));
+    float lumaNE = luma(sampleInput(fragUv + vec2(1.0, -1.0) * texel));
+    float lumaSW = luma(sampleInput(fragUv + vec2(-1.0, 1.0) * texel));
+    float lumaSE = luma(sampleInput(fragUv + vec2(1.0, 1.0) * texel));
+    float lumaM = luma(colorM);
+
+    float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+    float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+    // blur along the edge, perpendicular to the luma gradient
+    vec2 direction = vec2(-((lumaNW + lumaNE) - (lumaSW + lumaSE)), (lumaNW + lumaSW) - (lumaNE + lumaSE));
+
+    float directionReduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * 0.25 * FXAA_REDUCE_MUL, FXAA_REDUCE_MIN);
+    float inverseDirectionMin = 1.0 / (min(abs(direction.x), abs(direction.y)) + directionReduce);
+    direction = clamp(direction * inverseDirectionMin, vec2(-FXAA_SPAN_MAX), vec2(FXAA_SPAN_MAX)) * texel;
+
+    vec3 colorA = 0.5 * (sampleInput(fragUv + direction * (1.0 / 3.0 - 0.5)) + sampleInput(fragUv + direction * (2.0 / 3.0 - 0.5)));
+    vec3 colorB = colorA * 0.5 + 0.25 * (sampleInput(fragUv + direction * -0.5) + sampleInput(fragUv + direction * 0.5));
+
+    // wider sample went past the edge
+    float lumaB = luma(colorB);
+    vec3 color = (lumaB < lumaMin || lumaB > lumaMax) ? colorA : colorB;
+
+    // srgb targets encode on write
+    if ((constants.flags & FLAG_LINEAR_OUTPUT) != 0) {
+        color = pow(color, vec3(constants.gamma));
+    }
+
+    outColor = vec4(color, 1.0);
+}
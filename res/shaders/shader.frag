@@ -1,10 +1,304 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
 
+// must match ViewMode::shader_index
+const uint VIEW_MODE_SHADED = 0;
+const uint VIEW_MODE_NORMALS = 1;
+const uint VIEW_MODE_UV_CHECKER = 2;
+const uint VIEW_MODE_DEPTH = 3;
+const uint VIEW_MODE_LOD = 4;
+
+// one per level of detail, must have lod::MAX_LODS entries
+const vec3 LOD_COLORS[4] = vec3[](vec3(0.2, 0.8, 0.2), vec3(0.9, 0.9, 0.2), vec3(0.9, 0.5, 0.1), vec3(0.9, 0.1, 0.1));
+
+const float CHECKER_SCALE = 8.0;
+
+const float PI = 3.14159265359;
+
+// must match lighting::MAX_*_LIGHTS
+const uint MAX_DIRECTIONAL_LIGHTS = 4;
+const uint MAX_POINT_LIGHTS = 8;
+const uint MAX_SPOT_LIGHTS = 4;
+
+// must match shadow::CASCADE_COUNT & shadow::SHADOW_MAP_LAYERS
+const uint CASCADE_COUNT = 4;
+const uint SHADOW_MAP_LAYERS = 8;
+
+// moves the lookup off the surface along the normal, in world units
+const float SHADOW_NORMAL_OFFSET = 0.005;
+
+struct DirectionalLight {
+	vec4 direction;
+	vec4 color;
+};
+
+struct PointLight {
+	vec4 position;
+	vec4 color;
+};
+
+struct SpotLight {
+	vec4 position;
+	vec4 direction;
+	vec4 color;
+	vec4 cone;
+};
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+	mat4 view;
+	mat4 proj;
+	uint viewMode;
+} ubo;
+
+// must match lighting::LightUniformBlock
+layout(set = 0, binding = 1) uniform LightUniformBlock {
+	vec4 ambient;
+	vec4 cameraPosition;
+	uvec4 counts;
+	DirectionalLight directionalLights[MAX_DIRECTIONAL_LIGHTS];
+	PointLight pointLights[MAX_POINT_LIGHTS];
+	SpotLight spotLights[MAX_SPOT_LIGHTS];
+} lights;
+
+// must match shadow::ShadowUniformBlock
+layout(set = 0, binding = 2) uniform ShadowUniformBlock {
+	mat4 lightMatrices[SHADOW_MAP_LAYERS];
+	vec4 cascadeSplits;
+	uvec4 counts;
+} shadows;
+
+layout(set = 0, binding = 3) uniform texture2DArray shadowMap;
+layout(set = 0, binding = 4) uniform samplerShadow shadowSampler;
+
+// must match material::MaterialFactors
+layout(set = 1, binding = 0) uniform MaterialFactors {
+	vec4 baseColor;
+	vec4 emissive;
+	float metallic;
+	float roughness;
+	float normalScale;
+	float occlusionStrength;
+} material;
+
+// must match material::TextureSlot::binding
+layout(set = 1, binding = 1) uniform sampler materialSampler;
+layout(set = 1, binding = 2) uniform texture2D baseColorTexture;
+layout(set = 1, binding = 3) uniform texture2D metallicRoughnessTexture;
+layout(set = 1, binding = 4) uniform texture2D normalTexture;
+layout(set = 1, binding = 5) uniform texture2D occlusionTexture;
+layout(set = 1, binding = 6) uniform texture2D emissiveTexture;
+
+// image based lighting, must match environment::create_environment_set_layout
+layout(set = 2, binding = 0) uniform sampler environmentSampler;
+layout(set = 2, binding = 1) uniform textureCube irradianceMap;
+layout(set = 2, binding = 2) uniform textureCube prefilteredMap;
+layout(set = 2, binding = 3) uniform texture2D brdfLut;
+
+// must match environment::PREFILTERED_MIP_LEVELS - 1
+const float PREFILTERED_MAX_LOD = 4.0;
+
+// per-draw data, must match model::DrawConstants
+layout(push_constant) uniform DrawConstants {
+	vec4 tint;
+	uint materialIndex;
+	uint lod;
+} draw;
+
 layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec3 fragPosition;
+layout(location = 2) in vec2 fragUv;
+layout(location = 3) in vec3 fragNormal;
+layout(location = 4) in vec4 fragTangent;
+layout(location = 5) flat in uint fragLod;
 
 layout(location = 0) out vec4 outColor;
 
+// trowbridge-reitz ggx normal distribution
+float distributionGgx(float nDotH, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float denominator = nDotH * nDotH * (a2 - 1.0) + 1.0;
+    return a2 / (PI * denominator * denominator);
+}
+
+// smith's method with schlick-ggx for direct lighting
+float geometrySmith(float nDotV, float nDotL, float roughness) {
+    float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    float ggxV = nDotV / (nDotV * (1.0 - k) + k);
+    float ggxL = nDotL / (nDotL * (1.0 - k) + k);
+    return ggxV * ggxL;
+}
+
+vec3 fresnelSchlick(float cosTheta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cosTheta, 0.0, 1.0), 5.0);
+}
+
+// rough surfaces reflect less at grazing angles
+vec3 fresnelSchlickRoughness(float cosTheta, vec3 f0, float roughness) {
+    return f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - cosTheta, 0.0, 1.0), 5.0);
+}
+
+// cook-torrance brdf * radiance * n.l for one light, light direction points towards the light
+vec3 cookTorrance(vec3 albedo, float metallic, float roughness, vec3 normal, vec3 viewDirection, vec3 lightDirection, vec3 radiance) {
+    vec3 halfway = normalize(lightDirection + viewDirection);
+    float nDotV = max(dot(normal, viewDirection), 0.0001);
+    float nDotL = max(dot(normal, lightDirection), 0.0);
+    float nDotH = max(dot(normal, halfway), 0.0);
+
+    // dielectrics reflect ~4%, metals reflect their albedo
+    vec3 f0 = mix(vec3(0.04), albedo, metallic);
+    vec3 fresnel = fresnelSchlick(max(dot(halfway, viewDirection), 0.0), f0);
+
+    vec3 specular = distributionGgx(nDotH, roughness) * geometrySmith(nDotV, nDotL, roughness) * fresnel
+        / (4.0 * nDotV * max(nDotL, 0.0001));
+
+    // metals have no diffuse
+    vec3 diffuse = (vec3(1.0) - fresnel) * (1.0 - metallic) * albedo / PI;
+
+    return (diffuse + specular) * radiance * nDotL;
+}
+
+// diffuse from the irradiance map, specular from the prefiltered map & brdf lut (split sum)
+vec3 imageBasedLighting(vec3 albedo, float metallic, float roughness, vec3 normal, vec3 viewDirection) {
+    float nDotV = max(dot(normal, viewDirection), 0.0001);
+    vec3 f0 = mix(vec3(0.04), albedo, metallic);
+    vec3 fresnel = fresnelSchlickRoughness(nDotV, f0, roughness);
+
+    vec3 irradiance = texture(samplerCube(irradianceMap, environmentSampler), normal).rgb;
+    vec3 diffuse = (vec3(1.0) - fresnel) * (1.0 - metallic) * irradiance * albedo;
+
+    vec3 reflection = reflect(-viewDirection, normal);
+    vec3 prefiltered = textureLod(samplerCube(prefilteredMap, environmentSampler), reflection, roughness * PREFILTERED_MAX_LOD).rgb;
+    vec2 brdf = texture(sampler2D(brdfLut, environmentSampler), vec2(nDotV, roughness)).rg;
+    vec3 specular = prefiltered * (fresnel * brdf.x + brdf.y);
+
+    return diffuse + specular;
+}
+
+// 3x3 pcf, 1 fully lit, 0 fully shadowed
+float sampleShadow(uint layer, vec3 normal) {
+    vec2 texelSize = 1.0 / vec2(textureSize(sampler2DArrayShadow(shadowMap, shadowSampler), 0).xy);
+    vec4 lightPosition = shadows.lightMatrices[layer] * vec4(fragPosition + normal * SHADOW_NORMAL_OFFSET, 1.0);
+    vec3 projected = lightPosition.xyz / lightPosition.w;
+
+    // beyond the far plane or behind a spot light
+    if (projected.z > 1.0 || lightPosition.w <= 0.0) {
+        return 1.0;
+    }
+
+    vec2 uv = projected.xy * 0.5 + 0.5;
+
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            vec4 coordinate = vec4(uv + vec2(x, y) * texelSize, float(layer), projected.z);
+            lit += texture(sampler2DArrayShadow(shadowMap, shadowSampler), coordinate);
+        }
+    }
+
+    return lit / 9.0;
+}
+
+// first cascade whose far split is beyond the fragment
+uint getCascade() {
+    for (uint cascade = 0; cascade < CASCADE_COUNT - 1; cascade++) {
+        if (gl_FragCoord.z <= shadows.cascadeSplits[cascade]) {
+            return cascade;
+        }
+    }
+    return CASCADE_COUNT - 1;
+}
+
+vec3 getNormal() {
+    vec3 normal = normalize(fragNormal);
+    vec3 tangent = normalize(fragTangent.xyz - normal * dot(normal, fragTangent.xyz));
+    vec3 bitangent = cross(normal, tangent) * fragTangent.w;
+
+    vec3 tangentNormal = texture(sampler2D(normalTexture, materialSampler), fragUv).xyz * 2.0 - 1.0;
+    tangentNormal.xy *= material.normalScale;
+
+    return normalize(mat3(tangent, bitangent, normal) * tangentNormal);
+}
+
+vec4 shade() {
+    vec4 baseColor = texture(sampler2D(baseColorTexture, materialSampler), fragUv) * material.baseColor * vec4(fragColor, 1.0) * draw.tint;
+    vec4 metallicRoughness = texture(sampler2D(metallicRoughnessTexture, materialSampler), fragUv);
+    float metallic = clamp(metallicRoughness.b * material.metallic, 0.0, 1.0);
+    // very low roughness gives fireflies
+    float roughness = clamp(metallicRoughness.g * material.roughness, 0.04, 1.0);
+    float occlusion = mix(1.0, texture(sampler2D(occlusionTexture, materialSampler), fragUv).r, material.occlusionStrength);
+    vec3 emissive = texture(sampler2D(emissiveTexture, materialSampler), fragUv).rgb * material.emissive.rgb;
+
+    vec3 normal = getNormal();
+    vec3 viewDirection = normalize(lights.cameraPosition.xyz - fragPosition);
+
+    // light both sides of the surface
+    if (dot(normal, viewDirection) < 0.0) {
+        normal = -normal;
+    }
+
+    // ambient scales the environment's contribution
+    vec3 ambient = imageBasedLighting(baseColor.rgb, metallic, roughness, normal, viewDirection);
+    vec3 color = ambient * lights.ambient.rgb * lights.ambient.a * occlusion;
+
+    for (uint i = 0; i < lights.counts.x; i++) {
+        DirectionalLight light = lights.directionalLights[i];
+        vec3 radiance = light.color.rgb * light.color.a;
+
+        // only the first directional light has shadows
+        if (i == 0 && shadows.counts.x == 1) {
+            radiance *= sampleShadow(getCascade(), normal);
+        }
+
+        color += cookTorrance(baseColor.rgb, metallic, roughness, normal, viewDirection, -light.direction.xyz, radiance);
+    }
+
+    for (uint i = 0; i < lights.counts.y; i++) {
+        PointLight light = lights.pointLights[i];
+        vec3 toLight = light.position.xyz - fragPosition;
+        float distance = length(toLight);
+        // smooth falloff to zero at range
+        float attenuation = clamp(1.0 - distance / light.position.w, 0.0, 1.0);
+        vec3 radiance = light.color.rgb * light.color.a * attenuation * attenuation;
+        color += cookTorrance(baseColor.rgb, metallic, roughness, normal, viewDirection, toLight / distance, radiance);
+    }
+
+    for (uint i = 0; i < lights.counts.z; i++) {
+        SpotLight light = lights.spotLights[i];
+        vec3 toLight = light.position.xyz - fragPosition;
+        float distance = length(toLight);
+        vec3 lightDirection = toLight / distance;
+
+        // falls off between the inner & outer cone
+        float cosAngle = dot(-lightDirection, light.direction.xyz);
+        float cone = clamp((cosAngle - light.cone.y) / max(light.cone.x - light.cone.y, 0.0001), 0.0, 1.0);
+        float attenuation = clamp(1.0 - distance / light.position.w, 0.0, 1.0);
+        vec3 radiance = light.color.rgb * light.color.a * cone * attenuation * attenuation;
+
+        if (i < shadows.counts.y) {
+            radiance *= sampleShadow(CASCADE_COUNT + i, normal);
+        }
+
+        color += cookTorrance(baseColor.rgb, metallic, roughness, normal, viewDirection, lightDirection, radiance);
+    }
+
+    return vec4(color + emissive, baseColor.a);
+}
+
 void main() {
-    outColor = vec4(fragColor, 1.0);
-}
\ No newline at end of file
+    if (ubo.viewMode == VIEW_MODE_NORMALS) {
+        outColor = vec4(getNormal() * 0.5 + 0.5, 1.0);
+    } else if (ubo.viewMode == VIEW_MODE_UV_CHECKER) {
+        vec2 cell = floor(fragUv * CHECKER_SCALE);
+        float checker = mod(cell.x + cell.y, 2.0);
+        outColor = vec4(mix(vec3(0.1), vec3(0.9), checker), 1.0);
+    } else if (ubo.viewMode == VIEW_MODE_DEPTH) {
+        outColor = vec4(vec3(gl_FragCoord.z), 1.0);
+    } else if (ubo.viewMode == VIEW_MODE_LOD) {
+        // shaded by facing so the simplified silhouette is visible
+        float facing = abs(normalize(fragNormal).z) * 0.5 + 0.5;
+        outColor = vec4(LOD_COLORS[min(fragLod, 3u)] * facing, 1.0);
+    } else {
+        outColor = shade();
+    }
+}
@@ -2,17 +2,59 @@
 #extension GL_ARB_separate_shader_objects : enable
 
 layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec3 fragPosition;
+layout(location = 2) out vec2 fragUv;
+layout(location = 3) out vec3 fragNormal;
+layout(location = 4) out vec4 fragTangent;
+layout(location = 5) flat out uint fragLod;
 
 layout(binding = 0) uniform UniformBufferObject {
-	mat4 model;
 	mat4 view;
 	mat4 proj;
+	uint viewMode;
 } ubo;
 
+// per object data, must match model::ObjectUniformBlock
+layout(binding = 5) uniform ObjectUniformBlock {
+	mat4 model;
+} object;
+
+// per-draw data, must match model::DrawConstants
+layout(push_constant) uniform DrawConstants {
+	vec4 tint;
+	uint materialIndex;
+	uint lod;
+} draw;
+
+// must match model::LOD_FROM_INSTANCE, the culling pass writes the level to each instance
+const uint LOD_FROM_INSTANCE = 0xFFFFFFFFu;
+
 layout(location = 0) in vec3 inPosition;
 layout(location = 1) in vec3 inColor;
+layout(location = 2) in vec3 inNormal;
+layout(location = 3) in vec2 inUv;
+layout(location = 4) in vec4 inTangent;
+
+// per-instance, transform is split into columns
+layout(location = 5) in vec4 instanceTransform0;
+layout(location = 6) in vec4 instanceTransform1;
+layout(location = 7) in vec4 instanceTransform2;
+layout(location = 8) in vec4 instanceTransform3;
+layout(location = 9) in vec4 instanceColor;
+layout(location = 10) in uint instanceLod;
 
 void main() {
-    gl_Position = ubo.proj * ubo.view * ubo.model * vec4(inPosition, 1.0);
-    fragColor = inColor;
+    mat4 instanceTransform = mat4(instanceTransform0, instanceTransform1, instanceTransform2, instanceTransform3);
+    mat4 model = object.model * instanceTransform;
+    vec4 worldPosition = model * vec4(inPosition, 1.0);
+    gl_Position = ubo.proj * ubo.view * worldPosition;
+    // only used by the points pipeline
+    gl_PointSize = 4.0;
+    fragColor = inColor * instanceColor.rgb;
+    fragPosition = worldPosition.xyz;
+    // assumes uniform scaling, otherwise needs the inverse transpose
+    fragNormal = mat3(model) * inNormal;
+    fragTangent = vec4(mat3(model) * inTangent.xyz, inTangent.w);
+    fragUv = inUv;
+    fragLod = draw.lod == LOD_FROM_INSTANCE ? instanceLod : draw.lod;
 }
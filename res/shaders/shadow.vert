@@ -0,0 +1,36 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// must match shadow::SHADOW_MAP_LAYERS
+const uint SHADOW_MAP_LAYERS = 8;
+
+// must match shadow::ShadowUniformBlock
+layout(set = 0, binding = 2) uniform ShadowUniformBlock {
+	mat4 lightMatrices[SHADOW_MAP_LAYERS];
+	vec4 cascadeSplits;
+	uvec4 counts;
+} shadows;
+
+// must match model::ObjectUniformBlock
+layout(set = 0, binding = 5) uniform ObjectUniformBlock {
+	mat4 model;
+} object;
+
+// must match shadow::ShadowConstants
+layout(push_constant) uniform ShadowConstants {
+	uint layer;
+} draw;
+
+layout(location = 0) in vec3 inPosition;
+
+// per-instance, transform is split into columns
+layout(location = 5) in vec4 instanceTransform0;
+layout(location = 6) in vec4 instanceTransform1;
+layout(location = 7) in vec4 instanceTransform2;
+layout(location = 8) in vec4 instanceTransform3;
+
+// depth only, rendered from a light's point of view
+void main() {
+    mat4 instanceTransform = mat4(instanceTransform0, instanceTransform1, instanceTransform2, instanceTransform3);
+    gl_Position = shadows.lightMatrices[draw.layer] * object.model * instanceTransform * vec4(inPosition, 1.0);
+}
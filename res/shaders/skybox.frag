@@ -0,0 +1,14 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// must match environment::ENVIRONMENT_SET bindings
+layout(set = 2, binding = 0) uniform sampler environmentSampler;
+layout(set = 2, binding = 4) uniform textureCube environmentTexture;
+
+layout(location = 0) in vec3 fragDirection;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    outColor = vec4(texture(samplerCube(environmentTexture, environmentSampler), fragDirection).rgb, 1.0);
+}
@@ -0,0 +1,20 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) out vec3 fragDirection;
+
+layout(binding = 0) uniform UniformBufferObject {
+	mat4 view;
+	mat4 proj;
+	uint viewMode;
+} ubo;
+
+// fullscreen triangle at the far plane, no vertex buffer needed
+void main() {
+    vec2 position = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+    gl_Position = vec4(position, 1.0, 1.0);
+
+    // unproject ignoring camera translation so the sky is infinitely far away
+    vec4 direction = inverse(ubo.proj * mat4(mat3(ubo.view))) * vec4(position, 1.0, 1.0);
+    fragDirection = direction.xyz / direction.w;
+}
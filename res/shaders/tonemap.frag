@@ -0,0 +1,138 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0) uniform sampler inputSampler;
+layout(binding = 1) uniform texture2D inputImage;
+layout(binding = 2) uniform texture2D bloomImage;
+// size * size wide & size high, one slice of blue per size wide square
+layout(binding = 3) uniform texture2D colorLut;
+
+// must match PostConstants in post.rs
+layout(push_constant) uniform PostConstants {
+    vec2 texelSize;
+    vec2 direction;
+    float exposure;
+    float gamma;
+    float bloomThreshold;
+    float bloomIntensity;
+    uint tonemapper;
+    uint flags;
+    float paperWhite;
+    float maxLuminance;
+} constants;
+
+// must match Tonemapper::shader_index
+const uint TONEMAPPER_ACES = 0;
+const uint TONEMAPPER_REINHARD = 1;
+
+// must match the FLAG_* constants in post.rs
+const uint FLAG_BLOOM = 1;
+const uint FLAG_COLOR_GRADING = 2;
+const uint FLAG_LINEAR_OUTPUT = 4;
+const uint FLAG_HDR10 = 8;
+const uint FLAG_SCRGB = 16;
+
+// scRGB 1.0 is 80 nits
+const float SCRGB_WHITE_NITS = 80.0;
+
+// linear rec 709 to linear rec 2020, column major
+const mat3 REC709_TO_REC2020 = mat3(
+    0.6274, 0.0691, 0.0164,
+    0.3293, 0.9195, 0.0880,
+    0.0433, 0.0114, 0.8956
+);
+
+// Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 color) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), 0.0, 1.0);
+}
+
+vec3 reinhard(vec3 color) {
+    return color / (1.0 + color);
+}
+
+vec3 tonemap(vec3 color) {
+    if (constants.tonemapper == TONEMAPPER_ACES) {
+        return aces(color);
+    } else if (constants.tonemapper == TONEMAPPER_REINHARD) {
+        return reinhard(color);
+    }
+    return clamp(color, 0.0, 1.0);
+}
+
+// st 2084 perceptual quantizer, absolute nits to 0-1
+vec3 encodePq(vec3 nits) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+
+    vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(m1));
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+// display encoded color through the lut, blending the two nearest blue slices
+vec3 gradeColor(vec3 color) {
+    vec2 lutSize = vec2(textureSize(sampler2D(colorLut, inputSampler), 0));
+    float size = lutSize.y;
+
+    float blue = color.b * (size - 1.0);
+    float slice0 = floor(blue);
+    float slice1 = min(slice0 + 1.0, size - 1.0);
+
+    // texel centres so neighbouring slices don't bleed into each other
+    vec2 uv = (color.rg * (size - 1.0) + 0.5) / lutSize;
+    vec3 color0 = texture(sampler2D(colorLut, inputSampler), uv + vec2(slice0 * size / lutSize.x, 0.0)).rgb;
+    vec3 color1 = texture(sampler2D(colorLut, inputSampler), uv + vec2(slice1 * size / lutSize.x, 0.0)).rgb;
+
+    return mix(color0, color1, blue - slice0);
+}
+
+void main() {
+    vec3 color = texture(sampler2D(inputImage, inputSampler), fragUv).rgb;
+
+    if ((constants.flags & FLAG_BLOOM) != 0) {
+        color += texture(sampler2D(bloomImage, inputSampler), fragUv).rgb * constants.bloomIntensity;
+    }
+
+    color *= constants.exposure;
+
+    // hdr displays only need highlights compressed down to their peak, paper white stays at 1.0
+    if ((constants.flags & (FLAG_HDR10 | FLAG_SCRGB)) != 0) {
+        float peak = max(constants.maxLuminance / constants.paperWhite, 1.0);
+        color = tonemap(color / peak) * peak * constants.paperWhite;
+
+        if ((constants.flags & FLAG_HDR10) != 0) {
+            color = encodePq(max(REC709_TO_REC2020 * color, 0.0));
+        } else {
+            color /= SCRGB_WHITE_NITS;
+        }
+
+        outColor = vec4(color, 1.0);
+        return;
+    }
+
+    color = tonemap(color);
+    color = pow(color, vec3(1.0 / constants.gamma));
+
+    if ((constants.flags & FLAG_COLOR_GRADING) != 0) {
+        color = gradeColor(color);
+    }
+
+    // srgb targets encode on write
+    if ((constants.flags & FLAG_LINEAR_OUTPUT) != 0) {
+        color = pow(color, vec3(constants.gamma));
+    }
+
+    outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,9 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) out vec4 outColor;
+
+// flat color used by the wireframe overlay & points
+void main() {
+    outColor = vec4(0.0, 0.0, 0.0, 1.0);
+}
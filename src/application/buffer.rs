@@ -1,20 +1,26 @@
-use crate::application::model::{Index, UniformBufferObject, Vertex, INDICIES, VERTICES};
+use crate::application::handle::{self, Owned};
+use crate::application::model::{Index, InstanceData, Vertex};
+use crate::application::upload::Uploader;
 use core::ffi::c_void;
 use erupt::{vk, DeviceLoader, InstanceLoader};
 
-use std::time::Instant;
+use std::mem::{size_of, size_of_val};
+use std::rc::Rc;
 
-use std::mem::size_of;
+// a buffer & its memory, the buffer is dropped first
+pub type OwnedBuffer = (Owned<vk::Buffer>, Owned<vk::DeviceMemory>);
 
+#[allow(clippy::too_many_arguments)]
 pub fn create_buffer(
     instance: &InstanceLoader,
     physical_device: &vk::PhysicalDevice,
-    device: &DeviceLoader,
+    device: &Rc<handle::Device>,
     buffer_size: u64,
     usage: vk::BufferUsageFlags,
     sharing_mode: vk::SharingMode,
     properties: vk::MemoryPropertyFlags,
-) -> (vk::Buffer, vk::DeviceMemory) {
+    name: &str,
+) -> OwnedBuffer {
     let buffer_info = vk::BufferCreateInfoBuilder::new()
         .size(buffer_size)
         .usage(usage)
@@ -23,9 +29,10 @@ pub fn create_buffer(
     // create buffer
     let buffer = unsafe { device.create_buffer(&buffer_info, None, None) }
         .expect("Failed to create buffer!");
+    let buffer = Owned::new(device, buffer, name);
 
     // get buffer memory requirements
-    let memory_requirements = unsafe { device.get_buffer_memory_requirements(buffer, None) };
+    let memory_requirements = unsafe { device.get_buffer_memory_requirements(*buffer, None) };
 
     // start to allocate buffer
     let allocation_info = vk::MemoryAllocateInfoBuilder::new()
@@ -40,9 +47,10 @@ pub fn create_buffer(
     // allocate buffer memory
     let buffer_memory = unsafe { device.allocate_memory(&allocation_info, None, None) }
         .expect("Failed to allocate staging buffer memory!");
+    let buffer_memory = Owned::new(device, buffer_memory, name);
 
     // bind such memory with buffer
-    unsafe { device.bind_buffer_memory(buffer, buffer_memory, 0) }
+    unsafe { device.bind_buffer_memory(*buffer, *buffer_memory, 0) }
         .expect("Failed to bind vertex staging memory");
 
     (buffer, buffer_memory)
@@ -50,12 +58,12 @@ pub fn create_buffer(
 
 pub fn create_vertex_buffer(
     instance: &InstanceLoader,
-    device: &DeviceLoader,
+    device: &Rc<handle::Device>,
     physical_device: &vk::PhysicalDevice,
-    command_pool: &vk::CommandPool,
-    queue: &vk::Queue,
-) -> (vk::Buffer, vk::DeviceMemory) {
-    let buffer_size = (size_of::<Vertex>() * VERTICES.len()) as u64;
+    uploader: &mut Uploader,
+    vertices: &[Vertex],
+) -> OwnedBuffer {
+    let buffer_size = size_of_val(vertices) as u64;
 
     // create temp staging buffer
     let (staging_buffer, staging_buffer_memory) = create_buffer(
@@ -68,17 +76,18 @@ pub fn create_vertex_buffer(
         vk::BufferUsageFlags::TRANSFER_SRC,
         vk::SharingMode::EXCLUSIVE,
         vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        "vertex staging buffer",
     );
 
     // copy vertex data to staging buffer
-    copy_to_staging_buffer::<[Vertex; 4]>(
+    copy_to_staging_buffer(
         instance,
         device,
         physical_device,
         &staging_buffer,
         &staging_buffer_memory,
-        &VERTICES,
-        VERTICES.len() * size_of::<Vertex>(),
+        vertices,
+        buffer_size as usize,
     );
 
     // create real vertex buffer
@@ -90,34 +99,30 @@ pub fn create_vertex_buffer(
         vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::VERTEX_BUFFER,
         vk::SharingMode::EXCLUSIVE,
         vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        "vertex buffer",
     );
 
     // copy from host visible staging buffer
     // to device local vertex buffer
     copy_buffer(
         device,
-        command_pool,
-        queue,
-        &staging_buffer,
+        uploader,
+        (staging_buffer, staging_buffer_memory),
         &vertex_buffer,
         buffer_size,
     );
 
-    // clean up staging buffer & memory
-    unsafe { device.destroy_buffer(Some(staging_buffer), None) };
-    unsafe { device.free_memory(Some(staging_buffer_memory), None) };
-
     (vertex_buffer, vertex_buffer_memory)
 }
 
 pub fn create_index_buffer(
     instance: &InstanceLoader,
-    device: &DeviceLoader,
+    device: &Rc<handle::Device>,
     physical_device: &vk::PhysicalDevice,
-    command_pool: &vk::CommandPool,
-    queue: &vk::Queue,
-) -> (vk::Buffer, vk::DeviceMemory) {
-    let buffer_size = (size_of::<Index>() * INDICIES.len()) as u64;
+    uploader: &mut Uploader,
+    indices: &[Index],
+) -> OwnedBuffer {
+    let buffer_size = size_of_val(indices) as u64;
 
     // create temp staging buffer
     let (staging_buffer, staging_buffer_memory) = create_buffer(
@@ -130,17 +135,18 @@ pub fn create_index_buffer(
         vk::BufferUsageFlags::TRANSFER_SRC,
         vk::SharingMode::EXCLUSIVE,
         vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        "index staging buffer",
     );
 
     // copy index data to staging buffer
-    copy_to_staging_buffer::<[Index; 6]>(
+    copy_to_staging_buffer(
         instance,
         device,
         physical_device,
         &staging_buffer,
         &staging_buffer_memory,
-        &INDICIES,
-        INDICIES.len() * size_of::<Index>(),
+        indices,
+        buffer_size as usize,
     );
 
     // create real index buffer
@@ -152,59 +158,87 @@ pub fn create_index_buffer(
         vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::INDEX_BUFFER,
         vk::SharingMode::EXCLUSIVE,
         vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        "index buffer",
     );
 
     // copy from host visible staging buffer
     // to device local index buffer
     copy_buffer(
-        &device,
-        &command_pool,
-        &queue,
-        &staging_buffer,
+        device,
+        uploader,
+        (staging_buffer, staging_buffer_memory),
         &index_buffer,
         buffer_size,
     );
 
-    // clean up staging buffer & memory
-    unsafe { device.destroy_buffer(Some(staging_buffer), None) };
-    unsafe { device.free_memory(Some(staging_buffer_memory), None) };
-
     (index_buffer, index_buffer_memory)
 }
 
-pub fn create_uniform_buffer(
-    instance: &InstanceLoader,
-    physical_device: &vk::PhysicalDevice,
+pub fn update_uniform_buffer<T>(
     device: &DeviceLoader,
-    swapchain_length: usize,
-) -> (Vec<vk::Buffer>, Vec<vk::DeviceMemory>) {
-    let buffer_size = size_of::<UniformBufferObject>() as u64;
+    ubo: &T,
+    uniform_buffer_memory: &vk::DeviceMemory,
+) {
+    // copy data to buffer
+    unsafe {
+        let mut data: *mut c_void = core::ptr::null_mut();
+
+        // map physical_device memory to *data so we can copy onto it
+        device
+            .map_memory(
+                *uniform_buffer_memory,
+                0,
+                size_of::<T>() as u64,
+                None,
+                &mut data,
+            )
+            .expect("Failed to map memory for uniform buffer!");
+
+        // copy over data to buffer
+        core::ptr::copy(ubo, data as *mut T, 1);
 
-    // create uniform buffer & memory for each image in swapchain
-    (0..swapchain_length)
-        .map(|_| {
+        // unmap physical_device memory as we have copied the needed data over
+        device.unmap_memory(*uniform_buffer_memory);
+    };
+}
+
+pub fn create_instance_buffers(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    device: &Rc<handle::Device>,
+    capacity: usize,
+    frames_in_flight: usize,
+) -> Vec<OwnedBuffer> {
+    let buffer_size = (size_of::<InstanceData>() * capacity) as u64;
+
+    // host visible & one per frame in flight as instance data is rewritten every frame
+    (0..frames_in_flight)
+        .map(|index| {
             create_buffer(
                 instance,
                 physical_device,
                 device,
                 buffer_size,
-                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                // also read by the gpu culling pass
+                vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER,
                 vk::SharingMode::EXCLUSIVE,
                 vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                &format!("instance buffer {}", index),
             )
         })
-        .unzip()
+        .collect()
 }
 
-pub fn update_uniform_buffer(
+pub fn update_instance_buffer(
     device: &DeviceLoader,
-    ubo: &mut UniformBufferObject,
-    start: &Instant,
-    uniform_buffer_memory: &vk::DeviceMemory,
+    instances: &[InstanceData],
+    instance_buffer_memory: &vk::DeviceMemory,
 ) {
-    let duration = Instant::now().duration_since(*start).as_secs_f32();
+    if instances.is_empty() {
+        return;
+    }
 
-    ubo.model = ultraviolet::mat::Mat4::from_rotation_z(duration);
+    let buffer_size = size_of_val(instances);
 
     // copy data to buffer
     unsafe {
@@ -213,40 +247,97 @@ pub fn update_uniform_buffer(
         // map physical_device memory to *data so we can copy onto it
         device
             .map_memory(
-                *uniform_buffer_memory,
+                *instance_buffer_memory,
                 0,
-                size_of::<UniformBufferObject>() as u64,
+                buffer_size as u64,
                 None,
                 &mut data,
             )
-            .expect("Failed to map memory for uniform buffer!");
+            .expect("Failed to map memory for instance buffer!");
 
         // copy over data to buffer
-        core::ptr::copy(ubo, data as *mut UniformBufferObject, 1);
+        core::ptr::copy_nonoverlapping(
+            instances.as_ptr(),
+            data as *mut InstanceData,
+            instances.len(),
+        );
 
         // unmap physical_device memory as we have copied the needed data over
-        device.unmap_memory(*uniform_buffer_memory);
+        device.unmap_memory(*instance_buffer_memory);
     };
 }
 
-fn find_physical_device_memory(
+// host visible storage buffers rewritten every frame
+pub fn update_storage_buffer<T>(device: &DeviceLoader, items: &[T], memory: &vk::DeviceMemory) {
+    if items.is_empty() {
+        return;
+    }
+
+    unsafe {
+        let mut data: *mut c_void = core::ptr::null_mut();
+
+        device
+            .map_memory(*memory, 0, size_of_val(items) as u64, None, &mut data)
+            .expect("Failed to map memory for storage buffer!");
+
+        core::ptr::copy_nonoverlapping(items.as_ptr(), data as *mut T, items.len());
+
+        device.unmap_memory(*memory);
+    };
+}
+
+// results written by the gpu, the caller makes sure the writes are finished & visible
+pub fn read_storage_buffer<T: Copy>(
+    device: &DeviceLoader,
+    memory: &vk::DeviceMemory,
+    count: usize,
+) -> Vec<T> {
+    if count == 0 {
+        return Vec::new();
+    }
+
+    unsafe {
+        let mut data: *mut c_void = core::ptr::null_mut();
+
+        device
+            .map_memory(*memory, 0, (size_of::<T>() * count) as u64, None, &mut data)
+            .expect("Failed to map memory for storage buffer!");
+
+        let items = core::slice::from_raw_parts(data as *const T, count).to_vec();
+
+        device.unmap_memory(*memory);
+
+        items
+    }
+}
+
+pub fn find_physical_device_memory(
     instance: &InstanceLoader,
     physical_device: &vk::PhysicalDevice,
     type_filter: u32,
     properties: vk::MemoryPropertyFlags,
 ) -> u32 {
+    find_memory_type(instance, physical_device, type_filter, properties)
+        .expect("Failed to find valid memory for vertex buffer allocation!")
+}
+
+// None when no memory type has all of the properties
+pub fn find_memory_type(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    type_filter: u32,
+    properties: vk::MemoryPropertyFlags,
+) -> Option<u32> {
     // get physical device memory properties
     let memory_properties =
         unsafe { instance.get_physical_device_memory_properties(*physical_device, None) };
 
     // get memory that aligns with type_filter & properties
-    (0..memory_properties.memory_type_count)
-        .find(|&i| {
-            ((type_filter & (1 << i)) != 0)
-                && ((memory_properties.memory_types[i as usize].property_flags & properties)
-                    == properties)
-        })
-        .expect("Failed to find valid memory for vertex buffer allocation!")
+    (0..memory_properties.memory_type_count).find(|&i| {
+        ((type_filter & (1 << i)) != 0)
+            && ((memory_properties.memory_types[i as usize].property_flags & properties)
+                == properties)
+    })
 }
 
 pub fn copy_to_staging_buffer<T>(
@@ -255,7 +346,7 @@ pub fn copy_to_staging_buffer<T>(
     _physical_device: &vk::PhysicalDevice,
     _buffer: &vk::Buffer,
     buffer_memory: &vk::DeviceMemory,
-    buffer_data: &T,
+    buffer_data: &[T],
     buffer_size: usize,
 ) {
     // copy data to buffer
@@ -268,53 +359,26 @@ pub fn copy_to_staging_buffer<T>(
             .expect("Failed to map memory for staging buffer!");
 
         // copy over data to buffer
-        core::ptr::copy(buffer_data, data as *mut T, 1);
+        core::ptr::copy_nonoverlapping(buffer_data.as_ptr(), data as *mut T, buffer_data.len());
 
         // unmap physical_device memory as we have copied the needed data over
         device.unmap_memory(*buffer_memory);
     };
 }
 
+// the staging buffer is dropped once the copy has finished
 fn copy_buffer(
-    device: &DeviceLoader,
-    command_pool: &vk::CommandPool,
-    queue: &vk::Queue,
-    src_buffer: &vk::Buffer,
+    device: &Rc<handle::Device>,
+    uploader: &mut Uploader,
+    staging_buffer: OwnedBuffer,
     dst_buffer: &vk::Buffer,
     buffer_size: u64,
 ) {
-    // allocate temp command buffer for copy operation
-    let command_buffer_allocate_info = vk::CommandBufferAllocateInfoBuilder::new()
-        .level(vk::CommandBufferLevel::PRIMARY)
-        .command_pool(*command_pool)
-        .command_buffer_count(1);
-
-    let command_buffer = unsafe { device.allocate_command_buffers(&command_buffer_allocate_info) }
-        .expect("Failed to allocate copy command buffer!");
-
-    let begin_info = vk::CommandBufferBeginInfoBuilder::new()
-        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
-
-    // begin copy operation
-    unsafe { device.begin_command_buffer(command_buffer[0], &begin_info) }
-        .expect("Failed to begin recording copy command buffer!");
+    let (command_pool, command_buffer) = uploader.begin(device, "buffer copy");
 
     let copy_region = vec![vk::BufferCopyBuilder::new().size(buffer_size)];
 
-    unsafe { device.cmd_copy_buffer(command_buffer[0], *src_buffer, *dst_buffer, &copy_region) };
-
-    unsafe { device.end_command_buffer(command_buffer[0]) }
-        .expect("Failed to end recording copy command buffer!");
-
-    let submit_info = vk::SubmitInfoBuilder::new().command_buffers(&command_buffer);
-
-    // submit command buffer to queue
-    unsafe { device.queue_submit(*queue, &[submit_info], None) }
-        .expect("Failed to submit queue with copy command buffer!");
-
-    // wait idle then free command buffer
-    unsafe { device.queue_wait_idle(*queue) }
-        .expect("Queue wait idle failed in copy buffer function!");
+    unsafe { device.cmd_copy_buffer(command_buffer, *staging_buffer.0, *dst_buffer, &copy_region) };
 
-    unsafe { device.free_command_buffers(*command_pool, &command_buffer) };
+    uploader.end(device, command_pool, command_buffer, staging_buffer);
 }
@@ -0,0 +1,237 @@
+use erupt::{vk, DeviceLoader};
+
+use core::ffi::c_void;
+
+use std::convert::TryInto;
+use std::fs;
+use std::path::PathBuf;
+
+// directory (relative to working directory) pipeline cache blobs are stored in
+pub const PIPELINE_CACHE_DIR: &str = "cache";
+
+// size of VkPipelineCacheHeaderVersionOne
+const PIPELINE_CACHE_HEADER_SIZE: usize = 32;
+
+// VK_PIPELINE_CACHE_HEADER_VERSION_ONE
+const PIPELINE_CACHE_HEADER_VERSION_ONE: u32 = 1;
+
+pub fn create_pipeline_cache(
+    device: &DeviceLoader,
+    physical_device_properties: &vk::PhysicalDeviceProperties,
+) -> vk::PipelineCache {
+    // use previous cache if there is one that matches this device & driver
+    let initial_data = fs::read(get_pipeline_cache_path(physical_device_properties))
+        .ok()
+        .filter(|data| validate_pipeline_cache_header(data, physical_device_properties))
+        .unwrap_or_default();
+
+    if !initial_data.is_empty() {
+        println!("Loaded pipeline cache ({} bytes).", initial_data.len());
+    }
+
+    let pipeline_cache_info = vk::PipelineCacheCreateInfoBuilder::new()
+        .initial_data_size(initial_data.len())
+        .initial_data(initial_data.as_ptr() as *const c_void);
+
+    unsafe { device.create_pipeline_cache(&pipeline_cache_info, None, None) }
+        .expect("Failed to create pipeline cache!")
+}
+
+pub fn save_pipeline_cache(
+    device: &DeviceLoader,
+    pipeline_cache: vk::PipelineCache,
+    physical_device_properties: &vk::PhysicalDeviceProperties,
+) {
+    // failing to save the cache isn't fatal, it just gets rebuilt next run, first call gets size,
+    // second call gets the data
+    let mut data_size = 0;
+    let result = unsafe {
+        device.get_pipeline_cache_data(pipeline_cache, &mut data_size, core::ptr::null_mut())
+    };
+    if let Err(e) = result.result() {
+        log::warn!("Failed to get pipeline cache size: {}", e);
+        return;
+    }
+
+    let mut data = vec![0u8; data_size];
+    let result = unsafe {
+        device.get_pipeline_cache_data(
+            pipeline_cache,
+            &mut data_size,
+            data.as_mut_ptr() as *mut c_void,
+        )
+    };
+    if let Err(e) = result.result() {
+        log::warn!("Failed to get pipeline cache data: {}", e);
+        return;
+    }
+    data.truncate(data_size);
+
+    let path = get_pipeline_cache_path(physical_device_properties);
+    if let Err(e) = fs::create_dir_all(PIPELINE_CACHE_DIR).and_then(|_| fs::write(&path, &data)) {
+        log::warn!("Failed to save pipeline cache to {:?}: {}", path, e);
+    }
+}
+
+// cache blobs are only valid for the same device, so key the file name by it
+fn get_pipeline_cache_path(physical_device_properties: &vk::PhysicalDeviceProperties) -> PathBuf {
+    let uuid: String = physical_device_properties
+        .pipeline_cache_uuid
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    PathBuf::from(PIPELINE_CACHE_DIR).join(format!(
+        "pipeline_{:04x}_{:08x}_{}.bin",
+        physical_device_properties.vendor_id, physical_device_properties.driver_version, uuid
+    ))
+}
+
+// drivers should reject mismatched blobs but not all do, so check header ourselves
+fn validate_pipeline_cache_header(
+    data: &[u8],
+    physical_device_properties: &vk::PhysicalDeviceProperties,
+) -> bool {
+    if data.len() < PIPELINE_CACHE_HEADER_SIZE {
+        return false;
+    }
+
+    // header fields are all native endian u32s bar the uuid
+    let read_u32 = |offset: usize| {
+        u32::from_ne_bytes(
+            data[offset..offset + 4]
+                .try_into()
+                .expect("Failed to read u32 from pipeline cache header!"),
+        )
+    };
+
+    read_u32(0) as usize >= PIPELINE_CACHE_HEADER_SIZE
+        && read_u32(4) == PIPELINE_CACHE_HEADER_VERSION_ONE
+        && read_u32(8) == physical_device_properties.vendor_id
+        && read_u32(12) == physical_device_properties.device_id
+        && data[16..32] == physical_device_properties.pipeline_cache_uuid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties() -> vk::PhysicalDeviceProperties {
+        vk::PhysicalDeviceProperties {
+            vendor_id: 0x10de,
+            device_id: 0x2204,
+            driver_version: 0x1d8c_0000,
+            pipeline_cache_uuid: [7; 16],
+            ..Default::default()
+        }
+    }
+
+    // a header as the driver that made it would have written it, followed by some payload
+    fn header(properties: &vk::PhysicalDeviceProperties) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(PIPELINE_CACHE_HEADER_SIZE as u32).to_ne_bytes());
+        data.extend_from_slice(&PIPELINE_CACHE_HEADER_VERSION_ONE.to_ne_bytes());
+        data.extend_from_slice(&properties.vendor_id.to_ne_bytes());
+        data.extend_from_slice(&properties.device_id.to_ne_bytes());
+        data.extend_from_slice(&properties.pipeline_cache_uuid);
+        data.extend_from_slice(&[0xab; 64]);
+        data
+    }
+
+    #[test]
+    fn matching_header_is_valid() {
+        let properties = properties();
+        assert!(validate_pipeline_cache_header(
+            &header(&properties),
+            &properties
+        ));
+    }
+
+    #[test]
+    fn header_alone_is_valid() {
+        let properties = properties();
+        let data = header(&properties);
+        assert!(validate_pipeline_cache_header(
+            &data[..PIPELINE_CACHE_HEADER_SIZE],
+            &properties
+        ));
+    }
+
+    #[test]
+    fn truncated_header_is_rejected() {
+        let properties = properties();
+        let data = header(&properties);
+
+        for len in 0..PIPELINE_CACHE_HEADER_SIZE {
+            assert!(!validate_pipeline_cache_header(&data[..len], &properties));
+        }
+    }
+
+    #[test]
+    fn short_header_length_is_rejected() {
+        let properties = properties();
+        let mut data = header(&properties);
+        data[0..4].copy_from_slice(&(PIPELINE_CACHE_HEADER_SIZE as u32 - 1).to_ne_bytes());
+        assert!(!validate_pipeline_cache_header(&data, &properties));
+    }
+
+    #[test]
+    fn unknown_header_version_is_rejected() {
+        let properties = properties();
+        let mut data = header(&properties);
+        data[4..8].copy_from_slice(&2u32.to_ne_bytes());
+        assert!(!validate_pipeline_cache_header(&data, &properties));
+    }
+
+    #[test]
+    fn foreign_vendor_is_rejected() {
+        let properties = properties();
+        let foreign = vk::PhysicalDeviceProperties {
+            vendor_id: 0x1002,
+            ..properties
+        };
+        assert!(!validate_pipeline_cache_header(
+            &header(&foreign),
+            &properties
+        ));
+    }
+
+    #[test]
+    fn foreign_device_is_rejected() {
+        let properties = properties();
+        let foreign = vk::PhysicalDeviceProperties {
+            device_id: 0x2206,
+            ..properties
+        };
+        assert!(!validate_pipeline_cache_header(
+            &header(&foreign),
+            &properties
+        ));
+    }
+
+    #[test]
+    fn foreign_uuid_is_rejected() {
+        let properties = properties();
+        let mut foreign = properties;
+        foreign.pipeline_cache_uuid[15] ^= 1;
+        assert!(!validate_pipeline_cache_header(
+            &header(&foreign),
+            &properties
+        ));
+    }
+
+    #[test]
+    fn path_is_keyed_by_device() {
+        let properties = properties();
+        let path = get_pipeline_cache_path(&properties);
+        assert_eq!(
+            path,
+            PathBuf::from(PIPELINE_CACHE_DIR)
+                .join("pipeline_10de_1d8c0000_07070707070707070707070707070707.bin")
+        );
+
+        let mut foreign = properties;
+        foreign.pipeline_cache_uuid[0] = 8;
+        assert_ne!(get_pipeline_cache_path(&foreign), path);
+    }
+}
@@ -0,0 +1,584 @@
+use crate::application::post::Tonemapper;
+use crate::application::presentation::{OutputMode, PresentMode};
+use crate::application::validation::ValidationFeature;
+use crate::application::Opt;
+
+use serde::{de, Deserialize, Deserializer};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// read from the working directory when --config isn't given, defaults are used if it's missing
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+pub const MAX_FRAMES_IN_FLIGHT: usize = 4;
+
+// every renderer setting, missing sections & fields take their defaults
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub window: WindowConfig,
+    pub device: DeviceConfig,
+    pub render: RenderConfig,
+    pub post: PostConfig,
+    pub assets: AssetConfig,
+    pub debug: DebugConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub title: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig {
+            title: "WIP".to_string(),
+            width: 1024,
+            height: 768,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceConfig {
+    // case insensitive part of the device name, preferred over discrete/integrated
+    pub name: Option<String>,
+    // fifo, mailbox or immediate, falls back to fifo if unsupported
+    #[serde(deserialize_with = "from_str")]
+    pub present_mode: PresentMode,
+    // cull on the gpu & draw with one indirect call per material
+    pub gpu_culling: bool,
+    // pace frames with a timeline semaphore when the device supports them (Vulkan 1.2)
+    pub timeline_semaphores: bool,
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        DeviceConfig {
+            name: None,
+            present_mode: PresentMode::Immediate,
+            gpu_culling: false,
+            timeline_semaphores: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderConfig {
+    // more lets the cpu get further ahead of the gpu at the cost of latency
+    pub frames_in_flight: usize,
+    // samples per pixel of the lit pass, lowered to what the device supports
+    pub msaa: u32,
+    // linear hdr color behind the sky
+    pub clear_color: [f32; 4],
+    // instancing demo, draws a grid of this many quads
+    pub instances: Option<usize>,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        RenderConfig {
+            frames_in_flight: 2,
+            msaa: 1,
+            // greenish cause black is boring
+            clear_color: [0.1961, 0.6588, 0.3216, 1.0],
+            instances: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PostConfig {
+    // aces, reinhard or clamp
+    #[serde(deserialize_with = "from_str")]
+    pub tonemapper: Tonemapper,
+    pub exposure: f32,
+    pub gamma: f32,
+    pub bloom: bool,
+    pub fxaa: bool,
+    // sdr, hdr10 or scrgb, hdr falls back to sdr if the display doesn't support it
+    #[serde(deserialize_with = "from_str")]
+    pub output: OutputMode,
+    // hdr output brightness of scene white in nits
+    pub paper_white: f32,
+    // hdr output peak brightness in nits
+    pub max_luminance: f32,
+}
+
+impl Default for PostConfig {
+    fn default() -> Self {
+        PostConfig {
+            tonemapper: Tonemapper::Aces,
+            exposure: 1.0,
+            gamma: 2.2,
+            bloom: true,
+            fxaa: true,
+            output: OutputMode::Sdr,
+            paper_white: 200.0,
+            max_luminance: 1000.0,
+        }
+    }
+}
+
+// relative paths in the config file are relative to the file
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AssetConfig {
+    // directory with material textures (base_color.png, normal.png etc.)
+    pub material: Option<PathBuf>,
+    // equirectangular .hdr environment map, a procedural sky is used otherwise
+    pub environment: Option<PathBuf>,
+    // color grading lut png, size * size wide & size high
+    pub color_lut: Option<PathBuf>,
+}
+
+impl AssetConfig {
+    fn resolve(&mut self, directory: &Path) {
+        for path in self
+            .material
+            .iter_mut()
+            .chain(self.environment.iter_mut())
+            .chain(self.color_lut.iter_mut())
+        {
+            *path = directory.join(&path);
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DebugConfig {
+    pub validation: bool,
+    // panic on the first validation error, implies validation
+    pub validation_fatal: bool,
+    // sync, gpu-assisted or best-practices, implies validation
+    #[serde(deserialize_with = "from_strs")]
+    pub validation_features: Vec<ValidationFeature>,
+    // validation message id names or numbers to ignore
+    pub suppress_validation: Vec<String>,
+    // print fps & culling counters once a second
+    pub stats: bool,
+}
+
+impl DebugConfig {
+    pub fn is_validation_enabled(&self) -> bool {
+        self.validation || self.validation_fatal || !self.validation_features.is_empty()
+    }
+}
+
+// enums are written the same as their cmd arguments
+fn from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(de::Error::custom)
+}
+
+fn from_strs<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|name| name.parse().map_err(de::Error::custom))
+        .collect()
+}
+
+fn set<T>(value: &mut T, option: Option<T>) {
+    if let Some(option) = option {
+        *value = option;
+    }
+}
+
+// a flag & its --no-* counterpart, at most one of them is given
+fn set_flag(value: &mut bool, on: bool, off: bool) {
+    match (on, off) {
+        (true, _) => *value = true,
+        (_, true) => *value = false,
+        _ => (),
+    }
+}
+
+// lists given on the cmd line replace the file's rather than adding to them
+fn set_list<T>(value: &mut Vec<T>, list: Vec<T>) {
+    if !list.is_empty() {
+        *value = list;
+    }
+}
+
+impl Config {
+    fn from_file(path: &Path) -> Result<Config, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config {:?}: {}", path, e))?;
+        let mut config: Config = toml::from_str(&text)
+            .map_err(|e| format!("Failed to parse config {:?}: {}", path, e))?;
+
+        config
+            .assets
+            .resolve(path.parent().unwrap_or_else(|| Path::new("")));
+
+        Ok(config)
+    }
+
+    // anything given replaces the file's value
+    fn apply(&mut self, opt: Opt) {
+        set_flag(
+            &mut self.debug.validation,
+            opt.validation,
+            opt.no_validation,
+        );
+        set_flag(
+            &mut self.debug.validation_fatal,
+            opt.validation_fatal,
+            opt.no_validation_fatal,
+        );
+        set_list(&mut self.debug.validation_features, opt.validation_features);
+        set_list(&mut self.debug.suppress_validation, opt.suppress_validation);
+        set_flag(&mut self.debug.stats, opt.stats, opt.no_stats);
+        set_flag(
+            &mut self.device.gpu_culling,
+            opt.gpu_culling,
+            opt.no_gpu_culling,
+        );
+        set_flag(&mut self.post.bloom, opt.bloom, opt.no_bloom);
+        set_flag(&mut self.post.fxaa, opt.fxaa, opt.no_fxaa);
+        set_flag(
+            &mut self.device.timeline_semaphores,
+            opt.timeline_semaphores,
+            opt.no_timeline_semaphores,
+        );
+
+        set(&mut self.device.name, opt.device.map(Some));
+        set(&mut self.device.present_mode, opt.present_mode);
+        set(&mut self.render.frames_in_flight, opt.frames_in_flight);
+        set(&mut self.render.msaa, opt.msaa);
+        set(&mut self.render.instances, opt.instances.map(Some));
+        set(&mut self.post.tonemapper, opt.tonemapper);
+        set(&mut self.post.exposure, opt.exposure);
+        set(&mut self.post.gamma, opt.gamma);
+        set(&mut self.post.output, opt.output);
+        set(&mut self.post.paper_white, opt.paper_white);
+        set(&mut self.post.max_luminance, opt.max_luminance);
+        set(&mut self.assets.material, opt.material.map(Some));
+        set(&mut self.assets.environment, opt.environment.map(Some));
+        set(&mut self.assets.color_lut, opt.color_lut.map(Some));
+    }
+
+    // every invalid value is reported at once
+    fn validate(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+
+        if self.window.width == 0 || self.window.height == 0 {
+            errors.push(format!(
+                "window.width & window.height must be above 0, got {}x{}",
+                self.window.width, self.window.height
+            ));
+        }
+
+        if !(1..=MAX_FRAMES_IN_FLIGHT).contains(&self.render.frames_in_flight) {
+            errors.push(format!(
+                "render.frames_in_flight must be between 1 & {}, got {}",
+                MAX_FRAMES_IN_FLIGHT, self.render.frames_in_flight
+            ));
+        }
+
+        if !self.render.msaa.is_power_of_two() || self.render.msaa > 64 {
+            errors.push(format!(
+                "render.msaa must be 1, 2, 4, 8, 16, 32 or 64, got {}",
+                self.render.msaa
+            ));
+        }
+
+        if self
+            .render
+            .clear_color
+            .iter()
+            .any(|component| !component.is_finite() || *component < 0.0)
+        {
+            errors.push(format!(
+                "render.clear_color components must be finite & not negative, got {:?}",
+                self.render.clear_color
+            ));
+        }
+
+        if self.render.instances == Some(0) {
+            errors.push("render.instances must be above 0".to_string());
+        }
+
+        for (name, value) in &[
+            ("post.exposure", self.post.exposure),
+            ("post.gamma", self.post.gamma),
+            ("post.paper_white", self.post.paper_white),
+            ("post.max_luminance", self.post.max_luminance),
+        ] {
+            if !value.is_finite() || *value <= 0.0 {
+                errors.push(format!("{} must be above 0, got {}", name, value));
+            }
+        }
+
+        if self.post.max_luminance < self.post.paper_white {
+            errors.push(format!(
+                "post.max_luminance must be at least post.paper_white ({}), got {}",
+                self.post.paper_white, self.post.max_luminance
+            ));
+        }
+
+        if let Some(material) = &self.assets.material {
+            if !material.is_dir() {
+                errors.push(format!(
+                    "assets.material must be a directory, {:?} isn't",
+                    material
+                ));
+            }
+        }
+
+        for (name, path) in &[
+            ("assets.environment", &self.assets.environment),
+            ("assets.color_lut", &self.assets.color_lut),
+        ] {
+            if let Some(path) = path {
+                if !path.is_file() {
+                    errors.push(format!("{} must be a file, {:?} isn't", name, path));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Invalid configuration:\n  {}", errors.join("\n  ")))
+        }
+    }
+}
+
+// config file if there is one, overridden by the cmd arguments
+pub fn load(opt: Opt) -> Result<Config, String> {
+    let mut config = match &opt.config {
+        Some(path) => Config::from_file(path)?,
+        None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+            Config::from_file(Path::new(DEFAULT_CONFIG_PATH))?
+        }
+        None => Config::default(),
+    };
+
+    config.apply(opt);
+    config.validate()?;
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use structopt::StructOpt;
+
+    fn opt(args: &[&str]) -> Opt {
+        Opt::from_iter(std::iter::once("vulkan_rust").chain(args.iter().copied()))
+    }
+
+    fn parse(text: &str) -> Config {
+        toml::from_str(text).expect("Failed to parse test config!")
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert_eq!(Config::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn every_error_is_reported() {
+        let mut config = parse(
+            r#"
+            [window]
+            width = 0
+
+            [render]
+            frames_in_flight = 0
+            msaa = 3
+            clear_color = [-1.0, 0.0, 0.0, 1.0]
+            instances = 0
+
+            [post]
+            exposure = 0.0
+            paper_white = 500.0
+            max_luminance = 400.0
+            "#,
+        );
+        config.apply(opt(&[]));
+
+        let errors = config
+            .validate()
+            .expect_err("Invalid config passed validation!");
+        for field in &[
+            "window.width",
+            "render.frames_in_flight",
+            "render.msaa",
+            "render.clear_color",
+            "render.instances",
+            "post.exposure",
+            "post.max_luminance",
+        ] {
+            assert!(
+                errors.contains(field),
+                "{} not reported in {}",
+                field,
+                errors
+            );
+        }
+    }
+
+    #[test]
+    fn frames_in_flight_bounds() {
+        for (frames_in_flight, valid) in &[
+            (0, false),
+            (1, true),
+            (MAX_FRAMES_IN_FLIGHT, true),
+            (MAX_FRAMES_IN_FLIGHT + 1, false),
+        ] {
+            let mut config = Config::default();
+            config.render.frames_in_flight = *frames_in_flight;
+            assert_eq!(config.validate().is_ok(), *valid, "{}", frames_in_flight);
+        }
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        assert!(toml::from_str::<Config>("[render]\nframes = 2").is_err());
+        assert!(toml::from_str::<Config>("[renderer]").is_err());
+    }
+
+    #[test]
+    fn bad_enum_names_are_rejected() {
+        let error = toml::from_str::<Config>("[post]\ntonemapper = \"filmic\"")
+            .expect_err("Unknown tonemapper parsed!");
+        assert!(error.to_string().contains("filmic"));
+    }
+
+    #[test]
+    fn no_flags_turn_file_settings_off() {
+        let mut config = parse(
+            r#"
+            [device]
+            gpu_culling = true
+
+            [debug]
+            validation = true
+            validation_fatal = true
+            stats = true
+            "#,
+        );
+        config.apply(opt(&[
+            "--no-validation",
+            "--no-validation-fatal",
+            "--no-stats",
+            "--no-gpu-culling",
+            "--no-bloom",
+            "--no-fxaa",
+            "--no-timeline-semaphores",
+        ]));
+
+        assert!(!config.debug.is_validation_enabled());
+        assert!(!config.debug.stats);
+        assert!(!config.device.gpu_culling);
+        assert!(!config.post.bloom);
+        assert!(!config.post.fxaa);
+        assert!(!config.device.timeline_semaphores);
+    }
+
+    #[test]
+    fn flags_turn_file_settings_on() {
+        let mut config = parse(
+            r#"
+            [post]
+            bloom = false
+
+            [device]
+            timeline_semaphores = false
+            "#,
+        );
+        config.apply(opt(&["--bloom", "--timeline-semaphores", "--validation"]));
+
+        assert!(config.post.bloom);
+        assert!(config.device.timeline_semaphores);
+        assert!(config.debug.validation);
+    }
+
+    #[test]
+    fn missing_flags_keep_file_settings() {
+        let mut config = parse("[post]\nbloom = false\nfxaa = false");
+        config.apply(opt(&[]));
+
+        assert!(!config.post.bloom);
+        assert!(!config.post.fxaa);
+    }
+
+    #[test]
+    fn a_flag_conflicts_with_its_no_flag() {
+        let args = ["vulkan_rust", "--bloom", "--no-bloom"];
+        assert!(Opt::from_iter_safe(args.iter()).is_err());
+    }
+
+    #[test]
+    fn cmd_lists_replace_file_lists() {
+        let mut config = parse(
+            r#"
+            [debug]
+            validation_features = ["sync"]
+            suppress_validation = ["VUID-a", "VUID-b"]
+            "#,
+        );
+        config.apply(opt(&[
+            "--validation-features",
+            "best-practices",
+            "--suppress-validation",
+            "0x1234",
+        ]));
+
+        assert_eq!(
+            config.debug.validation_features,
+            vec![ValidationFeature::BestPractices]
+        );
+        assert_eq!(config.debug.suppress_validation, vec!["0x1234".to_string()]);
+    }
+
+    #[test]
+    fn empty_cmd_lists_keep_file_lists() {
+        let mut config = parse("[debug]\nsuppress_validation = [\"VUID-a\"]");
+        config.apply(opt(&[]));
+
+        assert_eq!(config.debug.suppress_validation, vec!["VUID-a".to_string()]);
+    }
+
+    #[test]
+    fn cmd_values_replace_file_values() {
+        let mut config = parse("[render]\nframes_in_flight = 3\nmsaa = 4");
+        config.apply(opt(&["--msaa", "8"]));
+
+        assert_eq!(config.render.frames_in_flight, 3);
+        assert_eq!(config.render.msaa, 8);
+    }
+
+    #[test]
+    fn asset_paths_are_relative_to_the_file() {
+        let mut config = parse("[assets]\nenvironment = \"sky.hdr\"");
+        config.assets.resolve(Path::new("configs"));
+
+        assert_eq!(
+            config.assets.environment,
+            Some(PathBuf::from("configs").join("sky.hdr"))
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use crate::application::model::{InstanceData, MeshRange};
+
+use ultraviolet::mat::Mat4;
+use ultraviolet::vec::{Vec3, Vec4};
+
+// axis aligned box & the sphere around it, tested sphere first as it's cheaper
+#[derive(Debug, Clone, Copy)]
+pub struct Bounds {
+    pub min: Vec3,
+    pub max: Vec3,
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl Bounds {
+    pub fn from_points(points: impl Iterator<Item = Vec3>) -> Bounds {
+        let (min, max) = points.fold(
+            (Vec3::broadcast(f32::MAX), Vec3::broadcast(f32::MIN)),
+            |(min, max), point| (min.min_by_component(point), max.max_by_component(point)),
+        );
+
+        // no points, empty box at the origin
+        if min.x > max.x {
+            return Bounds::from_box(Vec3::zero(), Vec3::zero());
+        }
+
+        Bounds::from_box(min, max)
+    }
+
+    fn from_box(min: Vec3, max: Vec3) -> Bounds {
+        Bounds {
+            min,
+            max,
+            center: (min + max) * 0.5,
+            radius: (max - min).mag() * 0.5,
+        }
+    }
+
+    // box around both
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        Bounds::from_box(
+            self.min.min_by_component(other.min),
+            self.max.max_by_component(other.max),
+        )
+    }
+
+    // box around the transformed box (Arvo's method), sphere scaled by the largest axis scale
+    pub fn transformed(&self, matrix: &Mat4) -> Bounds {
+        let translation = matrix.cols[3].truncated();
+        let (mut min, mut max) = (translation, translation);
+
+        for (axis, column) in matrix.cols[..3].iter().enumerate() {
+            let column = column.truncated();
+            let a = column * self.min[axis];
+            let b = column * self.max[axis];
+            min += a.min_by_component(b);
+            max += a.max_by_component(b);
+        }
+
+        let scale = (0..3)
+            .map(|column| matrix.cols[column].truncated().mag())
+            .fold(0.0, f32::max);
+
+        Bounds {
+            min,
+            max,
+            center: matrix.transform_point3(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+// bounds of every instance of each mesh, what the node's world matrix is applied to
+pub fn get_instanced_bounds(meshes: &[MeshRange], instances: &[InstanceData]) -> Vec<Bounds> {
+    meshes
+        .iter()
+        .map(|mesh| {
+            instances
+                .iter()
+                .map(|instance| mesh.bounds.transformed(&instance.transform))
+                .reduce(|a, b| a.union(&b))
+                .unwrap_or(mesh.bounds)
+        })
+        .collect()
+}
+
+// clip space planes pulled out of a view projection matrix (Gribb & Hartmann), normals face in
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    pub fn from_matrix(view_projection: &Mat4) -> Frustum {
+        let cols = &view_projection.cols;
+        let row = |index: usize| {
+            Vec4::new(
+                cols[0][index],
+                cols[1][index],
+                cols[2][index],
+                cols[3][index],
+            )
+        };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        // vulkan depth is 0 to 1 so the near plane is just z
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|plane| {
+            let length = plane.truncated().mag();
+            if length > f32::EPSILON {
+                plane / length
+            } else {
+                plane
+            }
+        });
+
+        Frustum { planes }
+    }
+
+    pub fn get_planes(&self) -> [Vec4; 6] {
+        self.planes
+    }
+
+    fn distance(plane: Vec4, point: Vec3) -> f32 {
+        plane.truncated().dot(point) + plane.w
+    }
+
+    pub fn intersects(&self, bounds: &Bounds) -> bool {
+        self.planes.iter().all(|&plane| {
+            // entirely behind the plane
+            if Frustum::distance(plane, bounds.center) < -bounds.radius {
+                return false;
+            }
+
+            // corner furthest along the plane normal
+            let normal = plane.truncated();
+            let corner = Vec3::new(
+                if normal.x >= 0.0 {
+                    bounds.max.x
+                } else {
+                    bounds.min.x
+                },
+                if normal.y >= 0.0 {
+                    bounds.max.y
+                } else {
+                    bounds.min.y
+                },
+                if normal.z >= 0.0 {
+                    bounds.max.z
+                } else {
+                    bounds.min.z
+                },
+            );
+            Frustum::distance(plane, corner) >= 0.0
+        })
+    }
+}
+
+// objects drawn & skipped in the last culled frame
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CullStats {
+    pub drawn: usize,
+    pub culled: usize,
+}
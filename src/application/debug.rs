@@ -0,0 +1,107 @@
+use erupt::{vk, DeviceLoader};
+
+use std::ffi::CString;
+
+// handles that can be given a name, shown in validation messages & capture tools
+pub trait DebugObject {
+    fn get_object(self) -> (vk::ObjectType, u64);
+}
+
+macro_rules! debug_object {
+    ($($handle:ident),*) => {
+        $(
+            impl DebugObject for vk::$handle {
+                fn get_object(self) -> (vk::ObjectType, u64) {
+                    (vk::$handle::TYPE, self.0 as u64)
+                }
+            }
+        )*
+    };
+}
+
+debug_object!(
+    Buffer,
+    DeviceMemory,
+    Image,
+    ImageView,
+    Sampler,
+    ShaderModule,
+    Pipeline,
+    PipelineLayout,
+    PipelineCache,
+    RenderPass,
+    Framebuffer,
+    DescriptorSetLayout,
+    DescriptorPool,
+    DescriptorSet,
+    CommandPool,
+    CommandBuffer,
+    Semaphore,
+    Fence,
+    Queue,
+    SwapchainKHR
+);
+
+// debug utils is enabled whenever the instance supports it, otherwise naming & labels do nothing
+fn is_enabled(device: &DeviceLoader) -> bool {
+    device.set_debug_utils_object_name_ext.is_some()
+}
+
+fn to_cstring(name: &str) -> CString {
+    CString::new(name).expect("Failed to convert debug name!")
+}
+
+pub fn set_object_name<T: DebugObject>(device: &DeviceLoader, object: T, name: &str) {
+    if !is_enabled(device) {
+        return;
+    }
+
+    let (object_type, object_handle) = object.get_object();
+    let name = to_cstring(name);
+    let name_info = vk::DebugUtilsObjectNameInfoEXTBuilder::new()
+        .object_type(object_type)
+        .object_handle(object_handle)
+        .object_name(&name);
+
+    unsafe { device.set_debug_utils_object_name_ext(&name_info) }
+        .expect("Failed to set object name!");
+}
+
+// one object per frame in flight or swapchain image, named "<name> <index>"
+pub fn set_object_names<T: DebugObject + Copy>(device: &DeviceLoader, objects: &[T], name: &str) {
+    for (index, &object) in objects.iter().enumerate() {
+        set_object_name(device, object, &format!("{} {}", name, index));
+    }
+}
+
+// opens a region of commands, closed by end_label
+pub fn begin_label(device: &DeviceLoader, command_buffer: vk::CommandBuffer, name: &str) {
+    if !is_enabled(device) {
+        return;
+    }
+
+    let name = to_cstring(name);
+    let label = vk::DebugUtilsLabelEXTBuilder::new().label_name(&name);
+
+    unsafe { device.cmd_begin_debug_utils_label_ext(command_buffer, &label) };
+}
+
+pub fn end_label(device: &DeviceLoader, command_buffer: vk::CommandBuffer) {
+    if !is_enabled(device) {
+        return;
+    }
+
+    unsafe { device.cmd_end_debug_utils_label_ext(command_buffer) };
+}
+
+// marks a single point between commands
+pub fn insert_label(device: &DeviceLoader, command_buffer: vk::CommandBuffer, name: &str) {
+    if !is_enabled(device) {
+        return;
+    }
+
+    let name = to_cstring(name);
+    let label = vk::DebugUtilsLabelEXTBuilder::new().label_name(&name);
+
+    unsafe { device.cmd_insert_debug_utils_label_ext(command_buffer, &label) };
+}
@@ -0,0 +1,42 @@
+use crate::application::buffer::OwnedBuffer;
+use crate::application::texture;
+
+use std::any::Any;
+
+// owned handles, destroyed when dropped
+type Retired = Box<dyn Any>;
+
+// objects replaced or unloaded while frames may still be using them, each is dropped once the
+// gpu has finished the last frame that could have used it rather than waiting for the device
+// to go idle, whatever is left is dropped with the queue
+#[derive(Default)]
+pub struct DeletionQueue {
+    // frame number last using the objects, not necessarily in order as a frame's own resources
+    // are retired at that frame's number
+    pending: Vec<(u64, Retired)>,
+}
+
+impl DeletionQueue {
+    pub fn new() -> DeletionQueue {
+        DeletionQueue::default()
+    }
+
+    // last_used is the number of the last submitted frame when the object stopped being used,
+    // 0 if no frame was ever submitted with it
+    pub fn push<T: 'static>(&mut self, last_used: u64, object: T) {
+        self.pending.push((last_used, Box::new(object)));
+    }
+
+    pub fn push_buffers(&mut self, last_used: u64, buffers: Vec<OwnedBuffer>) {
+        self.push(last_used, buffers);
+    }
+
+    pub fn push_texture(&mut self, last_used: u64, texture: texture::Texture) {
+        self.push(last_used, texture);
+    }
+
+    // drops everything the frames up to completed used, the rest stays queued
+    pub fn collect(&mut self, completed: u64) {
+        self.pending.retain(|(last_used, _)| *last_used > completed);
+    }
+}
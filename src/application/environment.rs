@@ -0,0 +1,742 @@
+use crate::application::handle::{self, Owned};
+use crate::application::upload::Uploader;
+use crate::application::{debug, pipeline, texture};
+
+use erupt::{vk, DeviceLoader, InstanceLoader};
+
+use half::f16;
+use image::codecs::hdr::HdrDecoder;
+
+use core::ffi::c_void;
+use std::fs::File;
+use std::io::BufReader;
+use std::mem::size_of;
+use std::path::Path;
+use std::rc::Rc;
+
+// image based lighting is descriptor set 2
+pub const ENVIRONMENT_SET: u32 = 2;
+
+// compute shader spvs
+const SHADER_EQUIRECT_TO_CUBE: &[u8] = include_bytes!("../../res/shaders/equirect_to_cube.spv");
+const SHADER_IRRADIANCE: &[u8] = include_bytes!("../../res/shaders/irradiance.spv");
+const SHADER_PREFILTER: &[u8] = include_bytes!("../../res/shaders/prefilter.spv");
+const SHADER_BRDF_LUT: &[u8] = include_bytes!("../../res/shaders/brdf_lut.spv");
+
+// face sizes in texels
+const ENVIRONMENT_SIZE: u32 = 512;
+const IRRADIANCE_SIZE: u32 = 32;
+const PREFILTERED_SIZE: u32 = 128;
+const BRDF_LUT_SIZE: u32 = 256;
+
+// down to 1x1, blitted from the base level so the convolutions can read the level whose texels
+// cover about as much as each of their samples
+const ENVIRONMENT_MIP_LEVELS: u32 = 32 - ENVIRONMENT_SIZE.leading_zeros();
+
+// roughness 0 to 1 across the mip chain, must match PREFILTERED_MAX_LOD in shader.frag
+const PREFILTERED_MIP_LEVELS: u32 = 5;
+
+// storage image, linear filtering & blit support is guaranteed for this format
+const FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+// must match local_size in the compute shaders
+const WORKGROUP_SIZE: u32 = 8;
+
+// procedural sky used when no environment map is given
+const DEFAULT_SKY_EXTENT: vk::Extent2D = vk::Extent2D {
+    width: 128,
+    height: 64,
+};
+const SKY_ZENITH: [f32; 3] = [0.25, 0.45, 0.9];
+const SKY_HORIZON: [f32; 3] = [0.8, 0.85, 0.9];
+const SKY_GROUND: [f32; 3] = [0.2, 0.18, 0.15];
+
+// push constants shared by all the compute shaders
+#[repr(C)]
+struct ComputeConstants {
+    face_size: u32,
+    // prefilter only
+    roughness: f32,
+}
+
+// precomputed maps sampled by the lit & skybox shaders, only read through the descriptor set
+pub struct Environment {
+    _environment: texture::Texture,
+    _irradiance: texture::Texture,
+    _prefiltered: texture::Texture,
+    _brdf_lut: texture::Texture,
+    _sampler: Owned<vk::Sampler>,
+    // frees the descriptor set
+    _descriptor_pool: Owned<vk::DescriptorPool>,
+    pub descriptor_set: vk::DescriptorSet,
+}
+
+// radiance .hdr file as rgba16f pixels
+fn load_equirectangular(path: &Path) -> Result<(vk::Extent2D, Vec<u8>), image::ImageError> {
+    let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+    let metadata = decoder.metadata();
+    let pixels = decoder.read_image_hdr()?;
+
+    let extent = vk::Extent2D {
+        width: metadata.width,
+        height: metadata.height,
+    };
+
+    Ok((
+        extent,
+        to_rgba16f(pixels.iter().map(|pixel| [pixel[0], pixel[1], pixel[2]])),
+    ))
+}
+
+// vertical gradient from ground to horizon to zenith
+fn create_default_sky() -> (vk::Extent2D, Vec<u8>) {
+    let height = DEFAULT_SKY_EXTENT.height;
+
+    let rows = (0..height).map(|y| {
+        // 1 straight up, -1 straight down
+        let elevation = 1.0 - 2.0 * (y as f32 + 0.5) / height as f32;
+
+        let mix = |a: [f32; 3], b: [f32; 3], t: f32| {
+            [
+                a[0] + (b[0] - a[0]) * t,
+                a[1] + (b[1] - a[1]) * t,
+                a[2] + (b[2] - a[2]) * t,
+            ]
+        };
+
+        if elevation >= 0.0 {
+            mix(SKY_HORIZON, SKY_ZENITH, elevation.sqrt())
+        } else {
+            mix(SKY_HORIZON, SKY_GROUND, (-elevation * 8.0).min(1.0))
+        }
+    });
+
+    let pixels = rows.flat_map(|color| (0..DEFAULT_SKY_EXTENT.width).map(move |_| color));
+
+    (DEFAULT_SKY_EXTENT, to_rgba16f(pixels))
+}
+
+fn to_rgba16f(pixels: impl Iterator<Item = [f32; 3]>) -> Vec<u8> {
+    pixels
+        .flat_map(|[r, g, b]| {
+            let mut bytes = [0; 8];
+            for (channel, value) in [r, g, b, 1.0].iter().enumerate() {
+                bytes[channel * 2..channel * 2 + 2]
+                    .copy_from_slice(&f16::from_f32(*value).to_bits().to_ne_bytes());
+            }
+            bytes
+        })
+        .collect()
+}
+
+pub fn create_environment_set_layout(device: &DeviceLoader) -> vk::DescriptorSetLayout {
+    let binding = |binding: u32, descriptor_type: vk::DescriptorType| {
+        vk::DescriptorSetLayoutBindingBuilder::new()
+            .binding(binding)
+            .descriptor_type(descriptor_type)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+    };
+
+    let bindings = &[
+        binding(0, vk::DescriptorType::SAMPLER),
+        // irradiance
+        binding(1, vk::DescriptorType::SAMPLED_IMAGE),
+        // prefiltered specular
+        binding(2, vk::DescriptorType::SAMPLED_IMAGE),
+        // brdf lut
+        binding(3, vk::DescriptorType::SAMPLED_IMAGE),
+        // environment for the skybox
+        binding(4, vk::DescriptorType::SAMPLED_IMAGE),
+    ];
+
+    let create_info = vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(bindings);
+
+    unsafe { device.create_descriptor_set_layout(&create_info, None, None) }
+        .expect("Failed to create environment descriptor set layout!")
+}
+
+// trilinear, clamped so cube faces & the lut edges don't bleed
+fn create_environment_sampler(device: &DeviceLoader) -> vk::Sampler {
+    let sampler_info = vk::SamplerCreateInfoBuilder::new()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .anisotropy_enable(false)
+        .max_anisotropy(1.0)
+        .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+        .unnormalized_coordinates(false)
+        .compare_enable(false)
+        .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+        .min_lod(0.0)
+        .max_lod(vk::LOD_CLAMP_NONE);
+
+    unsafe { device.create_sampler(&sampler_info, None, None) }
+        .expect("Failed to create environment sampler!")
+}
+
+// texture written by compute shaders, returns a storage view (2d array) per mip level
+fn create_storage_texture(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    device: &Rc<handle::Device>,
+    size: u32,
+    mip_levels: u32,
+    array_layers: u32,
+    name: &str,
+) -> (texture::Texture, Vec<Owned<vk::ImageView>>) {
+    let (flags, view_type) = if array_layers == 6 {
+        (
+            vk::ImageCreateFlags::CUBE_COMPATIBLE,
+            vk::ImageViewType::CUBE,
+        )
+    } else {
+        (vk::ImageCreateFlags::empty(), vk::ImageViewType::_2D)
+    };
+
+    let (image, memory) = texture::create_image(
+        instance,
+        physical_device,
+        device,
+        vk::Extent2D {
+            width: size,
+            height: size,
+        },
+        mip_levels,
+        array_layers,
+        vk::SampleCountFlagBits::_1,
+        FORMAT,
+        vk::ImageUsageFlags::STORAGE
+            | vk::ImageUsageFlags::SAMPLED
+            | vk::ImageUsageFlags::TRANSFER_SRC
+            | vk::ImageUsageFlags::TRANSFER_DST,
+        flags,
+    );
+
+    let view = texture::create_image_view(
+        device,
+        image,
+        FORMAT,
+        view_type,
+        texture::color_subresource_range(mip_levels, array_layers),
+    );
+
+    let storage_views = (0..mip_levels)
+        .map(|mip_level| {
+            let mut subresource_range = texture::color_subresource_range(1, array_layers);
+            subresource_range.base_mip_level = mip_level;
+
+            texture::create_image_view(
+                device,
+                image,
+                FORMAT,
+                vk::ImageViewType::_2D_ARRAY,
+                subresource_range,
+            )
+        })
+        .collect();
+
+    (
+        texture::Texture::new(device, image, memory, view, name),
+        Owned::new_each(device, storage_views, &format!("{} storage mip", name)),
+    )
+}
+
+fn create_compute_set_layout(device: &DeviceLoader) -> vk::DescriptorSetLayout {
+    let binding = |binding: u32, descriptor_type: vk::DescriptorType| {
+        vk::DescriptorSetLayoutBindingBuilder::new()
+            .binding(binding)
+            .descriptor_type(descriptor_type)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+    };
+
+    let bindings = &[
+        binding(0, vk::DescriptorType::SAMPLER),
+        // input
+        binding(1, vk::DescriptorType::SAMPLED_IMAGE),
+        // output
+        binding(2, vk::DescriptorType::STORAGE_IMAGE),
+    ];
+
+    let create_info = vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(bindings);
+
+    unsafe { device.create_descriptor_set_layout(&create_info, None, None) }
+        .expect("Failed to create compute descriptor set layout!")
+}
+
+fn create_compute_pipeline_layout(
+    device: &DeviceLoader,
+    set_layout: vk::DescriptorSetLayout,
+) -> vk::PipelineLayout {
+    let set_layouts = &[set_layout];
+    let push_constant_ranges = &[vk::PushConstantRangeBuilder::new()
+        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        .offset(0)
+        .size(size_of::<ComputeConstants>() as u32)];
+
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfoBuilder::new()
+        .set_layouts(set_layouts)
+        .push_constant_ranges(push_constant_ranges);
+
+    unsafe { device.create_pipeline_layout(&pipeline_layout_info, None, None) }
+        .expect("Failed to create compute pipeline layout!")
+}
+
+fn create_compute_descriptor_pool(device: &DeviceLoader, max_sets: u32) -> vk::DescriptorPool {
+    let pool_sizes = &[
+        vk::DescriptorPoolSizeBuilder::new()
+            ._type(vk::DescriptorType::SAMPLER)
+            .descriptor_count(max_sets),
+        vk::DescriptorPoolSizeBuilder::new()
+            ._type(vk::DescriptorType::SAMPLED_IMAGE)
+            .descriptor_count(max_sets),
+        vk::DescriptorPoolSizeBuilder::new()
+            ._type(vk::DescriptorType::STORAGE_IMAGE)
+            .descriptor_count(max_sets),
+    ];
+
+    let pool_info = vk::DescriptorPoolCreateInfoBuilder::new()
+        .pool_sizes(pool_sizes)
+        .max_sets(max_sets);
+
+    unsafe { device.create_descriptor_pool(&pool_info, None, None) }
+        .expect("Failed to create compute descriptor pool!")
+}
+
+// sampled input & storage output of a single dispatch
+fn create_compute_set(
+    device: &DeviceLoader,
+    pool: vk::DescriptorPool,
+    layout: vk::DescriptorSetLayout,
+    sampler: vk::Sampler,
+    input: vk::ImageView,
+    output: vk::ImageView,
+    name: &str,
+) -> vk::DescriptorSet {
+    let layouts = &[layout];
+    let alloc_info = vk::DescriptorSetAllocateInfoBuilder::new()
+        .set_layouts(layouts)
+        .descriptor_pool(pool);
+
+    let descriptor_set = unsafe { device.allocate_descriptor_sets(&alloc_info) }
+        .expect("Failed to allocate compute descriptor set!")[0];
+
+    let sampler_info = &[vk::DescriptorImageInfoBuilder::new().sampler(sampler)];
+    let input_info = &[vk::DescriptorImageInfoBuilder::new()
+        .image_view(input)
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+    let output_info = &[vk::DescriptorImageInfoBuilder::new()
+        .image_view(output)
+        .image_layout(vk::ImageLayout::GENERAL)];
+
+    let descriptor_writes = &[
+        vk::WriteDescriptorSetBuilder::new()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::SAMPLER)
+            .image_info(sampler_info),
+        vk::WriteDescriptorSetBuilder::new()
+            .dst_set(descriptor_set)
+            .dst_binding(1)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(input_info),
+        vk::WriteDescriptorSetBuilder::new()
+            .dst_set(descriptor_set)
+            .dst_binding(2)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .image_info(output_info),
+    ];
+
+    unsafe { device.update_descriptor_sets(descriptor_writes, &[]) };
+
+    debug::set_object_name(device, descriptor_set, &format!("{} descriptor set", name));
+
+    descriptor_set
+}
+
+// one invocation per output texel
+#[allow(clippy::too_many_arguments)]
+fn dispatch(
+    device: &DeviceLoader,
+    command_buffer: vk::CommandBuffer,
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    descriptor_set: vk::DescriptorSet,
+    face_size: u32,
+    roughness: f32,
+    array_layers: u32,
+) {
+    let constants = ComputeConstants {
+        face_size,
+        roughness,
+    };
+    let group_count = face_size.div_ceil(WORKGROUP_SIZE);
+
+    unsafe {
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline);
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            pipeline_layout,
+            0,
+            &[descriptor_set],
+            &[],
+        );
+        device.cmd_push_constants(
+            command_buffer,
+            pipeline_layout,
+            vk::ShaderStageFlags::COMPUTE,
+            0,
+            size_of::<ComputeConstants>() as u32,
+            &constants as *const ComputeConstants as *const c_void,
+        );
+        device.cmd_dispatch(command_buffer, group_count, group_count, array_layers);
+    }
+}
+
+// converts the equirectangular map (or default sky) to a cubemap then precomputes the
+// irradiance map, prefiltered specular mip chain & brdf lut on the gpu
+#[allow(clippy::too_many_arguments)]
+pub fn create_environment(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    device: &Rc<handle::Device>,
+    uploader: &mut Uploader,
+    pipeline_cache: vk::PipelineCache,
+    set_layout: &vk::DescriptorSetLayout,
+    path: Option<&Path>,
+) -> Environment {
+    let (extent, pixels) = path
+        .and_then(|path| {
+            load_equirectangular(path)
+                .map_err(|e| eprintln!("Failed to load environment map {:?}: {}", path, e))
+                .ok()
+        })
+        .unwrap_or_else(create_default_sky);
+
+    let equirectangular = texture::create_texture(
+        instance,
+        physical_device,
+        device,
+        uploader,
+        extent,
+        FORMAT,
+        &pixels,
+        false,
+        "equirectangular environment",
+    );
+
+    let sampler = Owned::new(
+        device,
+        create_environment_sampler(device),
+        "environment sampler",
+    );
+
+    let (environment, environment_views) = create_storage_texture(
+        instance,
+        physical_device,
+        device,
+        ENVIRONMENT_SIZE,
+        ENVIRONMENT_MIP_LEVELS,
+        6,
+        "environment cubemap",
+    );
+    let (irradiance, irradiance_views) = create_storage_texture(
+        instance,
+        physical_device,
+        device,
+        IRRADIANCE_SIZE,
+        1,
+        6,
+        "irradiance cubemap",
+    );
+    let (prefiltered, prefiltered_views) = create_storage_texture(
+        instance,
+        physical_device,
+        device,
+        PREFILTERED_SIZE,
+        PREFILTERED_MIP_LEVELS,
+        6,
+        "prefiltered cubemap",
+    );
+    let (brdf_lut, brdf_lut_views) = create_storage_texture(
+        instance,
+        physical_device,
+        device,
+        BRDF_LUT_SIZE,
+        1,
+        1,
+        "brdf lut",
+    );
+
+    // compute pipelines, only needed until the maps are generated & dropped with the storage
+    // views & equirectangular texture once the commands below have finished
+    let compute_set_layout = Owned::new(
+        device,
+        create_compute_set_layout(device),
+        "environment compute set layout",
+    );
+    let compute_pipeline_layout = Owned::new(
+        device,
+        create_compute_pipeline_layout(device, *compute_set_layout),
+        "environment compute pipeline layout",
+    );
+    let compute_descriptor_pool = Owned::new(
+        device,
+        create_compute_descriptor_pool(device, 3 + PREFILTERED_MIP_LEVELS),
+        "environment compute descriptor pool",
+    );
+
+    let compute_pipelines: Vec<_> = [
+        (SHADER_EQUIRECT_TO_CUBE, "equirect to cube"),
+        (SHADER_IRRADIANCE, "irradiance"),
+        (SHADER_PREFILTER, "prefilter"),
+        (SHADER_BRDF_LUT, "brdf lut"),
+    ]
+    .iter()
+    .map(|(spv, name)| {
+        let shader = pipeline::create_shader_module(device, spv);
+        let compute_pipeline = pipeline::create_compute_pipeline(
+            device,
+            pipeline_cache,
+            shader,
+            *compute_pipeline_layout,
+        );
+
+        (
+            Owned::new(device, compute_pipeline, &format!("{} pipeline", name)),
+            Owned::new(device, shader, &format!("{} shader", name)),
+        )
+    })
+    .collect();
+    let pipelines: Vec<_> = compute_pipelines
+        .iter()
+        .map(|(pipeline, _)| **pipeline)
+        .collect();
+
+    let compute_set = |input: vk::ImageView, output: vk::ImageView, name: &str| {
+        create_compute_set(
+            device,
+            *compute_descriptor_pool,
+            *compute_set_layout,
+            *sampler,
+            input,
+            output,
+            name,
+        )
+    };
+
+    let equirect_to_cube_set = compute_set(
+        *equirectangular.view,
+        *environment_views[0],
+        "equirect to cube",
+    );
+    let irradiance_set = compute_set(*environment.view, *irradiance_views[0], "irradiance");
+    let prefilter_sets: Vec<_> = prefiltered_views
+        .iter()
+        .enumerate()
+        .map(|(mip, view)| {
+            compute_set(*environment.view, **view, &format!("prefilter mip {}", mip))
+        })
+        .collect();
+    // lut doesn't read its input
+    let brdf_lut_set = compute_set(*equirectangular.view, *brdf_lut_views[0], "brdf lut");
+
+    let outputs = [
+        (&environment, ENVIRONMENT_MIP_LEVELS, 6),
+        (&irradiance, 1, 6),
+        (&prefiltered, PREFILTERED_MIP_LEVELS, 6),
+        (&brdf_lut, 1, 1),
+    ];
+
+    let (command_pool, command_buffer) = uploader.begin(device, "environment maps");
+
+    // storage images are written in the general layout
+    for &(texture, mip_levels, array_layers) in &outputs {
+        texture::transition_image_layout(
+            device,
+            command_buffer,
+            *texture.image,
+            texture::color_subresource_range(mip_levels, array_layers),
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::GENERAL,
+        );
+    }
+
+    let run = |pipeline: vk::Pipeline, set: vk::DescriptorSet, size: u32, roughness, layers| {
+        dispatch(
+            device,
+            command_buffer,
+            pipeline,
+            *compute_pipeline_layout,
+            set,
+            size,
+            roughness,
+            layers,
+        )
+    };
+
+    debug::insert_label(device, command_buffer, "equirect to cube");
+    run(pipelines[0], equirect_to_cube_set, ENVIRONMENT_SIZE, 0.0, 6);
+
+    // the rest read the environment cubemap's mip chain
+    debug::insert_label(device, command_buffer, "environment mips");
+    texture::transition_image_layout(
+        device,
+        command_buffer,
+        *environment.image,
+        texture::color_subresource_range(ENVIRONMENT_MIP_LEVELS, 6),
+        vk::ImageLayout::GENERAL,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+    );
+    texture::generate_mipmaps(
+        device,
+        command_buffer,
+        *environment.image,
+        vk::Extent2D {
+            width: ENVIRONMENT_SIZE,
+            height: ENVIRONMENT_SIZE,
+        },
+        ENVIRONMENT_MIP_LEVELS,
+        6,
+    );
+
+    debug::insert_label(device, command_buffer, "irradiance");
+    run(pipelines[1], irradiance_set, IRRADIANCE_SIZE, 0.0, 6);
+
+    for (mip_level, &set) in prefilter_sets.iter().enumerate() {
+        let roughness = mip_level as f32 / (PREFILTERED_MIP_LEVELS - 1) as f32;
+        debug::insert_label(
+            device,
+            command_buffer,
+            &format!("prefilter mip {}", mip_level),
+        );
+        run(
+            pipelines[2],
+            set,
+            PREFILTERED_SIZE >> mip_level,
+            roughness,
+            6,
+        );
+    }
+
+    debug::insert_label(device, command_buffer, "brdf lut");
+    run(pipelines[3], brdf_lut_set, BRDF_LUT_SIZE, 0.0, 1);
+
+    for &(texture, mip_levels, array_layers) in &outputs[1..] {
+        texture::transition_image_layout(
+            device,
+            command_buffer,
+            *texture.image,
+            texture::color_subresource_range(mip_levels, array_layers),
+            vk::ImageLayout::GENERAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+    }
+
+    // the temporaries are dropped once the maps have been generated
+    uploader.end(
+        device,
+        command_pool,
+        command_buffer,
+        (
+            compute_pipelines,
+            compute_descriptor_pool,
+            compute_pipeline_layout,
+            compute_set_layout,
+            (
+                environment_views,
+                irradiance_views,
+                prefiltered_views,
+                brdf_lut_views,
+            ),
+            equirectangular,
+        ),
+    );
+
+    let (descriptor_pool, descriptor_set) = create_environment_descriptor_set(
+        device,
+        set_layout,
+        *sampler,
+        &[
+            *irradiance.view,
+            *prefiltered.view,
+            *brdf_lut.view,
+            *environment.view,
+        ],
+    );
+    debug::set_object_name(device, descriptor_set, "environment descriptor set");
+
+    Environment {
+        _environment: environment,
+        _irradiance: irradiance,
+        _prefiltered: prefiltered,
+        _brdf_lut: brdf_lut,
+        _sampler: sampler,
+        _descriptor_pool: Owned::new(device, descriptor_pool, "environment descriptor pool"),
+        descriptor_set,
+    }
+}
+
+// views in binding order starting at binding 1
+fn create_environment_descriptor_set(
+    device: &DeviceLoader,
+    layout: &vk::DescriptorSetLayout,
+    sampler: vk::Sampler,
+    views: &[vk::ImageView],
+) -> (vk::DescriptorPool, vk::DescriptorSet) {
+    let pool_sizes = &[
+        vk::DescriptorPoolSizeBuilder::new()
+            ._type(vk::DescriptorType::SAMPLER)
+            .descriptor_count(1),
+        vk::DescriptorPoolSizeBuilder::new()
+            ._type(vk::DescriptorType::SAMPLED_IMAGE)
+            .descriptor_count(views.len() as u32),
+    ];
+
+    let pool_info = vk::DescriptorPoolCreateInfoBuilder::new()
+        .pool_sizes(pool_sizes)
+        .max_sets(1);
+
+    let pool = unsafe { device.create_descriptor_pool(&pool_info, None, None) }
+        .expect("Failed to create environment descriptor pool!");
+
+    let layouts = &[*layout];
+    let alloc_info = vk::DescriptorSetAllocateInfoBuilder::new()
+        .set_layouts(layouts)
+        .descriptor_pool(pool);
+
+    let descriptor_set = unsafe { device.allocate_descriptor_sets(&alloc_info) }
+        .expect("Failed to allocate environment descriptor set!")[0];
+
+    let sampler_info = &[vk::DescriptorImageInfoBuilder::new().sampler(sampler)];
+    let image_infos: Vec<_> = views
+        .iter()
+        .map(|&view| {
+            [vk::DescriptorImageInfoBuilder::new()
+                .image_view(view)
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)]
+        })
+        .collect();
+
+    let mut descriptor_writes = vec![vk::WriteDescriptorSetBuilder::new()
+        .dst_set(descriptor_set)
+        .dst_binding(0)
+        .descriptor_type(vk::DescriptorType::SAMPLER)
+        .image_info(sampler_info)];
+
+    descriptor_writes.extend(image_infos.iter().enumerate().map(|(index, image_info)| {
+        vk::WriteDescriptorSetBuilder::new()
+            .dst_set(descriptor_set)
+            .dst_binding(1 + index as u32)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(image_info)
+    }));
+
+    unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+    (pool, descriptor_set)
+}
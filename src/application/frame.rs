@@ -0,0 +1,128 @@
+use crate::application::handle::{self, Owned};
+use crate::application::{buffer, debug, pipeline, render, shadow};
+use crate::Application;
+
+use erupt::{vk, InstanceLoader};
+
+use std::rc::Rc;
+
+// everything a frame in flight writes to or records into, free to reuse once its submit has
+// finished, independent of the swapchain so kept across resizes
+pub struct FrameContext {
+    // signalled by the acquire, waited on by the submit
+    pub image_available: Owned<vk::Semaphore>,
+    // signalled by the submit, waited on by the present
+    pub render_finished: Owned<vk::Semaphore>,
+    // signalled by the submit, None when the frame timeline semaphore is used instead
+    pub in_flight: Option<Owned<vk::Fence>>,
+    // number of the frame last submitted from this context, 0 before its first submit
+    pub submitted: u64,
+    // reset as a whole before the frame is recorded, frees the command buffer when dropped
+    pub command_pool: Owned<vk::CommandPool>,
+    pub command_buffer: vk::CommandBuffer,
+    // dynamic offsets of the camera, light & shadow blocks in the uniform ring, in binding order
+    pub uniform_offsets: [u32; 3],
+    // dynamic offset of each scene node's object block, indexed by node id
+    pub object_offsets: Vec<u32>,
+    // the uniform ring & shadow map, freed with the descriptor pool
+    pub descriptor_set: vk::DescriptorSet,
+    // instance data is rewritten every frame, replaced when the instance count outgrows it
+    pub instance_buffer: Owned<vk::Buffer>,
+    pub instance_buffer_memory: Owned<vk::DeviceMemory>,
+}
+
+impl FrameContext {
+    // offsets for every dynamic binding of set 0, the frame's blocks then the object's
+    pub fn get_dynamic_offsets(&self, object_offset: u32) -> [u32; 4] {
+        let [camera, light, shadow] = self.uniform_offsets;
+        [camera, light, shadow, object_offset]
+    }
+}
+
+// frame currently being recorded
+pub fn get_current(app: &Application) -> &FrameContext {
+    &app.frames[app.current_frame]
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn create_frames(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    device: &Rc<handle::Device>,
+    queue_family: u32,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    uniform_buffers: &[vk::Buffer],
+    shadow_maps: &shadow::ShadowMaps,
+    instance_capacity: usize,
+    count: usize,
+    fences: bool,
+) -> Vec<FrameContext> {
+    let (image_available, render_finished, in_flight) =
+        render::create_sync_primitives(device, count, fences);
+
+    let descriptor_sets = pipeline::create_descriptor_sets(
+        device,
+        &descriptor_set_layout,
+        &descriptor_pool,
+        uniform_buffers,
+        *shadow_maps.texture.view,
+        *shadow_maps.sampler,
+    );
+
+    let instance_buffers = buffer::create_instance_buffers(
+        instance,
+        physical_device,
+        device,
+        instance_capacity,
+        count,
+    );
+
+    // empty when the frame timeline semaphore is used
+    let mut in_flight = in_flight.into_iter();
+
+    image_available
+        .into_iter()
+        .zip(render_finished)
+        .zip(descriptor_sets)
+        .zip(instance_buffers)
+        .enumerate()
+        .map(
+            |(index, (((image_available, render_finished), descriptor_set), instance_buffer))| {
+                let (instance_buffer, instance_buffer_memory) = instance_buffer;
+
+                // command buffers are only recorded once per use of the frame
+                let command_pool = render::create_command_pool(
+                    device,
+                    queue_family,
+                    vk::CommandPoolCreateFlags::TRANSIENT,
+                );
+                let command_pool = Owned::new(
+                    device,
+                    command_pool,
+                    &format!("frame command pool {}", index),
+                );
+                let command_buffer = render::allocate_command_buffers(device, &command_pool, 1)[0];
+                debug::set_object_name(
+                    device,
+                    command_buffer,
+                    &format!("frame command buffer {}", index),
+                );
+
+                FrameContext {
+                    image_available,
+                    render_finished,
+                    in_flight: in_flight.next(),
+                    submitted: 0,
+                    command_pool,
+                    command_buffer,
+                    uniform_offsets: [0; 3],
+                    object_offsets: Vec::new(),
+                    descriptor_set,
+                    instance_buffer,
+                    instance_buffer_memory,
+                }
+            },
+        )
+        .collect()
+}
@@ -0,0 +1,751 @@
+use crate::application::handle;
+use crate::application::{debug, deletion, texture};
+use crate::Application;
+
+use erupt::{vk, InstanceLoader};
+
+use std::collections::HashSet;
+use std::rc::Rc;
+
+// records a pass' commands, called with the swapchain image index
+pub type RecordFn = fn(&Application, vk::CommandBuffer, usize);
+
+// looks up an imported image for the swapchain image index
+pub type ImageFn = fn(&Application, usize) -> vk::Image;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResourceId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PassId(usize);
+
+// how a pass uses an image, decides layouts & barriers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    // contents don't matter
+    Undefined,
+    // swapchain image, the acquire semaphore is waited on at color attachment output
+    Acquired,
+    ColorAttachment,
+    DepthAttachment,
+    SampledFragment,
+    Present,
+}
+
+impl Access {
+    fn layout(self) -> vk::ImageLayout {
+        match self {
+            Access::Undefined | Access::Acquired => vk::ImageLayout::UNDEFINED,
+            Access::ColorAttachment => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            Access::DepthAttachment => vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            Access::SampledFragment => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            Access::Present => vk::ImageLayout::PRESENT_SRC_KHR,
+        }
+    }
+
+    fn stage(self) -> vk::PipelineStageFlags {
+        match self {
+            Access::Undefined => vk::PipelineStageFlags::TOP_OF_PIPE,
+            Access::Acquired | Access::ColorAttachment => {
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            }
+            Access::DepthAttachment => {
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+            }
+            Access::SampledFragment => vk::PipelineStageFlags::FRAGMENT_SHADER,
+            Access::Present => vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+        }
+    }
+
+    fn access_mask(self) -> vk::AccessFlags {
+        match self {
+            Access::Undefined | Access::Acquired | Access::Present => vk::AccessFlags::empty(),
+            Access::ColorAttachment => {
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            }
+            Access::DepthAttachment => {
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+            }
+            Access::SampledFragment => vk::AccessFlags::SHADER_READ,
+        }
+    }
+
+    fn is_write(self) -> bool {
+        matches!(self, Access::ColorAttachment | Access::DepthAttachment)
+    }
+
+    fn usage(self) -> vk::ImageUsageFlags {
+        match self {
+            Access::ColorAttachment => vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            Access::DepthAttachment => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            Access::SampledFragment => vk::ImageUsageFlags::SAMPLED,
+            _ => vk::ImageUsageFlags::empty(),
+        }
+    }
+}
+
+// image allocated by the graph, only valid during a frame
+#[derive(Debug, Clone, Copy)]
+struct TransientDescription {
+    format: vk::Format,
+    extent: vk::Extent2D,
+    aspect_mask: vk::ImageAspectFlags,
+    usage: vk::ImageUsageFlags,
+    samples: vk::SampleCountFlagBits,
+}
+
+// Extent2D doesn't implement PartialEq
+impl PartialEq for TransientDescription {
+    fn eq(&self, other: &Self) -> bool {
+        self.format == other.format
+            && self.extent.width == other.extent.width
+            && self.extent.height == other.extent.height
+            && self.aspect_mask == other.aspect_mask
+            && self.usage == other.usage
+            && self.samples == other.samples
+    }
+}
+
+struct TransientImage {
+    name: &'static str,
+    description: TransientDescription,
+    texture: texture::Texture,
+}
+
+enum ResourceKind {
+    // owned elsewhere, state carried over between frames through initial & final access
+    Imported {
+        image: ImageFn,
+        initial: Access,
+        final_access: Option<Access>,
+    },
+    Transient {
+        format: vk::Format,
+        extent: vk::Extent2D,
+        samples: vk::SampleCountFlagBits,
+    },
+}
+
+struct Resource {
+    name: &'static str,
+    aspect_mask: vk::ImageAspectFlags,
+    array_layers: u32,
+    kind: ResourceKind,
+}
+
+struct Pass {
+    name: &'static str,
+    record: RecordFn,
+    reads: Vec<(ResourceId, Access)>,
+    writes: Vec<(ResourceId, Access)>,
+}
+
+impl Pass {
+    fn accesses(&self) -> impl Iterator<Item = &(ResourceId, Access)> {
+        self.reads.iter().chain(self.writes.iter())
+    }
+}
+
+// passes declare the images they read & write, compile() orders them, culls passes whose
+// writes are never read, allocates transient images & works out the barriers between passes
+pub struct RenderGraph {
+    resources: Vec<Resource>,
+    passes: Vec<Pass>,
+    // passes that survived culling in execution order
+    order: Vec<PassId>,
+    transients: Vec<TransientImage>,
+}
+
+impl RenderGraph {
+    pub fn new() -> RenderGraph {
+        RenderGraph {
+            resources: Vec::new(),
+            passes: Vec::new(),
+            order: Vec::new(),
+            transients: Vec::new(),
+        }
+    }
+
+    // final_access of Some marks the image as an output of the graph, passes writing it are kept
+    pub fn import_image(
+        &mut self,
+        name: &'static str,
+        aspect_mask: vk::ImageAspectFlags,
+        array_layers: u32,
+        image: ImageFn,
+        initial: Access,
+        final_access: Option<Access>,
+    ) -> ResourceId {
+        self.resources.push(Resource {
+            name,
+            aspect_mask,
+            array_layers,
+            kind: ResourceKind::Imported {
+                image,
+                initial,
+                final_access,
+            },
+        });
+        ResourceId(self.resources.len() - 1)
+    }
+
+    // usage is worked out from the passes using it
+    pub fn add_transient_image(
+        &mut self,
+        name: &'static str,
+        format: vk::Format,
+        extent: vk::Extent2D,
+        aspect_mask: vk::ImageAspectFlags,
+    ) -> ResourceId {
+        self.add_multisampled_image(
+            name,
+            format,
+            extent,
+            aspect_mask,
+            vk::SampleCountFlagBits::_1,
+        )
+    }
+
+    // multisampled attachments can't be sampled, resolve them into a transient image instead
+    pub fn add_multisampled_image(
+        &mut self,
+        name: &'static str,
+        format: vk::Format,
+        extent: vk::Extent2D,
+        aspect_mask: vk::ImageAspectFlags,
+        samples: vk::SampleCountFlagBits,
+    ) -> ResourceId {
+        self.resources.push(Resource {
+            name,
+            aspect_mask,
+            array_layers: 1,
+            kind: ResourceKind::Transient {
+                format,
+                extent,
+                samples,
+            },
+        });
+        ResourceId(self.resources.len() - 1)
+    }
+
+    pub fn add_pass(&mut self, name: &'static str, record: RecordFn) -> PassId {
+        self.passes.push(Pass {
+            name,
+            record,
+            reads: Vec::new(),
+            writes: Vec::new(),
+        });
+        PassId(self.passes.len() - 1)
+    }
+
+    pub fn read(&mut self, pass: PassId, resource: ResourceId, access: Access) {
+        self.passes[pass.0].reads.push((resource, access));
+    }
+
+    pub fn write(&mut self, pass: PassId, resource: ResourceId, access: Access) {
+        self.passes[pass.0].writes.push((resource, access));
+    }
+
+    // view of a transient image, for framebuffers
+    pub fn get_image_view(&self, resource: ResourceId) -> vk::ImageView {
+        let name = self.resources[resource.0].name;
+        self.transients
+            .iter()
+            .find(|transient| transient.name == name)
+            .map(|transient| *transient.texture.view)
+            .expect("Failed to find transient image, is it used by any pass?")
+    }
+
+    // transient images with the same name & description are taken from previous rather than
+    // reallocated, the rest of previous is destroyed once the frame last_used has finished
+    pub fn compile(
+        &mut self,
+        instance: &InstanceLoader,
+        physical_device: &vk::PhysicalDevice,
+        device: &Rc<handle::Device>,
+        previous: Option<RenderGraph>,
+        deletion_queue: &mut deletion::DeletionQueue,
+        last_used: u64,
+    ) {
+        let alive = self.cull_passes();
+        self.order = self.sort_passes(&alive);
+
+        let mut reusable = previous.map(|graph| graph.transients).unwrap_or_default();
+
+        for (index, resource) in self.resources.iter().enumerate() {
+            let (format, extent, samples) = match resource.kind {
+                ResourceKind::Transient {
+                    format,
+                    extent,
+                    samples,
+                } => (format, extent, samples),
+                ResourceKind::Imported { .. } => continue,
+            };
+
+            // union of every use by a surviving pass
+            let usage = self
+                .order
+                .iter()
+                .flat_map(|pass| self.passes[pass.0].accesses())
+                .filter(|(id, _)| id.0 == index)
+                .fold(vk::ImageUsageFlags::empty(), |usage, (_, access)| {
+                    usage | access.usage()
+                });
+
+            if usage.is_empty() {
+                continue;
+            }
+
+            // attachments that are never sampled don't need to be backed by memory on tilers
+            let usage = if usage.contains(vk::ImageUsageFlags::SAMPLED) {
+                usage
+            } else {
+                usage | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT
+            };
+
+            let description = TransientDescription {
+                format,
+                extent,
+                aspect_mask: resource.aspect_mask,
+                usage,
+                samples,
+            };
+
+            let existing = reusable.iter().position(|transient| {
+                transient.name == resource.name && transient.description == description
+            });
+
+            let transient = match existing {
+                Some(position) => reusable.remove(position),
+                None => create_transient_image(
+                    instance,
+                    physical_device,
+                    device,
+                    resource.name,
+                    description,
+                ),
+            };
+
+            self.transients.push(transient);
+        }
+
+        for transient in reusable {
+            deletion_queue.push_texture(last_used, transient.texture);
+        }
+    }
+
+    // walks back from the graph outputs keeping passes that write something needed
+    fn cull_passes(&self) -> Vec<PassId> {
+        let mut needed: HashSet<ResourceId> = self
+            .resources
+            .iter()
+            .enumerate()
+            .filter(|(_, resource)| {
+                matches!(
+                    resource.kind,
+                    ResourceKind::Imported {
+                        final_access: Some(_),
+                        ..
+                    }
+                )
+            })
+            .map(|(index, _)| ResourceId(index))
+            .collect();
+
+        // passes may be added before the writers they read from, so keep sweeping until no
+        // more passes come alive
+        let mut is_alive = vec![false; self.passes.len()];
+        let mut changed = true;
+
+        while changed {
+            changed = false;
+
+            for (index, pass) in self.passes.iter().enumerate().rev() {
+                if !is_alive[index] && pass.writes.iter().any(|(id, _)| needed.contains(id)) {
+                    needed.extend(pass.reads.iter().map(|&(id, _)| id));
+                    is_alive[index] = true;
+                    changed = true;
+                }
+            }
+        }
+
+        self.passes
+            .iter()
+            .enumerate()
+            .filter(|&(index, pass)| {
+                if !is_alive[index] {
+                    log::debug!("Render graph culled unused pass {:?}", pass.name);
+                }
+
+                is_alive[index]
+            })
+            .map(|(index, _)| PassId(index))
+            .collect()
+    }
+
+    // topological order where readers of a resource come after its writers, ties are broken
+    // by the order passes were added
+    fn sort_passes(&self, alive: &[PassId]) -> Vec<PassId> {
+        let depends_on = |pass: PassId, other: PassId| {
+            pass != other
+                && self.passes[pass.0].reads.iter().any(|(read, _)| {
+                    self.passes[other.0]
+                        .writes
+                        .iter()
+                        .any(|(write, _)| write == read)
+                })
+        };
+
+        let mut remaining = alive.to_vec();
+        let mut order = Vec::new();
+
+        while !remaining.is_empty() {
+            let next = remaining
+                .iter()
+                .position(|&pass| !remaining.iter().any(|&other| depends_on(pass, other)))
+                .expect("Failed to order render graph, passes depend on each other!");
+            order.push(remaining.remove(next));
+        }
+
+        order
+    }
+
+    fn get_image(&self, app: &Application, resource: ResourceId, image_index: usize) -> vk::Image {
+        let resource = &self.resources[resource.0];
+        match resource.kind {
+            ResourceKind::Imported { image, .. } => image(app, image_index),
+            ResourceKind::Transient { .. } => {
+                *self
+                    .transients
+                    .iter()
+                    .find(|transient| transient.name == resource.name)
+                    .expect("Failed to find transient image!")
+                    .texture
+                    .image
+            }
+        }
+    }
+
+    // records every pass with barriers between them, then moves imports to their final access
+    pub fn execute(
+        &self,
+        app: &Application,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+    ) {
+        // last access of each resource this frame
+        let mut states: Vec<Access> = self
+            .resources
+            .iter()
+            .map(|resource| match resource.kind {
+                ResourceKind::Imported { initial, .. } => initial,
+                ResourceKind::Transient { .. } => Access::Undefined,
+            })
+            .collect();
+
+        for pass in &self.order {
+            let pass = &self.passes[pass.0];
+
+            let transitions: Vec<_> = pass
+                .accesses()
+                .map(|&(id, access)| {
+                    let transition = (id, states[id.0], access);
+                    states[id.0] = access;
+                    transition
+                })
+                .collect();
+
+            debug::begin_label(&app.device, command_buffer, pass.name);
+
+            self.record_barriers(app, command_buffer, image_index, &transitions);
+
+            (pass.record)(app, command_buffer, image_index);
+
+            debug::end_label(&app.device, command_buffer);
+        }
+
+        let final_transitions: Vec<_> = self
+            .resources
+            .iter()
+            .enumerate()
+            .filter_map(|(index, resource)| match resource.kind {
+                ResourceKind::Imported {
+                    final_access: Some(final_access),
+                    ..
+                } => Some((ResourceId(index), states[index], final_access)),
+                // imports are left how they were found if not an output
+                ResourceKind::Imported { initial, .. } => {
+                    Some((ResourceId(index), states[index], initial))
+                }
+                ResourceKind::Transient { .. } => None,
+            })
+            .collect();
+
+        self.record_barriers(app, command_buffer, image_index, &final_transitions);
+    }
+
+    // one pipeline barrier for all (resource, from, to) transitions needing one
+    fn record_barriers(
+        &self,
+        app: &Application,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        transitions: &[(ResourceId, Access, Access)],
+    ) {
+        let mut src_stage_mask = vk::PipelineStageFlags::empty();
+        let mut dst_stage_mask = vk::PipelineStageFlags::empty();
+
+        let barriers: Vec<_> = transitions
+            .iter()
+            // read after read in the same layout is the only case without a hazard
+            .filter(|(_, from, to)| {
+                from.layout() != to.layout() || from.is_write() || to.is_write()
+            })
+            .map(|&(id, from, to)| {
+                // transients are shared by frames in flight, so discarding the contents still
+                // has to wait for the previous frame's use of the image
+                let (src_stage, src_access) = match from {
+                    Access::Undefined => (to.stage(), to.access_mask()),
+                    _ => (from.stage(), from.access_mask()),
+                };
+                src_stage_mask |= src_stage;
+                dst_stage_mask |= to.stage();
+
+                let resource = &self.resources[id.0];
+                let subresource_range = vk::ImageSubresourceRangeBuilder::new()
+                    .aspect_mask(resource.aspect_mask)
+                    .base_mip_level(0)
+                    .level_count(1)
+                    .base_array_layer(0)
+                    .layer_count(resource.array_layers)
+                    .build();
+
+                vk::ImageMemoryBarrierBuilder::new()
+                    .old_layout(from.layout())
+                    .new_layout(to.layout())
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(self.get_image(app, id, image_index))
+                    .subresource_range(subresource_range)
+                    .src_access_mask(src_access)
+                    .dst_access_mask(to.access_mask())
+            })
+            .collect();
+
+        if barriers.is_empty() {
+            return;
+        }
+
+        unsafe {
+            app.device.cmd_pipeline_barrier(
+                command_buffer,
+                src_stage_mask,
+                dst_stage_mask,
+                None,
+                &[],
+                &[],
+                &barriers,
+            )
+        };
+    }
+}
+
+fn create_transient_image(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    device: &Rc<handle::Device>,
+    name: &'static str,
+    description: TransientDescription,
+) -> TransientImage {
+    let (image, memory) = texture::create_image(
+        instance,
+        physical_device,
+        device,
+        description.extent,
+        1,
+        1,
+        description.samples,
+        description.format,
+        description.usage,
+        vk::ImageCreateFlags::empty(),
+    );
+
+    let mut subresource_range = texture::color_subresource_range(1, 1);
+    subresource_range.aspect_mask = description.aspect_mask;
+
+    let view = texture::create_image_view(
+        device,
+        image,
+        description.format,
+        vk::ImageViewType::_2D,
+        subresource_range,
+    );
+
+    TransientImage {
+        name,
+        description,
+        texture: texture::Texture::new(device, image, memory, view, name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(_: &Application, _: vk::CommandBuffer, _: usize) {}
+
+    fn image(_: &Application, _: usize) -> vk::Image {
+        vk::Image::null()
+    }
+
+    fn extent() -> vk::Extent2D {
+        vk::Extent2D {
+            width: 4,
+            height: 4,
+        }
+    }
+
+    // a graph whose only output is the swapchain image
+    fn graph_with_output() -> (RenderGraph, ResourceId) {
+        let mut graph = RenderGraph::new();
+        let swapchain = graph.import_image(
+            "swapchain",
+            vk::ImageAspectFlags::COLOR,
+            1,
+            image,
+            Access::Acquired,
+            Some(Access::Present),
+        );
+        (graph, swapchain)
+    }
+
+    fn transient(graph: &mut RenderGraph, name: &'static str) -> ResourceId {
+        graph.add_transient_image(
+            name,
+            vk::Format::R8G8B8A8_UNORM,
+            extent(),
+            vk::ImageAspectFlags::COLOR,
+        )
+    }
+
+    fn compile_order(graph: &RenderGraph) -> Vec<&'static str> {
+        let alive = graph.cull_passes();
+        graph
+            .sort_passes(&alive)
+            .iter()
+            .map(|pass| graph.passes[pass.0].name)
+            .collect()
+    }
+
+    #[test]
+    fn readers_come_after_writers() {
+        let (mut graph, swapchain) = graph_with_output();
+        let color = transient(&mut graph, "color");
+
+        // added out of order
+        let post = graph.add_pass("post", record);
+        graph.read(post, color, Access::SampledFragment);
+        graph.write(post, swapchain, Access::ColorAttachment);
+        let lit = graph.add_pass("lit", record);
+        graph.write(lit, color, Access::ColorAttachment);
+
+        assert_eq!(compile_order(&graph), vec!["lit", "post"]);
+    }
+
+    #[test]
+    fn independent_passes_keep_their_order() {
+        let (mut graph, swapchain) = graph_with_output();
+        let shadow = transient(&mut graph, "shadow");
+        let bloom = transient(&mut graph, "bloom");
+
+        let shadow_pass = graph.add_pass("shadow", record);
+        graph.write(shadow_pass, shadow, Access::DepthAttachment);
+        let bloom_pass = graph.add_pass("bloom", record);
+        graph.write(bloom_pass, bloom, Access::ColorAttachment);
+        let composite = graph.add_pass("composite", record);
+        graph.read(composite, shadow, Access::SampledFragment);
+        graph.read(composite, bloom, Access::SampledFragment);
+        graph.write(composite, swapchain, Access::ColorAttachment);
+
+        assert_eq!(compile_order(&graph), vec!["shadow", "bloom", "composite"]);
+    }
+
+    #[test]
+    fn passes_writing_unread_images_are_culled() {
+        let (mut graph, swapchain) = graph_with_output();
+        let color = transient(&mut graph, "color");
+        let unused = transient(&mut graph, "unused");
+        let untracked = graph.import_image(
+            "untracked",
+            vk::ImageAspectFlags::COLOR,
+            1,
+            image,
+            Access::Undefined,
+            None,
+        );
+
+        let lit = graph.add_pass("lit", record);
+        graph.write(lit, color, Access::ColorAttachment);
+        let debug = graph.add_pass("debug", record);
+        graph.read(debug, color, Access::SampledFragment);
+        graph.write(debug, unused, Access::ColorAttachment);
+        let imported = graph.add_pass("imported", record);
+        graph.write(imported, untracked, Access::ColorAttachment);
+        let post = graph.add_pass("post", record);
+        graph.read(post, color, Access::SampledFragment);
+        graph.write(post, swapchain, Access::ColorAttachment);
+
+        assert_eq!(compile_order(&graph), vec!["lit", "post"]);
+    }
+
+    #[test]
+    fn writers_only_read_by_culled_passes_are_culled() {
+        let (mut graph, swapchain) = graph_with_output();
+        let color = transient(&mut graph, "color");
+        let shadow = transient(&mut graph, "shadow");
+        let unused = transient(&mut graph, "unused");
+
+        let shadow_pass = graph.add_pass("shadow", record);
+        graph.write(shadow_pass, shadow, Access::DepthAttachment);
+        let debug = graph.add_pass("debug", record);
+        graph.read(debug, shadow, Access::SampledFragment);
+        graph.write(debug, unused, Access::ColorAttachment);
+        let lit = graph.add_pass("lit", record);
+        graph.write(lit, color, Access::ColorAttachment);
+        let post = graph.add_pass("post", record);
+        graph.read(post, color, Access::SampledFragment);
+        graph.write(post, swapchain, Access::ColorAttachment);
+
+        assert_eq!(compile_order(&graph), vec!["lit", "post"]);
+    }
+
+    #[test]
+    fn nothing_survives_without_an_output() {
+        let mut graph = RenderGraph::new();
+        let color = transient(&mut graph, "color");
+        let lit = graph.add_pass("lit", record);
+        graph.write(lit, color, Access::ColorAttachment);
+
+        assert!(compile_order(&graph).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "passes depend on each other")]
+    fn cycles_panic() {
+        let (mut graph, swapchain) = graph_with_output();
+        let a = transient(&mut graph, "a");
+        let b = transient(&mut graph, "b");
+
+        let first = graph.add_pass("first", record);
+        graph.read(first, b, Access::SampledFragment);
+        graph.write(first, a, Access::ColorAttachment);
+        let second = graph.add_pass("second", record);
+        graph.read(second, a, Access::SampledFragment);
+        graph.write(second, b, Access::ColorAttachment);
+        graph.write(second, swapchain, Access::ColorAttachment);
+
+        compile_order(&graph);
+    }
+}
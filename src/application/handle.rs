@@ -0,0 +1,300 @@
+use crate::application::debug::{self, DebugObject};
+use crate::application::validation::Validation;
+
+use erupt::{vk, DeviceLoader, InstanceLoader};
+
+use std::ops::Deref;
+use std::rc::{Rc, Weak};
+
+#[cfg(debug_assertions)]
+use std::collections::BTreeMap;
+#[cfg(debug_assertions)]
+use std::sync::Mutex;
+
+// debug builds track every owned handle, keyed by object type & raw handle, with its debug name
+#[cfg(debug_assertions)]
+static LIVE_HANDLES: Mutex<BTreeMap<(i32, u64), (vk::ObjectType, String)>> =
+    Mutex::new(BTreeMap::new());
+
+#[cfg(debug_assertions)]
+fn register((object_type, handle): (vk::ObjectType, u64), name: &str) {
+    LIVE_HANDLES
+        .lock()
+        .expect("Failed to lock live handles!")
+        .insert((object_type.0, handle), (object_type, name.to_string()));
+}
+
+#[cfg(not(debug_assertions))]
+fn register(_: (vk::ObjectType, u64), _: &str) {}
+
+#[cfg(debug_assertions)]
+fn unregister((object_type, handle): (vk::ObjectType, u64)) {
+    LIVE_HANDLES
+        .lock()
+        .expect("Failed to lock live handles!")
+        .remove(&(object_type.0, handle));
+}
+
+#[cfg(not(debug_assertions))]
+fn unregister(_: (vk::ObjectType, u64)) {}
+
+// anything still registered outlived the device & will never be destroyed
+#[cfg(debug_assertions)]
+fn report_leaks() {
+    let live = LIVE_HANDLES.lock().expect("Failed to lock live handles!");
+    if live.is_empty() {
+        return;
+    }
+
+    println!("{} objects still alive at device destruction!", live.len());
+    for ((_, handle), (object_type, name)) in live.iter() {
+        println!("  {:?} {:#x} {:?}", object_type, handle, name);
+    }
+}
+
+#[cfg(not(debug_assertions))]
+fn report_leaks() {}
+
+// the instance & debug messenger, destroyed after the device as that holds on to it
+pub struct Instance {
+    loader: InstanceLoader,
+    messenger: vk::DebugUtilsMessengerEXT,
+    // the messenger's user data, must outlive it
+    validation: Box<Validation>,
+}
+
+impl Instance {
+    pub fn new(
+        loader: InstanceLoader,
+        messenger: vk::DebugUtilsMessengerEXT,
+        validation: Box<Validation>,
+    ) -> Rc<Instance> {
+        Rc::new(Instance {
+            loader,
+            messenger,
+            validation,
+        })
+    }
+
+    pub fn get_validation(&self) -> &Validation {
+        &self.validation
+    }
+}
+
+impl Deref for Instance {
+    type Target = InstanceLoader;
+
+    fn deref(&self) -> &InstanceLoader {
+        &self.loader
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        unsafe {
+            // destroyed last so validation still reports on the device's destruction
+            if !self.messenger.is_null() {
+                self.loader
+                    .destroy_debug_utils_messenger_ext(Some(self.messenger), None);
+            }
+
+            self.loader.destroy_instance(None);
+        }
+
+        // errors destroying the device & everything it owned aren't part of any frame, a panic
+        // already unwinding through here would abort
+        if !std::thread::panicking() {
+            self.validation.check_fatal();
+        }
+
+        println!("All cleaned up!")
+    }
+}
+
+// shared by every owned handle, each holds it weakly so the device is destroyed when its owner
+// drops it rather than kept alive by a leaked handle
+pub struct Device {
+    // dropped before the instance, the instance loader panics if a device loader outlives it
+    loader: DeviceLoader,
+    _instance: Rc<Instance>,
+}
+
+impl Device {
+    pub fn new(loader: DeviceLoader, instance: &Rc<Instance>) -> Rc<Device> {
+        Rc::new(Device {
+            loader,
+            _instance: Rc::clone(instance),
+        })
+    }
+}
+
+impl Deref for Device {
+    type Target = DeviceLoader;
+
+    fn deref(&self) -> &DeviceLoader {
+        &self.loader
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        report_leaks();
+
+        unsafe { self.loader.destroy_device(None) };
+    }
+}
+
+// handles destroyed with the device that created them
+pub trait Destroy: DebugObject + Copy {
+    // # Safety
+    // the handle must not be in use by the gpu
+    unsafe fn destroy(self, device: &DeviceLoader);
+}
+
+macro_rules! destroy {
+    ($($handle:ident => $function:ident),*) => {
+        $(
+            impl Destroy for vk::$handle {
+                unsafe fn destroy(self, device: &DeviceLoader) {
+                    device.$function(Some(self), None);
+                }
+            }
+        )*
+    };
+}
+
+destroy!(
+    Buffer => destroy_buffer,
+    DeviceMemory => free_memory,
+    Image => destroy_image,
+    ImageView => destroy_image_view,
+    Sampler => destroy_sampler,
+    ShaderModule => destroy_shader_module,
+    Pipeline => destroy_pipeline,
+    PipelineLayout => destroy_pipeline_layout,
+    PipelineCache => destroy_pipeline_cache,
+    RenderPass => destroy_render_pass,
+    Framebuffer => destroy_framebuffer,
+    DescriptorSetLayout => destroy_descriptor_set_layout,
+    DescriptorPool => destroy_descriptor_pool,
+    CommandPool => destroy_command_pool,
+    Semaphore => destroy_semaphore,
+    Fence => destroy_fence,
+    SwapchainKHR => destroy_swapchain_khr
+);
+
+// a handle destroyed when dropped, named through debug utils & tracked in debug builds, the
+// gpu must be done with it by then
+pub struct Owned<T: Destroy> {
+    handle: T,
+    device: Weak<Device>,
+}
+
+impl<T: Destroy> Owned<T> {
+    pub fn new(device: &Rc<Device>, handle: T, name: &str) -> Owned<T> {
+        debug::set_object_name(device, handle, name);
+        register(handle.get_object(), name);
+
+        Owned {
+            handle,
+            device: Rc::downgrade(device),
+        }
+    }
+
+    // one handle per frame in flight or swapchain image, named "<name> <index>"
+    pub fn new_each(device: &Rc<Device>, handles: Vec<T>, name: &str) -> Vec<Owned<T>> {
+        handles
+            .into_iter()
+            .enumerate()
+            .map(|(index, handle)| Owned::new(device, handle, &format!("{} {}", name, index)))
+            .collect()
+    }
+}
+
+impl<T: Destroy> Deref for Owned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.handle
+    }
+}
+
+impl<T: Destroy> Drop for Owned<T> {
+    fn drop(&mut self) {
+        unregister(self.handle.get_object());
+
+        // already reported as leaked if the device went first
+        if let Some(device) = self.device.upgrade() {
+            unsafe { self.handle.destroy(&device) };
+        }
+    }
+}
+
+// a descriptor set freed back to its pool when dropped, the pool is held on to so it can't be
+// destroyed first & must have been created with FREE_DESCRIPTOR_SET
+pub struct OwnedDescriptorSet {
+    handle: vk::DescriptorSet,
+    pool: Rc<Owned<vk::DescriptorPool>>,
+}
+
+impl OwnedDescriptorSet {
+    pub fn new(
+        device: &Rc<Device>,
+        handle: vk::DescriptorSet,
+        pool: &Rc<Owned<vk::DescriptorPool>>,
+        name: &str,
+    ) -> OwnedDescriptorSet {
+        debug::set_object_name(device, handle, name);
+
+        OwnedDescriptorSet {
+            handle,
+            pool: Rc::clone(pool),
+        }
+    }
+}
+
+impl Deref for OwnedDescriptorSet {
+    type Target = vk::DescriptorSet;
+
+    fn deref(&self) -> &vk::DescriptorSet {
+        &self.handle
+    }
+}
+
+impl Drop for OwnedDescriptorSet {
+    fn drop(&mut self) {
+        if let Some(device) = self.pool.device.upgrade() {
+            unsafe { device.free_descriptor_sets(self.pool.handle, &[self.handle]) }
+                .expect("Failed to free descriptor set!");
+        }
+    }
+}
+
+// the window surface, destroyed through the instance after every swapchain created from it
+pub struct Surface {
+    handle: vk::SurfaceKHR,
+    instance: Rc<Instance>,
+}
+
+impl Surface {
+    pub fn new(instance: &Rc<Instance>, handle: vk::SurfaceKHR) -> Surface {
+        Surface {
+            handle,
+            instance: Rc::clone(instance),
+        }
+    }
+}
+
+impl Deref for Surface {
+    type Target = vk::SurfaceKHR;
+
+    fn deref(&self) -> &vk::SurfaceKHR {
+        &self.handle
+    }
+}
+
+impl Drop for Surface {
+    fn drop(&mut self) {
+        unsafe { self.instance.destroy_surface_khr(Some(self.handle), None) };
+    }
+}
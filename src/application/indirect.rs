@@ -0,0 +1,637 @@
+use crate::application::culling::{Bounds, CullStats, Frustum};
+use crate::application::deletion::DeletionQueue;
+use crate::application::handle::{self, Owned};
+use crate::application::lod::{self, MAX_LODS};
+use crate::application::material::MATERIAL_SET;
+use crate::application::model::{
+    DrawConstants, InstanceData, MeshRange, ObjectUniformBlock, LOD_FROM_INSTANCE,
+};
+use crate::application::scene::Scene;
+use crate::application::uniform::UniformRing;
+use crate::application::{buffer, debug, frame, pipeline, render};
+use crate::Application;
+
+use erupt::{vk, DeviceLoader, InstanceLoader};
+
+use ultraviolet::mat::Mat4;
+use ultraviolet::vec::Vec4;
+
+use core::ffi::c_void;
+use std::mem::{self, size_of};
+use std::rc::Rc;
+
+const SHADER_CULL: &[u8] = include_bytes!("../../res/shaders/cull.spv");
+
+// must match local_size_x in cull.comp
+const WORKGROUP_SIZE: u32 = 64;
+
+const COMMAND_STRIDE: u32 = size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+
+// storage buffers read & written by cull.comp
+const BINDING_COUNT: u32 = 6;
+
+// must match Object in cull.comp
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GpuObject {
+    model: Mat4,
+    tint: Vec4,
+    mesh: u32,
+    batch: u32,
+    batch_first: u32,
+    _padding: u32,
+}
+
+// must match Mesh in cull.comp
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GpuMesh {
+    lod_count: u32,
+    vertex_offset: i32,
+    _padding: [u32; 2],
+    // first index & index count of each level of detail
+    lods: [[u32; 2]; MAX_LODS],
+    // xyz centre, w radius
+    center: [f32; 4],
+    bounds_min: [f32; 4],
+    bounds_max: [f32; 4],
+}
+
+// must match CullConstants in cull.comp
+#[repr(C)]
+struct CullConstants {
+    planes: [Vec4; 6],
+    // levels of detail are picked by the culling pass from lod::get_camera_terms
+    w_row: Vec4,
+    object_count: u32,
+    instance_count: u32,
+    compact: u32,
+    projection_scale: f32,
+}
+
+// objects sharing a material, drawn with one indirect call
+#[derive(Clone, Copy)]
+struct Batch {
+    material: usize,
+    // command slots
+    first: u32,
+    count: u32,
+    // the batch's block in the uniform ring, transforms & tints are in the culled instances
+    object_offset: u32,
+}
+
+// buffers used by one frame in flight
+pub struct IndirectFrame {
+    // host visible, rewritten every frame
+    objects: buffer::OwnedBuffer,
+    meshes: buffer::OwnedBuffer,
+    // node transform * instance transform, bound as the instance vertex buffer
+    output_instances: buffer::OwnedBuffer,
+    commands: buffer::OwnedBuffer,
+    // drawn objects per batch, host visible so the stats can read them back
+    counts: buffer::OwnedBuffer,
+    descriptor_set: vk::DescriptorSet,
+    batches: Vec<Batch>,
+    object_count: u32,
+    instance_count: u32,
+}
+
+impl IndirectFrame {
+    fn into_buffers(self) -> Vec<buffer::OwnedBuffer> {
+        vec![
+            self.objects,
+            self.meshes,
+            self.output_instances,
+            self.commands,
+            self.counts,
+        ]
+    }
+}
+
+// frustum culling in a compute pass writing the draw commands for the lit pass
+pub struct IndirectDraws {
+    frames: Vec<IndirectFrame>,
+    // frees the frames' descriptor sets
+    _descriptor_pool: Owned<vk::DescriptorPool>,
+    pipeline: Owned<vk::Pipeline>,
+    pipeline_layout: Owned<vk::PipelineLayout>,
+    _set_layout: Owned<vk::DescriptorSetLayout>,
+    _shader: Owned<vk::ShaderModule>,
+    // objects, meshes & output instances the buffers fit
+    capacity: (usize, usize, usize),
+    // VK_KHR_draw_indirect_count is enabled, so draws are packed & the gpu sets the count
+    draw_count: bool,
+    multi_draw: bool,
+}
+
+fn create_cull_set_layout(device: &DeviceLoader) -> vk::DescriptorSetLayout {
+    let bindings: Vec<_> = (0..BINDING_COUNT)
+        .map(|binding| {
+            vk::DescriptorSetLayoutBindingBuilder::new()
+                .binding(binding)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        })
+        .collect();
+
+    let create_info = vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&bindings);
+
+    unsafe { device.create_descriptor_set_layout(&create_info, None, None) }
+        .expect("Failed to create cull descriptor set layout!")
+}
+
+fn create_cull_pipeline_layout(
+    device: &DeviceLoader,
+    set_layout: vk::DescriptorSetLayout,
+) -> vk::PipelineLayout {
+    let set_layouts = &[set_layout];
+    let push_constant_ranges = &[vk::PushConstantRangeBuilder::new()
+        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        .offset(0)
+        .size(size_of::<CullConstants>() as u32)];
+
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfoBuilder::new()
+        .set_layouts(set_layouts)
+        .push_constant_ranges(push_constant_ranges);
+
+    unsafe { device.create_pipeline_layout(&pipeline_layout_info, None, None) }
+        .expect("Failed to create cull pipeline layout!")
+}
+
+fn create_cull_descriptor_pool(
+    device: &DeviceLoader,
+    frames_in_flight: usize,
+) -> vk::DescriptorPool {
+    let pool_sizes = &[vk::DescriptorPoolSizeBuilder::new()
+        ._type(vk::DescriptorType::STORAGE_BUFFER)
+        .descriptor_count(BINDING_COUNT * frames_in_flight as u32)];
+
+    let pool_info = vk::DescriptorPoolCreateInfoBuilder::new()
+        .pool_sizes(pool_sizes)
+        .max_sets(frames_in_flight as u32);
+
+    unsafe { device.create_descriptor_pool(&pool_info, None, None) }
+        .expect("Failed to create cull descriptor pool!")
+}
+
+fn create_frame(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    device: &Rc<handle::Device>,
+    descriptor_set: vk::DescriptorSet,
+    frame_index: usize,
+    (objects, meshes, instances): (usize, usize, usize),
+) -> IndirectFrame {
+    let create = |name: &str,
+                  size: usize,
+                  usage: vk::BufferUsageFlags,
+                  properties: vk::MemoryPropertyFlags| {
+        buffer::create_buffer(
+            instance,
+            physical_device,
+            device,
+            size.max(1) as u64,
+            usage | vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::SharingMode::EXCLUSIVE,
+            properties,
+            &format!("cull {} {}", name, frame_index),
+        )
+    };
+    let host_visible =
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+    let device_local = vk::MemoryPropertyFlags::DEVICE_LOCAL;
+
+    IndirectFrame {
+        objects: create(
+            "objects",
+            size_of::<GpuObject>() * objects,
+            vk::BufferUsageFlags::empty(),
+            host_visible,
+        ),
+        meshes: create(
+            "meshes",
+            size_of::<GpuMesh>() * meshes,
+            vk::BufferUsageFlags::empty(),
+            host_visible,
+        ),
+        output_instances: create(
+            "output instances",
+            size_of::<InstanceData>() * instances,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            device_local,
+        ),
+        commands: create(
+            "draw commands",
+            COMMAND_STRIDE as usize * objects,
+            vk::BufferUsageFlags::INDIRECT_BUFFER,
+            device_local,
+        ),
+        // at most one batch per object
+        counts: create(
+            "draw counts",
+            size_of::<u32>() * objects,
+            vk::BufferUsageFlags::INDIRECT_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            host_visible,
+        ),
+        descriptor_set,
+        batches: Vec::new(),
+        object_count: 0,
+        instance_count: 0,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn create_indirect_draws(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    device: &Rc<handle::Device>,
+    pipeline_cache: vk::PipelineCache,
+    draw_count: bool,
+    multi_draw: bool,
+    frames_in_flight: usize,
+) -> IndirectDraws {
+    let set_layout = Owned::new(device, create_cull_set_layout(device), "cull set layout");
+    let pipeline_layout = create_cull_pipeline_layout(device, *set_layout);
+    let pipeline_layout = Owned::new(device, pipeline_layout, "cull pipeline layout");
+    let shader = pipeline::create_shader_module(device, SHADER_CULL);
+    let shader = Owned::new(device, shader, "cull shader");
+    let pipeline =
+        pipeline::create_compute_pipeline(device, pipeline_cache, *shader, *pipeline_layout);
+    let pipeline = Owned::new(device, pipeline, "cull pipeline");
+
+    let descriptor_pool = create_cull_descriptor_pool(device, frames_in_flight);
+    let descriptor_pool = Owned::new(device, descriptor_pool, "cull descriptor pool");
+    let set_layouts = vec![*set_layout; frames_in_flight];
+    let alloc_info = vk::DescriptorSetAllocateInfoBuilder::new()
+        .descriptor_pool(*descriptor_pool)
+        .set_layouts(&set_layouts);
+    let descriptor_sets = unsafe { device.allocate_descriptor_sets(&alloc_info) }
+        .expect("Failed to allocate cull descriptor sets!");
+
+    debug::set_object_names(device, &descriptor_sets, "cull descriptor set");
+
+    // grown as needed by update
+    let capacity = (1, 1, 1);
+    let frames = descriptor_sets
+        .into_iter()
+        .enumerate()
+        .map(|(frame_index, descriptor_set)| {
+            create_frame(
+                instance,
+                physical_device,
+                device,
+                descriptor_set,
+                frame_index,
+                capacity,
+            )
+        })
+        .collect();
+
+    IndirectDraws {
+        frames,
+        _descriptor_pool: descriptor_pool,
+        pipeline,
+        pipeline_layout,
+        _set_layout: set_layout,
+        _shader: shader,
+        capacity,
+        draw_count,
+        multi_draw,
+    }
+}
+
+// gathers this frame's objects into batches by material & uploads them, returns what the
+// last cull of this frame's buffers found (read back now its fence has been waited on)
+#[allow(clippy::too_many_arguments)]
+pub fn update(
+    indirect: &mut IndirectDraws,
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    device: &Rc<handle::Device>,
+    frame_index: usize,
+    scene: &Scene,
+    meshes: &[MeshRange],
+    mesh_bounds: &[Bounds],
+    instance_buffer: vk::Buffer,
+    instance_count: usize,
+    uniforms: &mut UniformRing,
+    deletion_queue: &mut DeletionQueue,
+    last_used: u64,
+) -> CullStats {
+    let stats = {
+        let frame = &indirect.frames[frame_index];
+        let counts: Vec<u32> =
+            buffer::read_storage_buffer(device, &frame.counts.1, frame.batches.len());
+        let drawn = counts.iter().sum::<u32>() as usize;
+
+        CullStats {
+            drawn,
+            culled: (frame.object_count as usize).saturating_sub(drawn),
+        }
+    };
+
+    // stable so objects keep their scene order within a batch
+    let mut items: Vec<_> = scene.get_visible_items().collect();
+    items.sort_by_key(|item| item.constants.material_index);
+
+    let mut batches: Vec<Batch> = Vec::new();
+    let objects: Vec<_> = items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let material = item.constants.material_index as usize;
+            match batches.last_mut() {
+                Some(batch) if batch.material == material => batch.count += 1,
+                _ => batches.push(Batch {
+                    material,
+                    first: index as u32,
+                    count: 1,
+                    object_offset: 0,
+                }),
+            }
+            let batch = batches.last().expect("Failed to get draw batch!");
+
+            GpuObject {
+                model: item.object.model,
+                tint: item.constants.tint,
+                mesh: item.mesh as u32,
+                batch: (batches.len() - 1) as u32,
+                batch_first: batch.first,
+                _padding: 0,
+            }
+        })
+        .collect();
+
+    // transforms are already in the culled instances, so every batch shares an identity block
+    let object_offset = uniforms.push(&ObjectUniformBlock::default());
+    for batch in batches.iter_mut() {
+        batch.object_offset = object_offset;
+    }
+
+    let gpu_meshes: Vec<_> = meshes
+        .iter()
+        .zip(mesh_bounds)
+        .map(|(mesh, bounds)| GpuMesh {
+            lod_count: mesh.lod_count as u32,
+            vertex_offset: mesh.vertex_offset,
+            _padding: [0; 2],
+            lods: mesh.lods.map(|lod| [lod.first_index, lod.index_count]),
+            center: [
+                bounds.center.x,
+                bounds.center.y,
+                bounds.center.z,
+                bounds.radius,
+            ],
+            bounds_min: [bounds.min.x, bounds.min.y, bounds.min.z, 0.0],
+            bounds_max: [bounds.max.x, bounds.max.y, bounds.max.z, 0.0],
+        })
+        .collect();
+
+    // grow every frame's buffers, the others' old ones are freed once their frames finish
+    let required = (
+        objects.len(),
+        gpu_meshes.len(),
+        objects.len() * instance_count,
+    );
+    if required.0 > indirect.capacity.0
+        || required.1 > indirect.capacity.1
+        || required.2 > indirect.capacity.2
+    {
+        indirect.capacity = (
+            required.0.max(indirect.capacity.0 * 2),
+            required.1.max(indirect.capacity.1),
+            required.2.max(indirect.capacity.2 * 2),
+        );
+
+        for (index, frame) in indirect.frames.iter_mut().enumerate() {
+            let grown = create_frame(
+                instance,
+                physical_device,
+                device,
+                frame.descriptor_set,
+                index,
+                indirect.capacity,
+            );
+            deletion_queue.push_buffers(last_used, mem::replace(frame, grown).into_buffers());
+        }
+    }
+
+    let frame = &mut indirect.frames[frame_index];
+    buffer::update_storage_buffer(device, &objects, &frame.objects.1);
+    buffer::update_storage_buffer(device, &gpu_meshes, &frame.meshes.1);
+    frame.batches = batches;
+    frame.object_count = objects.len() as u32;
+    frame.instance_count = instance_count as u32;
+
+    // the instance buffer is recreated when it grows so the set is rewritten every frame,
+    // safe as the fence for this frame has been waited on
+    let buffer_infos: Vec<_> = [
+        *frame.objects.0,
+        *frame.meshes.0,
+        instance_buffer,
+        *frame.output_instances.0,
+        *frame.commands.0,
+        *frame.counts.0,
+    ]
+    .iter()
+    .map(|&buffer| {
+        [vk::DescriptorBufferInfoBuilder::new()
+            .buffer(buffer)
+            .offset(0)
+            .range(vk::WHOLE_SIZE)]
+    })
+    .collect();
+
+    let descriptor_writes: Vec<_> = buffer_infos
+        .iter()
+        .enumerate()
+        .map(|(binding, info)| {
+            vk::WriteDescriptorSetBuilder::new()
+                .dst_set(frame.descriptor_set)
+                .dst_binding(binding as u32)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(info)
+        })
+        .collect();
+
+    unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+    stats
+}
+
+// clears the counts & runs the culling pass, recorded before the render graph
+pub fn record_cull(app: &Application, command_buffer: vk::CommandBuffer, frame: &IndirectFrame) {
+    if frame.object_count == 0 {
+        return;
+    }
+
+    let indirect = app
+        .indirect
+        .as_ref()
+        .expect("Failed to get indirect draws!");
+
+    let frustum = Frustum::from_matrix(&(app.ubo.proj * app.ubo.view));
+    let (w_row, projection_scale) = lod::get_camera_terms(&app.ubo.view, &app.ubo.proj);
+    let constants = CullConstants {
+        planes: frustum.get_planes(),
+        w_row,
+        object_count: frame.object_count,
+        instance_count: frame.instance_count,
+        compact: indirect.draw_count as u32,
+        projection_scale,
+    };
+
+    let counts_size = (size_of::<u32>() * frame.batches.len()) as u64;
+
+    unsafe {
+        app.device
+            .cmd_fill_buffer(command_buffer, *frame.counts.0, 0, counts_size, 0);
+
+        let cleared = [vk::MemoryBarrierBuilder::new()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)];
+        app.device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            None,
+            &cleared,
+            &[],
+            &[],
+        );
+
+        app.device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            *indirect.pipeline,
+        );
+        app.device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            *indirect.pipeline_layout,
+            0,
+            &[frame.descriptor_set],
+            &[],
+        );
+        app.device.cmd_push_constants(
+            command_buffer,
+            *indirect.pipeline_layout,
+            vk::ShaderStageFlags::COMPUTE,
+            0,
+            size_of::<CullConstants>() as u32,
+            &constants as *const CullConstants as *const c_void,
+        );
+        app.device.cmd_dispatch(
+            command_buffer,
+            frame.instance_count.div_ceil(WORKGROUP_SIZE),
+            frame.object_count,
+            1,
+        );
+
+        // commands & instances are read by the lit pass, counts by the stats on the host
+        let culled = [vk::MemoryBarrierBuilder::new()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(
+                vk::AccessFlags::INDIRECT_COMMAND_READ
+                    | vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+                    | vk::AccessFlags::HOST_READ,
+            )];
+        app.device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::DRAW_INDIRECT
+                | vk::PipelineStageFlags::VERTEX_INPUT
+                | vk::PipelineStageFlags::HOST,
+            None,
+            &culled,
+            &[],
+            &[],
+        );
+    }
+}
+
+pub fn get_frame(app: &Application) -> Option<&IndirectFrame> {
+    app.indirect
+        .as_ref()
+        .map(|indirect| &indirect.frames[app.current_frame])
+}
+
+// one indirect draw per material, replaces the per node draws in the lit pass, the culled
+// instances carry what the cpu path binds per draw (model matrix & tint baked into each instance,
+// the level of detail in InstanceData::lod) so both have to be kept in sync with
+// render::record_lit_pass & shader.vert
+pub fn record_draws(app: &Application, command_buffer: vk::CommandBuffer, frame: &IndirectFrame) {
+    let indirect = app
+        .indirect
+        .as_ref()
+        .expect("Failed to get indirect draws!");
+    let context = frame::get_current(app);
+
+    unsafe {
+        // node transforms are already in the culled instances
+        app.device
+            .cmd_bind_vertex_buffers(command_buffer, 1, &[*frame.output_instances.0], &[0]);
+    }
+
+    for (index, batch) in frame.batches.iter().enumerate() {
+        let offset = (batch.first * COMMAND_STRIDE) as u64;
+
+        unsafe {
+            app.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                *app.pipeline_layout,
+                0,
+                &[context.descriptor_set],
+                &context.get_dynamic_offsets(batch.object_offset),
+            );
+            app.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                *app.pipeline_layout,
+                MATERIAL_SET,
+                &[*app.materials[batch.material].descriptor_set],
+                &[],
+            );
+            // identity object block & a white tint as both are already in the instances
+            render::push_draw_constants(
+                &app.device,
+                command_buffer,
+                *app.pipeline_layout,
+                &DrawConstants::new(Vec4::one(), batch.material as u32, LOD_FROM_INSTANCE),
+            );
+
+            if indirect.draw_count {
+                app.device.cmd_draw_indexed_indirect_count_khr(
+                    command_buffer,
+                    *frame.commands.0,
+                    offset,
+                    *frame.counts.0,
+                    (index * size_of::<u32>()) as u64,
+                    batch.count,
+                    COMMAND_STRIDE,
+                );
+            } else if indirect.multi_draw {
+                app.device.cmd_draw_indexed_indirect(
+                    command_buffer,
+                    *frame.commands.0,
+                    offset,
+                    batch.count,
+                    COMMAND_STRIDE,
+                );
+            } else {
+                // culled draws are still issued with 0 instances
+                for draw in 0..batch.count as u64 {
+                    app.device.cmd_draw_indexed_indirect(
+                        command_buffer,
+                        *frame.commands.0,
+                        offset + draw * COMMAND_STRIDE as u64,
+                        1,
+                        COMMAND_STRIDE,
+                    );
+                }
+            }
+        }
+    }
+}
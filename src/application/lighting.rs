@@ -0,0 +1,228 @@
+use ultraviolet::vec::{Vec3, Vec4};
+
+// must match array sizes in shader.frag
+pub const MAX_DIRECTIONAL_LIGHTS: usize = 4;
+pub const MAX_POINT_LIGHTS: usize = 8;
+pub const MAX_SPOT_LIGHTS: usize = 4;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct DirectionalLight {
+    // xyz direction light travels in, w unused
+    pub direction: Vec4,
+    // rgb color, a intensity
+    pub color: Vec4,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct PointLight {
+    // xyz position, w range (light falls off to zero at range)
+    pub position: Vec4,
+    // rgb color, a intensity
+    pub color: Vec4,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct SpotLight {
+    // xyz position, w range
+    pub position: Vec4,
+    // xyz direction light travels in, w unused
+    pub direction: Vec4,
+    // rgb color, a intensity
+    pub color: Vec4,
+    // x cos of inner cone angle (full intensity), y cos of outer cone angle (zero intensity)
+    pub cone: Vec4,
+}
+
+// std140 layout of the light uniform block (binding 1)
+#[repr(C, align(16))]
+pub struct LightUniformBlock {
+    // rgb tint, a intensity of the image based lighting
+    pub ambient: Vec4,
+    // xyz used for specular
+    pub camera_position: Vec4,
+    // x directional light count, y point light count, z spot light count
+    pub counts: [u32; 4],
+    pub directional_lights: [DirectionalLight; MAX_DIRECTIONAL_LIGHTS],
+    pub point_lights: [PointLight; MAX_POINT_LIGHTS],
+    pub spot_lights: [SpotLight; MAX_SPOT_LIGHTS],
+}
+
+// host side list of lights in the scene
+pub struct Lights {
+    pub ambient: Vec4,
+    pub camera_position: Vec3,
+    directional_lights: Vec<DirectionalLight>,
+    point_lights: Vec<PointLight>,
+    spot_lights: Vec<SpotLight>,
+}
+
+impl Lights {
+    pub fn new() -> Lights {
+        Lights {
+            ambient: Vec4::one(),
+            camera_position: Vec3::zero(),
+            directional_lights: Vec::new(),
+            point_lights: Vec::new(),
+            spot_lights: Vec::new(),
+        }
+    }
+
+    pub fn set_ambient(&mut self, color: Vec3, intensity: f32) {
+        self.ambient = Vec4::new(color.x, color.y, color.z, intensity);
+    }
+
+    // returns index of light, None if there are already MAX_DIRECTIONAL_LIGHTS
+    pub fn add_directional_light(
+        &mut self,
+        direction: Vec3,
+        color: Vec3,
+        intensity: f32,
+    ) -> Option<usize> {
+        if self.directional_lights.len() == MAX_DIRECTIONAL_LIGHTS {
+            return None;
+        }
+
+        let direction = direction.normalized();
+        self.directional_lights.push(DirectionalLight {
+            direction: Vec4::new(direction.x, direction.y, direction.z, 0.0),
+            color: Vec4::new(color.x, color.y, color.z, intensity),
+        });
+
+        Some(self.directional_lights.len() - 1)
+    }
+
+    // returns index of light, None if there are already MAX_POINT_LIGHTS
+    pub fn add_point_light(
+        &mut self,
+        position: Vec3,
+        color: Vec3,
+        intensity: f32,
+        range: f32,
+    ) -> Option<usize> {
+        if self.point_lights.len() == MAX_POINT_LIGHTS {
+            return None;
+        }
+
+        self.point_lights.push(PointLight {
+            position: Vec4::new(position.x, position.y, position.z, range),
+            color: Vec4::new(color.x, color.y, color.z, intensity),
+        });
+
+        Some(self.point_lights.len() - 1)
+    }
+
+    // angles are from the cone axis in radians, returns None if there are already MAX_SPOT_LIGHTS
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_spot_light(
+        &mut self,
+        position: Vec3,
+        direction: Vec3,
+        color: Vec3,
+        intensity: f32,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Option<usize> {
+        if self.spot_lights.len() == MAX_SPOT_LIGHTS {
+            return None;
+        }
+
+        let direction = direction.normalized();
+        self.spot_lights.push(SpotLight {
+            position: Vec4::new(position.x, position.y, position.z, range),
+            direction: Vec4::new(direction.x, direction.y, direction.z, 0.0),
+            color: Vec4::new(color.x, color.y, color.z, intensity),
+            cone: Vec4::new(inner_angle.cos(), outer_angle.cos(), 0.0, 0.0),
+        });
+
+        Some(self.spot_lights.len() - 1)
+    }
+
+    pub fn directional_lights(&self) -> &[DirectionalLight] {
+        &self.directional_lights
+    }
+
+    pub fn spot_lights(&self) -> &[SpotLight] {
+        &self.spot_lights
+    }
+
+    // the setters & removals return false if there's no light at index
+
+    pub fn aim_directional_light(&mut self, index: usize, direction: Vec3) -> bool {
+        match self.directional_lights.get_mut(index) {
+            Some(light) => {
+                let direction = direction.normalized();
+                light.direction = Vec4::new(direction.x, direction.y, direction.z, 0.0);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn move_point_light(&mut self, index: usize, position: Vec3) -> bool {
+        match self.point_lights.get_mut(index) {
+            Some(light) => {
+                light.position = Vec4::new(position.x, position.y, position.z, light.position.w);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // lights after a removed one move down an index
+    pub fn remove_directional_light(&mut self, index: usize) -> bool {
+        remove(&mut self.directional_lights, index)
+    }
+
+    pub fn remove_point_light(&mut self, index: usize) -> bool {
+        remove(&mut self.point_lights, index)
+    }
+
+    pub fn remove_spot_light(&mut self, index: usize) -> bool {
+        remove(&mut self.spot_lights, index)
+    }
+
+    // lights packed for upload
+    pub fn get_uniform_block(&self) -> LightUniformBlock {
+        let mut directional_lights = [DirectionalLight::default(); MAX_DIRECTIONAL_LIGHTS];
+        directional_lights[..self.directional_lights.len()]
+            .copy_from_slice(&self.directional_lights);
+
+        let mut point_lights = [PointLight::default(); MAX_POINT_LIGHTS];
+        point_lights[..self.point_lights.len()].copy_from_slice(&self.point_lights);
+
+        let mut spot_lights = [SpotLight::default(); MAX_SPOT_LIGHTS];
+        spot_lights[..self.spot_lights.len()].copy_from_slice(&self.spot_lights);
+
+        LightUniformBlock {
+            ambient: self.ambient,
+            camera_position: Vec4::new(
+                self.camera_position.x,
+                self.camera_position.y,
+                self.camera_position.z,
+                1.0,
+            ),
+            counts: [
+                self.directional_lights.len() as u32,
+                self.point_lights.len() as u32,
+                self.spot_lights.len() as u32,
+                0,
+            ],
+            directional_lights,
+            point_lights,
+            spot_lights,
+        }
+    }
+}
+
+fn remove<T>(lights: &mut Vec<T>, index: usize) -> bool {
+    if index >= lights.len() {
+        return false;
+    }
+
+    lights.remove(index);
+    true
+}
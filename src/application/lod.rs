@@ -0,0 +1,212 @@
+use crate::application::culling::Bounds;
+use crate::application::model::Index;
+
+use ultraviolet::mat::Mat4;
+use ultraviolet::vec::{Vec3, Vec4};
+
+use std::collections::{HashMap, HashSet};
+
+// full detail plus up to 3 simplified levels, must match MAX_LODS in cull.comp
+pub const MAX_LODS: usize = 4;
+
+// projected radius (1.0 is half the screen) below which lod 1 is used, halved for each level,
+// must match LOD_SCREEN_SIZE in cull.comp
+const LOD_SCREEN_SIZE: f32 = 0.5;
+
+// finest grid vertices are clustered on, halved for each level after
+const MAX_GRID_SIZE: u32 = 64;
+
+// a level is only kept if it has at most this fraction of the previous level's triangles
+const MIN_REDUCTION: f32 = 0.75;
+
+// vertex clustering, vertices in the same grid cell merge into the first one found & triangles
+// that collapse are dropped, vertex attributes are kept so levels share the vertex buffer
+fn simplify(positions: &[Vec3], indices: &[Index], bounds: &Bounds, grid_size: u32) -> Vec<Index> {
+    let extent = bounds.max - bounds.min;
+    let cell = |position: Vec3| {
+        let coordinate = |offset: f32, extent: f32| {
+            if extent > f32::EPSILON {
+                ((offset / extent * grid_size as f32) as u32).min(grid_size - 1)
+            } else {
+                0
+            }
+        };
+        let offset = position - bounds.min;
+
+        (
+            coordinate(offset.x, extent.x),
+            coordinate(offset.y, extent.y),
+            coordinate(offset.z, extent.z),
+        )
+    };
+
+    let mut representatives = HashMap::new();
+    let remap: Vec<Index> = positions
+        .iter()
+        .enumerate()
+        .map(|(index, &position)| {
+            *representatives
+                .entry(cell(position))
+                .or_insert(index as Index)
+        })
+        .collect();
+
+    let mut seen = HashSet::new();
+    let mut simplified = Vec::new();
+
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [
+            remap[triangle[0] as usize],
+            remap[triangle[1] as usize],
+            remap[triangle[2] as usize],
+        ];
+
+        // collapsed to a line or point
+        if a == b || b == c || a == c {
+            continue;
+        }
+
+        // duplicates left after merging, either winding
+        let mut key = [a, b, c];
+        key.sort_unstable();
+        if !seen.insert(key) {
+            continue;
+        }
+
+        simplified.extend_from_slice(&[a, b, c]);
+    }
+
+    simplified
+}
+
+// index buffers of decreasing triangle count after the full detail one
+pub fn generate_lods(positions: &[Vec3], indices: &[Index]) -> Vec<Vec<Index>> {
+    let bounds = Bounds::from_points(positions.iter().copied());
+
+    let mut lods: Vec<Vec<Index>> = Vec::new();
+    let mut grid_size = MAX_GRID_SIZE;
+
+    while grid_size >= 1 && lods.len() < MAX_LODS - 1 {
+        let previous = lods.last().map_or(indices.len(), |lod| lod.len());
+        let simplified = simplify(positions, indices, &bounds, grid_size);
+
+        if !simplified.is_empty() && simplified.len() as f32 <= previous as f32 * MIN_REDUCTION {
+            lods.push(simplified);
+        }
+
+        grid_size /= 2;
+    }
+
+    lods
+}
+
+// how big the bounds appear, projected radius over clip w (1.0 is half the screen)
+pub fn get_screen_size(bounds: &Bounds, world: &Mat4, w_row: Vec4, projection_scale: f32) -> f32 {
+    let center = world.transform_point3(bounds.center);
+    let scale = (0..3)
+        .map(|column| world.cols[column].truncated().mag())
+        .fold(0.0, f32::max);
+
+    let w = w_row.truncated().dot(center) + w_row.w;
+
+    bounds.radius * scale * projection_scale / w.max(f32::EPSILON)
+}
+
+pub fn select_lod(screen_size: f32, lod_count: usize) -> usize {
+    if screen_size <= 0.0 {
+        return lod_count - 1;
+    }
+
+    // 0 at or above LOD_SCREEN_SIZE, then one level each time the size halves
+    let level = (LOD_SCREEN_SIZE / screen_size).log2().ceil();
+    (level.max(0.0) as usize).min(lod_count - 1)
+}
+
+// clip space w of a point is this dotted with the point, & the larger projection axis scale
+pub fn get_camera_terms(view: &Mat4, projection: &Mat4) -> (Vec4, f32) {
+    let view_projection = *projection * *view;
+    let cols = &view_projection.cols;
+    let w_row = Vec4::new(cols[0].w, cols[1].w, cols[2].w, cols[3].w);
+
+    let projection_scale = projection.cols[0].x.abs().max(projection.cols[1].y.abs());
+
+    (w_row, projection_scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // just under a boundary, bigger sizes are closer to the camera
+    fn below(size: f32) -> f32 {
+        size * (1.0 - 1e-4)
+    }
+
+    #[test]
+    fn full_detail_at_or_above_screen_size() {
+        assert_eq!(select_lod(LOD_SCREEN_SIZE, MAX_LODS), 0);
+        assert_eq!(select_lod(1.0, MAX_LODS), 0);
+        assert_eq!(select_lod(100.0, MAX_LODS), 0);
+    }
+
+    #[test]
+    fn level_changes_each_time_size_halves() {
+        for level in 1..MAX_LODS {
+            let boundary = LOD_SCREEN_SIZE / (1 << (level - 1)) as f32;
+            assert_eq!(select_lod(below(boundary), MAX_LODS), level);
+
+            // the next boundary down still uses this level
+            assert_eq!(select_lod(boundary / 2.0, MAX_LODS), level);
+        }
+    }
+
+    #[test]
+    fn small_sizes_clamp_to_coarsest_level() {
+        assert_eq!(select_lod(1e-6, MAX_LODS), MAX_LODS - 1);
+        assert_eq!(select_lod(f32::MIN_POSITIVE, MAX_LODS), MAX_LODS - 1);
+        assert_eq!(select_lod(1e-6, 2), 1);
+    }
+
+    #[test]
+    fn behind_camera_uses_coarsest_level() {
+        assert_eq!(select_lod(0.0, MAX_LODS), MAX_LODS - 1);
+        assert_eq!(select_lod(-1.0, MAX_LODS), MAX_LODS - 1);
+    }
+
+    #[test]
+    fn single_level_is_always_selected() {
+        for &size in &[
+            -1.0,
+            0.0,
+            1e-6,
+            below(LOD_SCREEN_SIZE),
+            LOD_SCREEN_SIZE,
+            10.0,
+        ] {
+            assert_eq!(select_lod(size, 1), 0);
+        }
+    }
+
+    #[test]
+    fn screen_size_shrinks_with_distance() {
+        let bounds = Bounds::from_points(
+            [Vec3::broadcast(-1.0), Vec3::broadcast(1.0)]
+                .iter()
+                .copied(),
+        );
+        // w is the distance along -z like a perspective projection
+        let w_row = Vec4::new(0.0, 0.0, -1.0, 0.0);
+
+        let size_at = |distance: f32| {
+            let world = Mat4::from_translation(Vec3::new(0.0, 0.0, -distance));
+            get_screen_size(&bounds, &world, w_row, 1.0)
+        };
+
+        assert!((size_at(10.0) - bounds.radius / 10.0).abs() < 1e-5);
+        assert!((size_at(20.0) * 2.0 - size_at(10.0)).abs() < 1e-5);
+        assert_eq!(
+            select_lod(size_at(20.0), MAX_LODS),
+            select_lod(size_at(10.0), MAX_LODS) + 1
+        );
+    }
+}
@@ -0,0 +1,322 @@
+use crate::application::handle::{self, Owned};
+use crate::application::upload::Uploader;
+use crate::application::{buffer, texture};
+
+use erupt::{vk, DeviceLoader, InstanceLoader};
+
+use ultraviolet::vec::Vec4;
+
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// materials are descriptor set 1, set 0 is per frame data
+pub const MATERIAL_SET: u32 = 1;
+
+// upper bound on materials allocated from the material descriptor pool
+pub const MAX_MATERIALS: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureSlot {
+    BaseColor,
+    // g roughness, b metallic (same as gltf)
+    MetallicRoughness,
+    Normal,
+    Occlusion,
+    Emissive,
+}
+
+pub const TEXTURE_SLOTS: [TextureSlot; 5] = [
+    TextureSlot::BaseColor,
+    TextureSlot::MetallicRoughness,
+    TextureSlot::Normal,
+    TextureSlot::Occlusion,
+    TextureSlot::Emissive,
+];
+
+impl TextureSlot {
+    fn index(self) -> usize {
+        TEXTURE_SLOTS
+            .iter()
+            .position(|&slot| slot == self)
+            .expect("Failed to find texture slot!")
+    }
+
+    // binding 0 is factors, 1 is the sampler
+    pub fn binding(self) -> u32 {
+        2 + self.index() as u32
+    }
+
+    // color textures are authored in srgb, the rest is linear data
+    pub fn format(self) -> vk::Format {
+        match self {
+            TextureSlot::BaseColor | TextureSlot::Emissive => vk::Format::R8G8B8A8_SRGB,
+            _ => vk::Format::R8G8B8A8_UNORM,
+        }
+    }
+
+    // 1x1 texture used when a material has no texture for the slot, chosen so that
+    // only the factors have an effect
+    fn fallback_pixel(self) -> [u8; 4] {
+        match self {
+            // flat tangent space normal
+            TextureSlot::Normal => [128, 128, 255, 255],
+            _ => [255, 255, 255, 255],
+        }
+    }
+
+    // file looked for by Material::from_directory
+    fn file_name(self) -> &'static str {
+        match self {
+            TextureSlot::BaseColor => "base_color.png",
+            TextureSlot::MetallicRoughness => "metallic_roughness.png",
+            TextureSlot::Normal => "normal.png",
+            TextureSlot::Occlusion => "occlusion.png",
+            TextureSlot::Emissive => "emissive.png",
+        }
+    }
+}
+
+// std140 layout of the material uniform block (set 1, binding 0)
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct MaterialFactors {
+    pub base_color: Vec4,
+    // rgb emissive, a unused
+    pub emissive: Vec4,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+}
+
+impl Default for MaterialFactors {
+    fn default() -> Self {
+        MaterialFactors {
+            base_color: Vec4::one(),
+            emissive: Vec4::zero(),
+            metallic: 0.0,
+            roughness: 0.5,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+        }
+    }
+}
+
+// host side description of a metallic-roughness material
+#[derive(Default)]
+pub struct Material {
+    pub factors: MaterialFactors,
+    // indexed by TextureSlot, None uses the fallback texture
+    pub textures: [Option<PathBuf>; 5],
+}
+
+impl Material {
+    // picks up any of the TextureSlot file names that exist in directory
+    pub fn from_directory(directory: &Path) -> Material {
+        let mut material = Material::default();
+
+        for &slot in TEXTURE_SLOTS.iter() {
+            let path = directory.join(slot.file_name());
+            if path.exists() {
+                material.textures[slot.index()] = Some(path);
+            }
+        }
+
+        // a metallic-roughness texture is scaled by the factors
+        if material.textures[TextureSlot::MetallicRoughness.index()].is_some() {
+            material.factors.metallic = 1.0;
+            material.factors.roughness = 1.0;
+        }
+
+        // as is emissive
+        if material.textures[TextureSlot::Emissive.index()].is_some() {
+            material.factors.emissive = Vec4::one();
+        }
+
+        material
+    }
+}
+
+// gpu resources for a material, retired together through the deletion queue when replaced
+pub struct GpuMaterial {
+    // only read through the descriptor set
+    _textures: Vec<texture::Texture>,
+    _uniform_buffer: buffer::OwnedBuffer,
+    pub descriptor_set: handle::OwnedDescriptorSet,
+}
+
+pub fn create_material_set_layout(device: &DeviceLoader) -> vk::DescriptorSetLayout {
+    let mut bindings = vec![
+        // factors
+        vk::DescriptorSetLayoutBindingBuilder::new()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        // sampler shared by all material textures
+        vk::DescriptorSetLayoutBindingBuilder::new()
+            .binding(1)
+            .descriptor_type(vk::DescriptorType::SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+    ];
+
+    bindings.extend(TEXTURE_SLOTS.iter().map(|slot| {
+        vk::DescriptorSetLayoutBindingBuilder::new()
+            .binding(slot.binding())
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+    }));
+
+    let create_info = vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&bindings);
+
+    unsafe { device.create_descriptor_set_layout(&create_info, None, None) }
+        .expect("Failed to create material descriptor set layout!")
+}
+
+pub fn create_material_descriptor_pool(device: &DeviceLoader) -> vk::DescriptorPool {
+    let pool_sizes = &[
+        vk::DescriptorPoolSizeBuilder::new()
+            ._type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(MAX_MATERIALS),
+        vk::DescriptorPoolSizeBuilder::new()
+            ._type(vk::DescriptorType::SAMPLER)
+            .descriptor_count(MAX_MATERIALS),
+        vk::DescriptorPoolSizeBuilder::new()
+            ._type(vk::DescriptorType::SAMPLED_IMAGE)
+            .descriptor_count(MAX_MATERIALS * TEXTURE_SLOTS.len() as u32),
+    ];
+
+    // replaced materials give their sets back
+    let pool_info = vk::DescriptorPoolCreateInfoBuilder::new()
+        .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+        .pool_sizes(pool_sizes)
+        .max_sets(MAX_MATERIALS);
+
+    unsafe { device.create_descriptor_pool(&pool_info, None, None) }
+        .expect("Failed to create material descriptor pool!")
+}
+
+// uploads textures & factors and writes the material's descriptor set
+#[allow(clippy::too_many_arguments)]
+pub fn upload_material(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    device: &Rc<handle::Device>,
+    uploader: &mut Uploader,
+    pool: &Rc<Owned<vk::DescriptorPool>>,
+    layout: &vk::DescriptorSetLayout,
+    sampler: vk::Sampler,
+    material: &Material,
+) -> GpuMaterial {
+    let textures: Vec<_> = TEXTURE_SLOTS
+        .iter()
+        .map(|&slot| {
+            let name = format!("{:?} texture", slot);
+            let loaded = material.textures[slot.index()].as_ref().and_then(|path| {
+                texture::load_texture(
+                    instance,
+                    physical_device,
+                    device,
+                    uploader,
+                    path,
+                    slot.format(),
+                    true,
+                    &name,
+                )
+                .map_err(|e| eprintln!("Failed to load {:?} texture {:?}: {}", slot, path, e))
+                .ok()
+            });
+
+            // missing or failed to load
+            loaded.unwrap_or_else(|| {
+                texture::create_texture(
+                    instance,
+                    physical_device,
+                    device,
+                    uploader,
+                    vk::Extent2D {
+                        width: 1,
+                        height: 1,
+                    },
+                    slot.format(),
+                    &slot.fallback_pixel(),
+                    true,
+                    &name,
+                )
+            })
+        })
+        .collect();
+
+    // factors never change so only need the one buffer
+    let (uniform_buffer, uniform_buffer_memory) = buffer::create_buffer(
+        instance,
+        physical_device,
+        device,
+        size_of::<MaterialFactors>() as u64,
+        vk::BufferUsageFlags::UNIFORM_BUFFER,
+        vk::SharingMode::EXCLUSIVE,
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        "material factors",
+    );
+    buffer::update_uniform_buffer(device, &material.factors, &uniform_buffer_memory);
+
+    let layouts = &[*layout];
+    let alloc_info = vk::DescriptorSetAllocateInfoBuilder::new()
+        .set_layouts(layouts)
+        .descriptor_pool(***pool);
+
+    let descriptor_set = unsafe { device.allocate_descriptor_sets(&alloc_info) }
+        .expect("Failed to allocate material descriptor set!")[0];
+
+    let buffer_info = &[vk::DescriptorBufferInfoBuilder::new()
+        .buffer(*uniform_buffer)
+        .range(vk::WHOLE_SIZE)];
+    let sampler_info = &[vk::DescriptorImageInfoBuilder::new().sampler(sampler)];
+    let image_infos: Vec<_> = textures
+        .iter()
+        .map(|texture| {
+            [vk::DescriptorImageInfoBuilder::new()
+                .image_view(*texture.view)
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)]
+        })
+        .collect();
+
+    let mut descriptor_writes = vec![
+        vk::WriteDescriptorSetBuilder::new()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .buffer_info(buffer_info),
+        vk::WriteDescriptorSetBuilder::new()
+            .dst_set(descriptor_set)
+            .dst_binding(1)
+            .descriptor_type(vk::DescriptorType::SAMPLER)
+            .image_info(sampler_info),
+    ];
+
+    descriptor_writes.extend(TEXTURE_SLOTS.iter().zip(image_infos.iter()).map(
+        |(slot, image_info)| {
+            vk::WriteDescriptorSetBuilder::new()
+                .dst_set(descriptor_set)
+                .dst_binding(slot.binding())
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .image_info(image_info)
+        },
+    ));
+
+    unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+    GpuMaterial {
+        _textures: textures,
+        _uniform_buffer: (uniform_buffer, uniform_buffer_memory),
+        descriptor_set: handle::OwnedDescriptorSet::new(
+            device,
+            descriptor_set,
+            pool,
+            "material descriptor set",
+        ),
+    }
+}
@@ -19,15 +19,92 @@ mod buffer;
 // state
 mod state;
 
+// pipeline cache
+mod cache;
+
+// debug view modes
+mod view;
+
+// debug utils object names & command labels
+mod debug;
+
+// settings file & cmd argument overrides
+mod config;
+
+// validation message logging
+mod validation;
+
+// lights
+mod lighting;
+
+// images & samplers
+mod texture;
+
+// pbr materials
+mod material;
+
+// image based lighting
+mod environment;
+
+// shadow maps
+mod shadow;
+
+// render graph
+mod graph;
+
+// post processing
+mod post;
+
+// scene graph
+mod scene;
+
+// bounding volumes & frustum culling
+mod culling;
+
+// frame timing & counters
+mod stats;
+
+// per frame in flight resources
+mod frame;
+
+// cpu-gpu frame pacing, timeline semaphores or fences
+mod sync;
+
+// owned vulkan handles & leak reporting
+mod handle;
+
+// objects destroyed once the gpu is done with them
+mod deletion;
+
+// one time transfer & compute work before the first frame
+mod upload;
+
+// gpu culling & indirect draws
+mod indirect;
+
+// mesh level of detail
+mod lod;
+
+// persistently mapped per frame uniform blocks
+mod uniform;
+
+use crate::application::handle::Owned;
 use crate::application::setup::LAYER_KHRONOS_VALIDATION;
+use crate::application::view::ViewMode;
 use std::time::Instant;
+use ultraviolet::rotor::Rotor3;
+use ultraviolet::vec::{Vec3, Vec4};
 use winit::dpi::PhysicalSize;
 
 use erupt::vk;
-use erupt::vk::{Image, ImageView, SurfaceCapabilitiesKHR, SurfaceKHR, SwapchainKHR};
-use erupt::{utils::surface, DefaultEntryLoader, DeviceLoader, InstanceLoader};
+use erupt::vk::{Image, SurfaceCapabilitiesKHR};
+use erupt::{utils::surface, DefaultEntryLoader};
 
 use std::ffi::CStr;
+use std::mem;
+use std::path::PathBuf;
+use std::process;
+use std::rc::Rc;
 
 use winit::{
     event::{
@@ -39,77 +116,251 @@ use winit::{
 
 use structopt::StructOpt;
 
-// struct for cmd arguments
+// struct for cmd arguments, each overrides the config file, flags have a --no-* counterpart to
+// turn off what the file turns on & lists replace the file's
 #[derive(Debug, StructOpt)]
 pub struct Opt {
-    #[structopt(short, long)]
+    // toml config file, config.toml in the working directory is used if it exists
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    #[structopt(short, long, conflicts_with = "no-validation")]
     validation: bool,
+
+    #[structopt(long)]
+    no_validation: bool,
+
+    // panic on the first validation error, implies --validation
+    #[structopt(long, conflicts_with = "no-validation-fatal")]
+    validation_fatal: bool,
+
+    #[structopt(long)]
+    no_validation_fatal: bool,
+
+    // sync, gpu-assisted or best-practices, can be given more than once, implies --validation
+    #[structopt(long, number_of_values = 1)]
+    validation_features: Vec<validation::ValidationFeature>,
+
+    // validation message id names or numbers to ignore, can be given more than once
+    #[structopt(long, number_of_values = 1)]
+    suppress_validation: Vec<String>,
+
+    // part of the name of the physical device to use
+    #[structopt(long)]
+    device: Option<String>,
+
+    // fifo, mailbox or immediate
+    #[structopt(long)]
+    present_mode: Option<presentation::PresentMode>,
+
+    // 1 to 4
+    #[structopt(long)]
+    frames_in_flight: Option<usize>,
+
+    // samples per pixel, 1 disables
+    #[structopt(long)]
+    msaa: Option<u32>,
+
+    // instancing demo, draws a grid of this many quads
+    #[structopt(long)]
+    instances: Option<usize>,
+
+    // directory with material textures (base_color.png, normal.png etc.)
+    #[structopt(long, parse(from_os_str))]
+    material: Option<PathBuf>,
+
+    // equirectangular .hdr environment map, a procedural sky is used otherwise
+    #[structopt(long, parse(from_os_str))]
+    environment: Option<PathBuf>,
+
+    // aces, reinhard or clamp
+    #[structopt(long)]
+    tonemapper: Option<post::Tonemapper>,
+
+    #[structopt(long)]
+    exposure: Option<f32>,
+
+    #[structopt(long)]
+    gamma: Option<f32>,
+
+    #[structopt(long, conflicts_with = "no-bloom")]
+    bloom: bool,
+
+    #[structopt(long)]
+    no_bloom: bool,
+
+    #[structopt(long, conflicts_with = "no-fxaa")]
+    fxaa: bool,
+
+    #[structopt(long)]
+    no_fxaa: bool,
+
+    // color grading lut png, size * size wide & size high
+    #[structopt(long, parse(from_os_str))]
+    color_lut: Option<PathBuf>,
+
+    // sdr, hdr10 or scrgb, hdr falls back to sdr if the display doesn't support it
+    #[structopt(long)]
+    output: Option<presentation::OutputMode>,
+
+    // hdr output brightness of scene white in nits
+    #[structopt(long)]
+    paper_white: Option<f32>,
+
+    // hdr output peak brightness in nits
+    #[structopt(long)]
+    max_luminance: Option<f32>,
+
+    // print fps & culling counters once a second
+    #[structopt(long, conflicts_with = "no-stats")]
+    stats: bool,
+
+    #[structopt(long)]
+    no_stats: bool,
+
+    // cull on the gpu & draw with one indirect call per material
+    #[structopt(long, conflicts_with = "no-gpu-culling")]
+    gpu_culling: bool,
+
+    #[structopt(long)]
+    no_gpu_culling: bool,
+
+    // pace frames with a timeline semaphore when the device supports them
+    #[structopt(long, conflicts_with = "no-timeline-semaphores")]
+    timeline_semaphores: bool,
+
+    // pace frames with fences even if timeline semaphores are supported
+    #[structopt(long)]
+    no_timeline_semaphores: bool,
 }
 
 // Application struct
 pub struct Application {
     event_loop: Option<winit::event_loop::EventLoop<()>>,
-    window: Window,
-    messenger: vk::DebugUtilsMessengerEXT,
-    surface: SurfaceKHR,
     physical_device: vk::PhysicalDevice,
+    physical_device_properties: vk::PhysicalDeviceProperties,
+    device_features: vk::PhysicalDeviceFeatures,
     surface_format: vk::SurfaceFormatKHR,
     present_mode: vk::PresentModeKHR,
-    device: DeviceLoader,
+    // VK_EXT_hdr_metadata is enabled
+    hdr_metadata: bool,
     queue: vk::Queue,
-    swapchain: SwapchainKHR,
+    // uploads after startup allocate their command pools from it
+    queue_family: u32,
+    // views of the swapchain images are dropped before the swapchain
+    swapchain_image_views: Vec<Owned<vk::ImageView>>,
+    swapchain: Owned<vk::SwapchainKHR>,
     swapchain_images: Vec<Image>,
     surface_capabilities: SurfaceCapabilitiesKHR,
-    swapchain_image_views: Vec<ImageView>,
-    shader_vert: vk::ShaderModule,
-    shader_frag: vk::ShaderModule,
-    render_pass: vk::RenderPass,
-    descriptor_set_layout: vk::DescriptorSetLayout,
-    pipeline_layout: vk::PipelineLayout,
-    pipeline: vk::Pipeline,
-    framebuffers: Vec<vk::Framebuffer>,
-    command_pool: vk::CommandPool,
-
-    // buffers
-    vertex_buffer: vk::Buffer,
-    vertex_buffer_memory: vk::DeviceMemory,
-    index_buffer: vk::Buffer,
-    index_buffer_memory: vk::DeviceMemory,
-    uniform_buffer: Vec<vk::Buffer>,
-    uniform_buffer_memory: Vec<vk::DeviceMemory>,
-    command_buffers: Vec<vk::CommandBuffer>,
-
-    descriptor_pool: vk::DescriptorPool,
-    descriptor_sets: Vec<vk::DescriptorSet>,
-
-    // semaphores and fences
-    image_available_semaphores: Vec<vk::Semaphore>,
-    render_finished_semaphores: Vec<vk::Semaphore>,
-    in_flight_fences: Vec<vk::Fence>,
-    images_in_flight: Vec<vk::Fence>,
+    shader_vert: Owned<vk::ShaderModule>,
+    shader_frag: Owned<vk::ShaderModule>,
+    shader_wire_frag: Owned<vk::ShaderModule>,
+    shader_skybox_vert: Owned<vk::ShaderModule>,
+    shader_skybox_frag: Owned<vk::ShaderModule>,
+    render_pass: Owned<vk::RenderPass>,
+    depth_format: vk::Format,
+    msaa_samples: vk::SampleCountFlagBits,
+    descriptor_set_layout: Owned<vk::DescriptorSetLayout>,
+    pipeline_layout: Owned<vk::PipelineLayout>,
+    pipelines: pipeline::Pipelines,
+    pipeline_cache: Owned<vk::PipelineCache>,
+    // lit pass, hdr color & depth
+    framebuffer: Owned<vk::Framebuffer>,
+
+    // buffers, fields starting with _ are only held to be destroyed with the application
+    vertex_buffer: Owned<vk::Buffer>,
+    _vertex_buffer_memory: Owned<vk::DeviceMemory>,
+    index_buffer: Owned<vk::Buffer>,
+    _index_buffer_memory: Owned<vk::DeviceMemory>,
+    // ranges of the vertex & index buffers, indexed by scene nodes
+    meshes: Vec<model::MeshRange>,
+    // mesh bounds grown to cover every instance, what culling tests
+    mesh_bounds: Vec<culling::Bounds>,
+    instance_capacity: usize,
+
+    // frame descriptor sets are allocated from it
+    _descriptor_pool: Owned<vk::DescriptorPool>,
+
+    // materials
+    sampler: Owned<vk::Sampler>,
+    material_set_layout: Owned<vk::DescriptorSetLayout>,
+    // held on to by every material's descriptor set
+    material_descriptor_pool: Rc<Owned<vk::DescriptorPool>>,
+    materials: Vec<material::GpuMaterial>,
+    // reloaded from with the R key, the default material if None
+    material_directory: Option<PathBuf>,
+
+    // image based lighting
+    environment_set_layout: Owned<vk::DescriptorSetLayout>,
+    environment: environment::Environment,
+
+    // depth only passes rendered before the lit pass
+    shadow_maps: shadow::ShadowMaps,
+
+    // pass order, transient attachments & barriers
+    render_graph: graph::RenderGraph,
+
+    // fullscreen passes from the hdr color to the swapchain
+    post: post::PostProcess,
+    post_targets: post::PostTargets,
+
+    // indexed by current_frame
+    frames: Vec<frame::FrameContext>,
+    // camera, light, shadow & object blocks of every frame in flight
+    uniforms: uniform::UniformRing,
+    frame_sync: sync::FrameSync,
+    // number of the frame last rendering to each swapchain image
+    images_in_flight: Vec<u64>,
+    // replaced or unloaded objects waiting on the frames using them
+    deletion_queue: deletion::DeletionQueue,
 
     // state
     start: Instant,
     current_frame: usize,
     resized: bool,
+    view_mode: ViewMode,
+    clear_color: [f32; 4],
     ubo: model::UniformBufferObject,
-
-    // instance loader is at bottom due to drop order
-    instance: InstanceLoader,
+    scene: scene::Scene,
+    // spinning mesh, the cube attached to it, the pivot orbiting the smaller cube around it & the
+    // pulsing sphere
+    demo_nodes: [scene::NodeId; 4],
+    stats: stats::FrameStats,
+    // None when culling on the cpu
+    indirect: Option<indirect::IndirectDraws>,
+    instances: Vec<model::InstanceData>,
+    lights: lighting::Lights,
+    // None while toggled off
+    demo_lights: Option<DemoLights>,
+
+    // dropped after every owned handle above, any still alive then are reported as leaked, the
+    // device holds on to the instance & its messenger
+    device: Rc<handle::Device>,
+    // outlives every swapchain created from it & is destroyed before its window
+    surface: handle::Surface,
+    window: Window,
+    instance: Rc<handle::Instance>,
 }
 
-// Main and only impl block
+// Main impl block
 impl Application {
     pub fn new(entry: &DefaultEntryLoader) -> Application {
-        // cmd arguments
-        let opt = Opt::from_args();
+        // config file & cmd arguments
+        let config = match config::load(Opt::from_args()) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
 
         // from winit
         let event_loop = EventLoop::new();
 
         // resizable false for now
         let window = match WindowBuilder::new()
-            .with_title("WIP")
+            .with_title(&config.window.title)
+            .with_inner_size(PhysicalSize::new(config.window.width, config.window.height))
             .with_resizable(false)
             .build(&event_loop)
         {
@@ -117,15 +368,21 @@ impl Application {
             Err(e) => panic!("Le Window creation failed! {:?}", e),
         };
 
-        let mut instance = setup::create_instance(&window, &entry);
+        let instance = setup::create_instance(&window, entry, &config);
 
-        let messenger = setup::setup_debug_messenger(&instance);
+        let validation = validation::Validation::new(
+            config.debug.suppress_validation.clone(),
+            config.debug.validation_fatal,
+        );
+        let messenger = setup::setup_debug_messenger(&instance, &validation, &config);
+        let instance = handle::Instance::new(instance, messenger, validation);
 
-        let surface = unsafe { surface::create_surface(&mut instance, &window, None) }
+        let surface = unsafe { surface::create_surface(&instance, &window, None) }
             .expect("Failed to create surface!");
+        let surface = handle::Surface::new(&instance, surface);
 
         // needed extension for presention
-        let device_extensions = vec![vk::KHR_SWAPCHAIN_EXTENSION_NAME];
+        let mut device_extensions = vec![vk::KHR_SWAPCHAIN_EXTENSION_NAME];
 
         // get physical device & queue family
         let (
@@ -134,74 +391,243 @@ impl Application {
             surface_format,
             present_mode,
             physical_device_properties,
-        ) = setup::pick_physical_device_and_queue_family(&instance, &surface, &device_extensions);
+        ) = setup::pick_physical_device_and_queue_family(
+            &instance,
+            &surface,
+            &device_extensions,
+            config.post.output,
+            &config.device,
+        );
+
+        // hdr modes the display doesn't support fall back to whatever format was picked
+        let output = presentation::OutputMode::from_color_space(surface_format.color_space);
+        if output != config.post.output {
+            println!(
+                "{:?} output not supported, falling back to {:?}.",
+                config.post.output, output
+            );
+        }
+
+        if present_mode != config.device.present_mode.get_present_mode() {
+            println!(
+                "{:?} present mode not supported, falling back to Fifo.",
+                config.device.present_mode
+            );
+        }
+
+        // tells the display the luminance range of hdr output
+        let hdr_metadata = output.is_hdr()
+            && setup::supports_device_extension(
+                &instance,
+                physical_device,
+                vk::EXT_HDR_METADATA_EXTENSION_NAME,
+            );
+        if hdr_metadata {
+            device_extensions.push(vk::EXT_HDR_METADATA_EXTENSION_NAME);
+        }
+
+        // lets the gpu culling pass set how many draws are made
+        let draw_indirect_count = config.device.gpu_culling
+            && setup::supports_device_extension(
+                &instance,
+                physical_device,
+                vk::KHR_DRAW_INDIRECT_COUNT_EXTENSION_NAME,
+            );
+        if draw_indirect_count {
+            device_extensions.push(vk::KHR_DRAW_INDIRECT_COUNT_EXTENSION_NAME);
+        }
 
         // get device layers (pretty much just validation)
         let mut device_layers = Vec::new();
-        if opt.validation {
+        if config.debug.is_validation_enabled() {
             device_layers.push(LAYER_KHRONOS_VALIDATION);
         }
 
+        // optional features supported by the physical device
+        let device_features = setup::get_device_features(&instance, physical_device, &config);
+
+        // frames are paced with one timeline semaphore instead of a fence each on Vulkan 1.2
+        let timeline_semaphores = config.device.timeline_semaphores
+            && setup::supports_timeline_semaphores(
+                &instance,
+                physical_device,
+                &physical_device_properties,
+            );
+
         // get queue & logical device
         let (device, queue) = setup::get_logical_device_and_queue(
             &instance,
             physical_device,
             &device_extensions,
             &device_layers,
+            &device_features,
+            timeline_semaphores,
             queue_family,
         );
+        let device = handle::Device::new(device, &instance);
+
+        debug::set_object_name(&device, queue, "graphics queue");
 
-        println!("Using physical device - {:?}", unsafe {
-            CStr::from_ptr(physical_device_properties.device_name.as_ptr())
-        });
+        let device_name =
+            unsafe { CStr::from_ptr(physical_device_properties.device_name.as_ptr()) };
+        println!("Using physical device - {:?}", device_name);
+
+        if let Some(name) = &config.device.name {
+            let device_name = device_name.to_string_lossy().to_lowercase();
+            if !device_name.contains(&name.to_lowercase()) {
+                println!("No physical device matching {:?} found.", name);
+            }
+        }
 
         // create swapchain and get image references
         let (swapchain, swapchain_images, surface_capabilities) =
             presentation::create_swapchain_and_images(
                 &instance,
                 &physical_device,
-                surface,
+                *surface,
                 surface_format,
                 present_mode,
                 &device,
+                None,
             );
 
         // get swapchain image views
         let swapchain_image_views =
             presentation::get_image_views(&swapchain_images, &device, surface_format);
 
-        // create descriptor set layout
-        let descriptor_set_layout = pipeline::create_descriptor_set_layout(&device);
+        // create descriptor set layouts
+        let descriptor_set_layout = Owned::new(
+            &device,
+            pipeline::create_descriptor_set_layout(&device),
+            "frame set layout",
+        );
+        let material_set_layout = Owned::new(
+            &device,
+            material::create_material_set_layout(&device),
+            "material set layout",
+        );
+        let environment_set_layout = Owned::new(
+            &device,
+            environment::create_environment_set_layout(&device),
+            "environment set layout",
+        );
 
         let (shader_vert, shader_frag) = pipeline::create_shader_modules(&device);
+        let shader_vert = Owned::new(&device, shader_vert, "scene vertex shader");
+        let shader_frag = Owned::new(&device, shader_frag, "scene fragment shader");
+        let shader_wire_frag = Owned::new(
+            &device,
+            pipeline::create_shader_module(&device, pipeline::SHADER_WIRE_FRAG),
+            "wire fragment shader",
+        );
+        let shader_skybox_vert = Owned::new(
+            &device,
+            pipeline::create_shader_module(&device, pipeline::SHADER_SKYBOX_VERT),
+            "skybox vertex shader",
+        );
+        let shader_skybox_frag = Owned::new(
+            &device,
+            pipeline::create_shader_module(&device, pipeline::SHADER_SKYBOX_FRAG),
+            "skybox fragment shader",
+        );
+
+        // load pipeline cache from previous runs
+        let pipeline_cache = Owned::new(
+            &device,
+            cache::create_pipeline_cache(&device, &physical_device_properties),
+            "pipeline cache",
+        );
+
+        let depth_format = texture::find_depth_format(&instance, &physical_device);
+
+        // lit pass samples, resolved into the hdr color before post processing
+        let msaa_samples =
+            pipeline::get_msaa_samples(&physical_device_properties.limits, config.render.msaa);
+        if msaa_samples.0 != config.render.msaa {
+            println!(
+                "{}x MSAA not supported, falling back to {}x.",
+                config.render.msaa, msaa_samples.0
+            );
+        }
 
         // graphics pipeline & render pass
-        let (pipeline, pipeline_layout, render_pass) = pipeline::create_graphics_pipeline(
+        let (pipelines, pipeline_layout, render_pass) = pipeline::create_graphics_pipeline(
             &device,
-            shader_vert,
-            shader_frag,
-            &descriptor_set_layout,
-            surface_format,
+            *pipeline_cache,
+            *shader_vert,
+            *shader_frag,
+            *shader_wire_frag,
+            *shader_skybox_vert,
+            *shader_skybox_frag,
+            &[
+                *descriptor_set_layout,
+                *material_set_layout,
+                *environment_set_layout,
+            ],
+            post::HDR_FORMAT,
+            depth_format,
+            msaa_samples,
+            &device_features,
         );
 
-        // create framebuffers
-        let framebuffers = render::create_framebuffers(
+        let mut frame_sync = sync::FrameSync::new(&device, timeline_semaphores);
+        match frame_sync.is_timeline() {
+            true => println!("Frame sync - timeline semaphore"),
+            false => println!("Frame sync - fences"),
+        }
+
+        let mut deletion_queue = deletion::DeletionQueue::new();
+
+        // textures, buffers & the environment are uploaded & generated without waiting, the
+        // first frame waits on them
+        let mut uploader =
+            upload::Uploader::new(queue, queue_family, &mut frame_sync, &mut deletion_queue);
+
+        // tonemapping, bloom, fxaa & color grading
+        let post = post::create_post_process(
+            &instance,
+            &physical_device,
             &device,
-            &swapchain_image_views,
-            &render_pass,
-            &surface_capabilities,
+            &mut uploader,
+            *pipeline_cache,
+            surface_format.format,
+            post::PostSettings {
+                tonemapper: config.post.tonemapper,
+                exposure: config.post.exposure,
+                gamma: config.post.gamma,
+                bloom: config.post.bloom,
+                fxaa: config.post.fxaa,
+                color_lut: config.assets.color_lut.clone(),
+                output,
+                paper_white: config.post.paper_white,
+                max_luminance: config.post.max_luminance,
+            },
         );
 
-        // create command pool
-        let command_pool = render::create_command_pool(&device, queue_family);
+        if hdr_metadata {
+            presentation::set_hdr_metadata(
+                &device,
+                *swapchain,
+                output,
+                config.post.paper_white,
+                config.post.max_luminance,
+            );
+        }
+
+        // all meshes share one vertex & one index buffer
+        let (vertices, indices, meshes) = model::merge_meshes(&[
+            model::Mesh::quad(),
+            model::Mesh::cube(),
+            model::Mesh::sphere(32, 16),
+        ]);
 
         // create vertex buffer
         let (vertex_buffer, vertex_buffer_memory) = buffer::create_vertex_buffer(
             &instance,
             &device,
             &physical_device,
-            &command_pool,
-            &queue,
+            &mut uploader,
+            &vertices,
         );
 
         // create index buffer
@@ -209,150 +635,302 @@ impl Application {
             &instance,
             &device,
             &physical_device,
-            &command_pool,
-            &queue,
+            &mut uploader,
+            &indices,
         );
 
-        // create uniform buffers
-        let (uniform_buffer, uniform_buffer_memory) = buffer::create_uniform_buffer(
+        // material textures are sampled through a single sampler
+        let sampler = Owned::new(
+            &device,
+            texture::create_sampler(
+                &device,
+                &device_features,
+                &physical_device_properties.limits,
+            ),
+            "material sampler",
+        );
+        let material_descriptor_pool = Rc::new(Owned::new(
+            &device,
+            material::create_material_descriptor_pool(&device),
+            "material descriptor pool",
+        ));
+
+        // default material unless a texture directory is given
+        let material_description = match &config.assets.material {
+            Some(directory) => material::Material::from_directory(directory),
+            None => material::Material::default(),
+        };
+        let materials = vec![material::upload_material(
             &instance,
             &physical_device,
             &device,
-            swapchain_images.len(),
+            &mut uploader,
+            &material_descriptor_pool,
+            &material_set_layout,
+            *sampler,
+            &material_description,
+        )];
+
+        // precomputed on the gpu before the first frame
+        let environment = environment::create_environment(
+            &instance,
+            &physical_device,
+            &device,
+            &mut uploader,
+            *pipeline_cache,
+            &environment_set_layout,
+            config.assets.environment.as_deref(),
         );
 
-        // create descriptor pool
-        let descriptor_pool =
-            pipeline::create_descriptor_pool(&device, swapchain_images.len() as u32);
+        // layered depth image, one layer per cascade & spot light
+        let shadow_maps = shadow::create_shadow_maps(
+            &instance,
+            &physical_device,
+            &device,
+            &mut uploader,
+            *pipeline_cache,
+            *descriptor_set_layout,
+        );
 
-        // create descriptor sets
-        let descriptor_sets = pipeline::create_descriptor_sets(
+        // no previous graph to retire
+        let (render_graph, graph_images) = render::create_render_graph(
+            &instance,
+            &physical_device,
             &device,
-            &descriptor_set_layout,
-            &descriptor_pool,
-            &uniform_buffer,
-            swapchain_images.len(),
+            depth_format,
+            surface_capabilities.current_extent,
+            ViewMode::default(),
+            &post.settings,
+            msaa_samples,
+            None,
+            &mut deletion_queue,
+            0,
         );
 
-        // allocate command buffers
-        let command_buffers =
-            render::allocate_command_buffers(&device, &command_pool, &framebuffers);
+        // create framebuffers
+        let framebuffer = Owned::new(
+            &device,
+            render::create_framebuffer(
+                &device,
+                *render_pass,
+                &graph_images.get_lit_attachments(&render_graph),
+                surface_capabilities.current_extent,
+            ),
+            "scene framebuffer",
+        );
+        let post_targets = post::create_post_targets(
+            &device,
+            &post,
+            &render_graph,
+            graph_images.hdr,
+            &graph_images.post,
+            &swapchain_image_views,
+            surface_capabilities.current_extent,
+        );
 
-        // record command buffers
-        render::record_command_buffers(
+        // single untransformed instance, replaced if running the instancing demo
+        let instances = vec![model::InstanceData::default()];
+        let mesh_bounds = culling::get_instanced_bounds(&meshes, &instances);
+
+        // each object's culled instances are drawn from its own offset in the instance buffer
+        let indirect = match config.device.gpu_culling {
+            true if device_features.draw_indirect_first_instance == vk::TRUE => {
+                let multi_draw = device_features.multi_draw_indirect == vk::TRUE;
+                Some(indirect::create_indirect_draws(
+                    &instance,
+                    &physical_device,
+                    &device,
+                    *pipeline_cache,
+                    draw_indirect_count && multi_draw,
+                    multi_draw,
+                    config.render.frames_in_flight,
+                ))
+            }
+            true => {
+                println!("GPU culling unsupported (no drawIndirectFirstInstance)!");
+                None
+            }
+            false => None,
+        };
+
+        // one descriptor set per frame in flight
+        let descriptor_pool = Owned::new(
             &device,
-            &pipeline,
-            &command_buffers,
-            &framebuffers,
-            &descriptor_sets,
-            &pipeline_layout,
-            &render_pass,
-            &surface_capabilities,
-            &vertex_buffer,
-            &index_buffer,
+            pipeline::create_descriptor_pool(&device, config.render.frames_in_flight as u32),
+            "frame descriptor pool",
         );
 
-        // create semaphores & fences
-        let (
-            image_available_semaphores,
-            render_finished_semaphores,
-            in_flight_fences,
-            images_in_flight,
-        ) = render::create_sync_primitives(&device, swapchain_images.len());
+        // no frame has used any image yet
+        let images_in_flight = vec![0; swapchain_images.len()];
 
         let (current_frame, resized) = (0, false);
 
         let ubo = model::UniformBufferObject::new();
 
+        // default lighting, the environment & the demo lights
+        let mut lights = lighting::Lights::new();
+        lights.set_ambient(Vec3::one(), 1.0);
+        let demo_lights = Some(add_demo_lights(&mut lights));
+
+        // quad spun around the view axis with a cube orbiting it & a smaller cube orbiting that,
+        // opposite the cube a sphere changing size to step through its levels of detail
+        let mut scene = scene::Scene::new();
+        let spinner = scene.add_mesh_node(None, scene::Transform::default(), 0, 0, Vec4::one());
+        let cube = scene.add_mesh_node(
+            Some(spinner),
+            scene::Transform::new(
+                Vec3::new(0.7, 0.0, 0.3),
+                Rotor3::identity(),
+                Vec3::broadcast(0.2),
+            ),
+            1,
+            0,
+            Vec4::new(1.0, 0.8, 0.6, 1.0),
+        );
+        let pivot = scene.add_node(Some(cube), scene::Transform::default());
+        scene.add_mesh_node(
+            Some(pivot),
+            scene::Transform::new(
+                Vec3::new(0.0, 1.5, 0.0),
+                Rotor3::identity(),
+                Vec3::broadcast(0.4),
+            ),
+            1,
+            0,
+            Vec4::new(0.6, 0.8, 1.0, 1.0),
+        );
+        let sphere = scene.add_mesh_node(
+            Some(spinner),
+            scene::Transform::new(
+                Vec3::new(-0.7, 0.0, 0.3),
+                Rotor3::identity(),
+                Vec3::broadcast(0.3),
+            ),
+            2,
+            0,
+            Vec4::new(0.8, 1.0, 0.7, 1.0),
+        );
+
+        // the frame's camera, light & shadow blocks, then one per scene node & material batch
+        let mut block_sizes = vec![
+            mem::size_of::<model::UniformBufferObject>(),
+            mem::size_of::<lighting::LightUniformBlock>(),
+            mem::size_of::<shadow::ShadowUniformBlock>(),
+        ];
+        block_sizes.resize(
+            block_sizes.len() + scene.get_node_count() + materials.len(),
+            mem::size_of::<model::ObjectUniformBlock>(),
+        );
+        let uniforms = uniform::UniformRing::new(
+            &instance,
+            &physical_device,
+            &device,
+            &physical_device_properties.limits,
+            &block_sizes,
+            config.render.frames_in_flight,
+        );
+        match uniforms.is_coherent() {
+            true => println!("Uniform memory - coherent"),
+            false => println!("Uniform memory - non-coherent, flushed"),
+        }
+
+        // semaphores, fences, command buffers & instance buffers
+        let instance_capacity = instances.len();
+        let frames = frame::create_frames(
+            &instance,
+            &physical_device,
+            &device,
+            queue_family,
+            *descriptor_pool,
+            *descriptor_set_layout,
+            &(0..config.render.frames_in_flight)
+                .map(|index| uniforms.get_buffer(index))
+                .collect::<Vec<_>>(),
+            &shadow_maps,
+            instance_capacity,
+            config.render.frames_in_flight,
+            !timeline_semaphores,
+        );
+
         // Struct creation
-        Application {
+        let mut app = Application {
             event_loop: Some(event_loop),
-            window,
-            instance,
-            messenger,
-            surface,
             physical_device,
+            physical_device_properties,
+            device_features,
             surface_format,
             present_mode,
-            device,
+            hdr_metadata,
             queue,
+            queue_family,
+            swapchain_image_views,
             swapchain,
             swapchain_images,
             surface_capabilities,
-            swapchain_image_views,
             shader_vert,
             shader_frag,
+            shader_wire_frag,
+            shader_skybox_vert,
+            shader_skybox_frag,
             render_pass,
+            depth_format,
+            msaa_samples,
             descriptor_set_layout,
             pipeline_layout,
-            pipeline,
-            framebuffers,
-            command_pool,
+            pipelines,
+            pipeline_cache,
+            framebuffer,
             vertex_buffer,
-            vertex_buffer_memory,
+            _vertex_buffer_memory: vertex_buffer_memory,
             index_buffer,
-            index_buffer_memory,
-            uniform_buffer,
-            uniform_buffer_memory,
-            descriptor_pool,
-            descriptor_sets,
-            command_buffers,
-            image_available_semaphores,
-            render_finished_semaphores,
-            in_flight_fences,
+            _index_buffer_memory: index_buffer_memory,
+            meshes,
+            mesh_bounds,
+            instance_capacity,
+            _descriptor_pool: descriptor_pool,
+            sampler,
+            material_set_layout,
+            material_descriptor_pool,
+            materials,
+            material_directory: config.assets.material.clone(),
+            environment_set_layout,
+            environment,
+            shadow_maps,
+            render_graph,
+            post,
+            post_targets,
+            frames,
+            uniforms,
+            frame_sync,
             images_in_flight,
+            deletion_queue,
             current_frame,
             start: Instant::now(),
             resized,
+            view_mode: ViewMode::default(),
+            clear_color: config.render.clear_color,
             ubo,
-        }
-    }
-
-    fn destroy_swapchain_related_objects(&self) {
-        unsafe {
-            // destory framebuffers
-            for &framebuffer in &self.framebuffers {
-                self.device.destroy_framebuffer(Some(framebuffer), None);
-            }
-
-            // destroy command buffers
-            self.device
-                .free_command_buffers(self.command_pool, &self.command_buffers);
-
-            // graphics pipeline destruction
-            self.device.destroy_pipeline(Some(self.pipeline), None);
-
-            // render pass destruction
-            self.device
-                .destroy_render_pass(Some(self.render_pass), None);
-
-            // graphics pipeline layout destruction
-            self.device
-                .destroy_pipeline_layout(Some(self.pipeline_layout), None);
+            scene,
+            demo_nodes: [spinner, cube, pivot, sphere],
+            stats: stats::FrameStats::new(config.debug.stats),
+            indirect,
+            instances,
+            lights,
+            demo_lights,
+            device,
+            surface,
+            window,
+            instance,
+        };
 
-            // image view destruction
-            for &image_view in &self.swapchain_image_views {
-                self.device.destroy_image_view(Some(image_view), None);
-            }
+        if let Some(count) = config.render.instances {
+            app.set_instances(model::create_instance_grid(count));
+        }
 
-            // swapchain destruction
-            self.device
-                .destroy_swapchain_khr(Some(self.swapchain), None);
-
-            // uniform buffers destruction
-            for (&buffer, &memory) in self
-                .uniform_buffer
-                .iter()
-                .zip(self.uniform_buffer_memory.iter())
-            {
-                self.device.destroy_buffer(Some(buffer), None);
-                self.device.free_memory(Some(memory), None);
-            }
+        // errors creating everything above aren't part of any frame
+        app.instance.get_validation().check_fatal();
 
-            // destory descriptor pool & sets
-            self.device
-                .destroy_descriptor_pool(Some(self.descriptor_pool), None);
-        }
+        app
     }
 
     fn resize_window(&mut self) {
@@ -362,94 +940,222 @@ impl Application {
                 .device_wait_idle()
                 .expect("Device wait idle failed on resize window!");
 
-            // cleanup swapchain and related devices
-            self.destroy_swapchain_related_objects();
-
-            // create swapchain and get image references
+            // create swapchain and get image references, the old swapchain is retired & dropped
+            // with the objects using its images once they're replaced below
             let (swapchain, swapchain_images, surface_capabilities) =
                 presentation::create_swapchain_and_images(
                     &self.instance,
                     &self.physical_device,
-                    self.surface,
+                    *self.surface,
                     self.surface_format,
                     self.present_mode,
                     &self.device,
+                    Some(*self.swapchain),
                 );
 
             // get swapchain image views
             let swapchain_image_views =
                 presentation::get_image_views(&swapchain_images, &self.device, self.surface_format);
 
+            // metadata belongs to the swapchain so is lost with the old one
+            if self.hdr_metadata {
+                presentation::set_hdr_metadata(
+                    &self.device,
+                    *swapchain,
+                    self.post.settings.output,
+                    self.post.settings.paper_white,
+                    self.post.settings.max_luminance,
+                );
+            }
+
             // graphics pipeline & render pass
-            let (pipeline, pipeline_layout, render_pass) = pipeline::create_graphics_pipeline(
+            let (pipelines, pipeline_layout, render_pass) = pipeline::create_graphics_pipeline(
                 &self.device,
-                self.shader_vert,
-                self.shader_frag,
-                &self.descriptor_set_layout,
-                self.surface_format,
+                *self.pipeline_cache,
+                *self.shader_vert,
+                *self.shader_frag,
+                *self.shader_wire_frag,
+                *self.shader_skybox_vert,
+                *self.shader_skybox_frag,
+                &[
+                    *self.descriptor_set_layout,
+                    *self.material_set_layout,
+                    *self.environment_set_layout,
+                ],
+                post::HDR_FORMAT,
+                self.depth_format,
+                self.msaa_samples,
+                &self.device_features,
+            );
+
+            // transient images are reallocated at the new size
+            let (render_graph, graph_images) = render::create_render_graph(
+                &self.instance,
+                &self.physical_device,
+                &self.device,
+                self.depth_format,
+                surface_capabilities.current_extent,
+                self.view_mode,
+                &self.post.settings,
+                self.msaa_samples,
+                Some(mem::replace(
+                    &mut self.render_graph,
+                    graph::RenderGraph::new(),
+                )),
+                &mut self.deletion_queue,
+                self.frame_sync.get_submitted(),
             );
 
             // create framebuffers
-            let framebuffers = render::create_framebuffers(
+            let framebuffer = Owned::new(
                 &self.device,
+                render::create_framebuffer(
+                    &self.device,
+                    *render_pass,
+                    &graph_images.get_lit_attachments(&render_graph),
+                    surface_capabilities.current_extent,
+                ),
+                "scene framebuffer",
+            );
+            let post_targets = post::create_post_targets(
+                &self.device,
+                &self.post,
+                &render_graph,
+                graph_images.hdr,
+                &graph_images.post,
                 &swapchain_image_views,
-                &render_pass,
-                &surface_capabilities,
+                surface_capabilities.current_extent,
             );
 
-            // create uniform buffers
-            let (uniform_buffer, uniform_buffer_memory) = buffer::create_uniform_buffer(
+            // none of the frames images_in_flight refers to are in flight after the wait
+            self.images_in_flight = vec![0; swapchain_images.len()];
+
+            // replaced objects are dropped here, everything using the old swapchain's images
+            // goes before the swapchain itself
+            self.post_targets = post_targets;
+            self.framebuffer = framebuffer;
+            self.render_graph = render_graph;
+            self.render_pass = render_pass;
+            self.pipeline_layout = pipeline_layout;
+            self.pipelines = pipelines;
+            self.swapchain_image_views = swapchain_image_views;
+            self.swapchain = swapchain;
+            self.swapchain_images = swapchain_images;
+            self.surface_capabilities = surface_capabilities;
+            self.resized = false;
+        };
+    }
+
+    // replace instance data, uploaded to the gpu at the start of the next frame
+    pub fn set_instances(&mut self, instances: Vec<model::InstanceData>) {
+        // grow instance buffers if needed, the old ones might still be in use
+        if instances.len() > self.instance_capacity {
+            let instance_buffers = buffer::create_instance_buffers(
                 &self.instance,
                 &self.physical_device,
                 &self.device,
-                swapchain_images.len(),
+                instances.len(),
+                self.frames.len(),
             );
 
-            // create descriptor pool
-            let descriptor_pool =
-                pipeline::create_descriptor_pool(&self.device, swapchain_images.len() as u32);
+            let last_used = self.frame_sync.get_submitted();
+            for (frame, (buffer, memory)) in self.frames.iter_mut().zip(instance_buffers) {
+                let replaced = (
+                    mem::replace(&mut frame.instance_buffer, buffer),
+                    mem::replace(&mut frame.instance_buffer_memory, memory),
+                );
+                self.deletion_queue.push_buffers(last_used, vec![replaced]);
+            }
+            self.instance_capacity = instances.len();
+        }
 
-            // create descriptor sets
-            let descriptor_sets = pipeline::create_descriptor_sets(
-                &self.device,
-                &self.descriptor_set_layout,
-                &descriptor_pool,
-                &uniform_buffer,
-                swapchain_images.len(),
-            );
+        self.mesh_bounds = culling::get_instanced_bounds(&self.meshes, &instances);
+        self.instances = instances;
+    }
+
+    fn set_view_mode(&mut self, view_mode: ViewMode) {
+        if view_mode == ViewMode::Wireframe && self.pipelines.wireframe.is_none() {
+            println!("Wireframe view mode unsupported (no fillModeNonSolid)!");
+            return;
+        }
 
-            // allocate command buffers
-            let command_buffers =
-                render::allocate_command_buffers(&self.device, &self.command_pool, &framebuffers);
+        println!("View mode - {:?}", view_mode);
 
-            // record command buffers
-            render::record_command_buffers(
+        // shadow pass is only needed by lit modes, transient images are reused so the
+        // framebuffers & post descriptor sets stay valid, unused ones are freed after the
+        // frames in flight
+        if view_mode.is_lit() != self.view_mode.is_lit() {
+            let (render_graph, _) = render::create_render_graph(
+                &self.instance,
+                &self.physical_device,
                 &self.device,
-                &pipeline,
-                &command_buffers,
-                &framebuffers,
-                &descriptor_sets,
-                &pipeline_layout,
-                &render_pass,
-                &surface_capabilities,
-                &self.vertex_buffer,
-                &self.index_buffer,
+                self.depth_format,
+                self.surface_capabilities.current_extent,
+                view_mode,
+                &self.post.settings,
+                self.msaa_samples,
+                Some(mem::replace(
+                    &mut self.render_graph,
+                    graph::RenderGraph::new(),
+                )),
+                &mut self.deletion_queue,
+                self.frame_sync.get_submitted(),
             );
+            self.render_graph = render_graph;
+        }
 
-            self.swapchain = swapchain;
-            self.swapchain_images = swapchain_images;
-            self.surface_capabilities = surface_capabilities;
-            self.swapchain_image_views = swapchain_image_views;
-            self.pipeline = pipeline;
-            self.pipeline_layout = pipeline_layout;
-            self.render_pass = render_pass;
-            self.framebuffers = framebuffers;
-            self.uniform_buffer = uniform_buffer;
-            self.uniform_buffer_memory = uniform_buffer_memory;
-            self.descriptor_pool = descriptor_pool;
-            self.descriptor_sets = descriptor_sets;
-            self.command_buffers = command_buffers;
-            self.resized = false;
+        self.view_mode = view_mode;
+    }
+
+    // uploads a material in place of the one at index without waiting for the gpu, the old
+    // textures, factors & descriptor set are freed once the frames drawing with them finish
+    pub fn replace_material(&mut self, index: usize, description: &material::Material) {
+        let last_used = self.frame_sync.get_submitted();
+
+        let mut uploader = upload::Uploader::new(
+            self.queue,
+            self.queue_family,
+            &mut self.frame_sync,
+            &mut self.deletion_queue,
+        );
+        let material = material::upload_material(
+            &self.instance,
+            &self.physical_device,
+            &self.device,
+            &mut uploader,
+            &self.material_descriptor_pool,
+            &self.material_set_layout,
+            *self.sampler,
+            description,
+        );
+
+        let replaced = mem::replace(&mut self.materials[index], material);
+        self.deletion_queue.push(last_used, replaced);
+    }
+
+    // picks up changes to the material's textures on disk
+    fn reload_material(&mut self) {
+        let description = match &self.material_directory {
+            Some(directory) => material::Material::from_directory(directory),
+            None => material::Material::default(),
+        };
+
+        println!("Material - reloaded");
+        self.replace_material(0, &description);
+    }
+
+    // the environment keeps lighting the scene without them
+    fn toggle_demo_lights(&mut self) {
+        self.demo_lights = match self.demo_lights.take() {
+            Some(demo_lights) => {
+                remove_demo_lights(&mut self.lights, demo_lights);
+                println!("Demo lights - off");
+                None
+            }
+            None => {
+                println!("Demo lights - on");
+                Some(add_demo_lights(&mut self.lights))
+            }
         };
     }
 
@@ -458,53 +1164,124 @@ impl Application {
             .event_loop
             .take()
             .expect("Failed to take event loop out of Option!");
-        event_loop.run(move |event, _, control_flow| match event {
-            // Init
-            Event::NewEvents(StartCause::Init) => {
-                *control_flow = ControlFlow::Poll;
-            }
 
-            Event::MainEventsCleared => {
-                state::main_events_cleared(&mut self);
-            }
+        // taken out & dropped when the loop is destroyed, before the process exits
+        let mut application = Some(self);
 
-            // Window events
-            Event::WindowEvent { event, .. } => match event {
-                WindowEvent::CloseRequested => {
-                    *control_flow = ControlFlow::Exit;
+        event_loop.run(move |event, _, control_flow| {
+            let app = match &mut application {
+                Some(app) => app,
+                None => return,
+            };
+
+            match event {
+                // Init
+                Event::NewEvents(StartCause::Init) => {
+                    *control_flow = ControlFlow::Poll;
                 }
-                WindowEvent::Resized { .. } => {
-                    // halt on minimization?
-                    if self.window.inner_size() == PhysicalSize::new(0, 0) {
-                        *control_flow = ControlFlow::Wait;
-                    }
 
-                    self.resized = true;
+                Event::MainEventsCleared => {
+                    state::main_events_cleared(app);
                 }
-                _ => (),
-            },
 
-            // Input events
-            Event::DeviceEvent { event, .. } => match event {
-                DeviceEvent::Key(KeyboardInput {
-                    virtual_keycode: Some(scancode),
-                    state,
-                    ..
-                }) => match (scancode, state) {
-                    (VirtualKeyCode::Escape, ElementState::Released) => {
+                // Window events
+                Event::WindowEvent { event, .. } => match event {
+                    WindowEvent::CloseRequested => {
                         *control_flow = ControlFlow::Exit;
                     }
+                    WindowEvent::Resized { .. } => {
+                        // halt on minimization?
+                        if app.window.inner_size() == PhysicalSize::new(0, 0) {
+                            *control_flow = ControlFlow::Wait;
+                        }
+
+                        app.resized = true;
+                    }
                     _ => (),
                 },
-                _ => (),
-            },
 
-            // Loop destruction
-            Event::LoopDestroyed => {
-                state::loop_destroyed(&mut self);
-            }
+                // Input events
+                Event::DeviceEvent {
+                    event:
+                        DeviceEvent::Key(KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            state: ElementState::Released,
+                            ..
+                        }),
+                    ..
+                } => {
+                    *control_flow = ControlFlow::Exit;
+                }
+                Event::DeviceEvent {
+                    event:
+                        DeviceEvent::Key(KeyboardInput {
+                            virtual_keycode: Some(keycode),
+                            state: ElementState::Released,
+                            ..
+                        }),
+                    ..
+                } => {
+                    if let Some(view_mode) = ViewMode::from_keycode(keycode) {
+                        app.set_view_mode(view_mode);
+                    } else if keycode == VirtualKeyCode::L {
+                        app.toggle_demo_lights();
+                    } else if keycode == VirtualKeyCode::R {
+                        app.reload_material();
+                    }
+                }
+
+                // Loop destruction, dropping the application destroys everything it owns
+                Event::LoopDestroyed => {
+                    application = None;
+                }
 
-            _ => (),
+                _ => (),
+            }
         })
     }
 }
+
+// indices of the lights added by add_demo_lights, None for any the lights were full for
+#[derive(Clone, Copy)]
+struct DemoLights {
+    key: Option<usize>,
+    point: Option<usize>,
+    spot: Option<usize>,
+}
+
+// a white key light, a warm point light orbiting the mesh & a spot light
+fn add_demo_lights(lights: &mut lighting::Lights) -> DemoLights {
+    let key = lights.add_directional_light(Vec3::new(0.3, 0.5, 1.0), Vec3::one(), 0.8);
+    let point = lights.add_point_light(Vec3::zero(), Vec3::new(1.0, 0.6, 0.2), 1.5, 2.0);
+    // cool spot light from behind the camera, casts a shadow like the key light
+    let spot = lights.add_spot_light(
+        Vec3::new(0.0, 0.0, -0.5),
+        Vec3::unit_z(),
+        Vec3::new(0.6, 0.8, 1.0),
+        2.0,
+        3.0,
+        0.25,
+        0.4,
+    );
+
+    DemoLights { key, point, spot }
+}
+
+fn remove_demo_lights(lights: &mut lighting::Lights, demo_lights: DemoLights) {
+    if let Some(index) = demo_lights.key {
+        lights.remove_directional_light(index);
+    }
+    if let Some(index) = demo_lights.point {
+        lights.remove_point_light(index);
+    }
+    if let Some(index) = demo_lights.spot {
+        lights.remove_spot_light(index);
+    }
+}
+
+// waits for the gpu & destroys what isn't owned, owned handles go with the fields after
+impl Drop for Application {
+    fn drop(&mut self) {
+        state::loop_destroyed(self);
+    }
+}
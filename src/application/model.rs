@@ -1,8 +1,12 @@
+use crate::application::culling::Bounds;
+use crate::application::lod::{self, MAX_LODS};
+
 use erupt::vk;
 
 use std::mem::size_of;
 
 use ultraviolet::mat::Mat4;
+use ultraviolet::vec::{Vec3, Vec4};
 
 pub type Index = u32;
 
@@ -10,26 +14,46 @@ pub const VERTICES: [Vertex; 4] = [
     Vertex {
         _pos: [-0.5, -0.5, 0.5],
         _color: [1.0, 0.0, 0.0],
+        // facing the camera
+        _normal: [0.0, 0.0, -1.0],
+        _uv: [0.0, 1.0],
+        // generated by Mesh::generate_tangents
+        _tangent: [0.0; 4],
     },
     Vertex {
         _pos: [0.5, -0.5, 0.5],
         _color: [0.0, 1.0, 0.0],
+        _normal: [0.0, 0.0, -1.0],
+        _uv: [1.0, 1.0],
+        _tangent: [0.0; 4],
     },
     Vertex {
         _pos: [0.5, 0.5, 0.5],
         _color: [0.0, 0.0, 1.0],
+        _normal: [0.0, 0.0, -1.0],
+        _uv: [1.0, 0.0],
+        _tangent: [0.0; 4],
     },
     Vertex {
         _pos: [-0.5, 0.5, 0.5],
         _color: [1.0, 1.0, 1.0],
+        _normal: [0.0, 0.0, -1.0],
+        _uv: [0.0, 0.0],
+        _tangent: [0.0; 4],
     },
 ];
 
 pub const INDICIES: [Index; 6] = [0, 1, 2, 2, 3, 0];
 
+#[repr(C)]
+#[derive(Clone, Copy)]
 pub struct Vertex {
     _pos: [f32; 3],
     _color: [f32; 3],
+    _normal: [f32; 3],
+    _uv: [f32; 2],
+    // xyz tangent, w handedness of bitangent
+    _tangent: [f32; 4],
 }
 
 impl Vertex {
@@ -40,8 +64,10 @@ impl Vertex {
             .input_rate(vk::VertexInputRate::VERTEX)
     }
 
-    pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescriptionBuilder<'static>; 2]
+    pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescriptionBuilder<'static>; 5]
     {
+        let vec3_size = size_of::<[f32; 3]>() as u32;
+
         [
             // position
             vk::VertexInputAttributeDescriptionBuilder::new()
@@ -55,24 +81,442 @@ impl Vertex {
                 .location(1)
                 .format(vk::Format::R32G32B32_SFLOAT)
                 // offset of color is just size_of(pos)
-                .offset(size_of::<[f32; 3]>() as u32),
+                .offset(vec3_size),
+            // normal
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(2)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(2 * vec3_size),
+            // uv
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(3)
+                .format(vk::Format::R32G32_SFLOAT)
+                .offset(3 * vec3_size),
+            // tangent
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(4)
+                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .offset(3 * vec3_size + size_of::<[f32; 2]>() as u32),
         ]
     }
 }
 
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<Index>,
+    // simplified index buffers into the same vertices, coarsest last
+    pub lods: Vec<Vec<Index>>,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<Index>) -> Mesh {
+        let mut mesh = Mesh {
+            vertices,
+            indices,
+            lods: Vec::new(),
+        };
+
+        // needed for normal mapping
+        if !mesh.has_tangents() {
+            mesh.generate_tangents();
+        }
+
+        let positions: Vec<_> = mesh
+            .vertices
+            .iter()
+            .map(|vertex| Vec3::from(vertex._pos))
+            .collect();
+        mesh.lods = lod::generate_lods(&positions, &mesh.indices);
+
+        mesh
+    }
+
+    pub fn quad() -> Mesh {
+        Mesh::new(VERTICES.to_vec(), INDICIES.to_vec())
+    }
+
+    // unit cube centred on the origin, 4 vertices per face so normals & uvs are flat
+    pub fn cube() -> Mesh {
+        // normal, then the two axes spanning the face
+        let faces = [
+            (Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z()),
+            (-Vec3::unit_x(), Vec3::unit_z(), Vec3::unit_y()),
+            (Vec3::unit_y(), Vec3::unit_z(), Vec3::unit_x()),
+            (-Vec3::unit_y(), Vec3::unit_x(), Vec3::unit_z()),
+            (Vec3::unit_z(), Vec3::unit_x(), Vec3::unit_y()),
+            (-Vec3::unit_z(), Vec3::unit_y(), Vec3::unit_x()),
+        ];
+
+        let mut vertices = Vec::with_capacity(24);
+        let mut indices = Vec::with_capacity(36);
+
+        for (normal, u, v) in faces {
+            let first = vertices.len() as Index;
+
+            for (x, y) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+                let position = (normal + u * (x * 2.0 - 1.0) + v * (y * 2.0 - 1.0)) * 0.5;
+
+                vertices.push(Vertex {
+                    _pos: position.into(),
+                    _color: [1.0, 1.0, 1.0],
+                    _normal: normal.into(),
+                    _uv: [x, 1.0 - y],
+                    _tangent: [0.0; 4],
+                });
+            }
+
+            indices.extend_from_slice(&[first, first + 1, first + 2, first + 2, first + 3, first]);
+        }
+
+        Mesh::new(vertices, indices)
+    }
+
+    // uv sphere of radius 0.5 centred on the origin
+    pub fn sphere(segments: u32, rings: u32) -> Mesh {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+
+        // seam column is duplicated so uvs wrap
+        for ring in 0..=rings {
+            let v = ring as f32 / rings as f32;
+            let polar = v * std::f32::consts::PI;
+
+            for segment in 0..=segments {
+                let u = segment as f32 / segments as f32;
+                let azimuth = u * std::f32::consts::TAU;
+
+                let normal = Vec3::new(
+                    polar.sin() * azimuth.cos(),
+                    polar.sin() * azimuth.sin(),
+                    polar.cos(),
+                );
+
+                vertices.push(Vertex {
+                    _pos: (normal * 0.5).into(),
+                    _color: [1.0, 1.0, 1.0],
+                    _normal: normal.into(),
+                    _uv: [u, v],
+                    _tangent: [0.0; 4],
+                });
+            }
+        }
+
+        let stride = segments + 1;
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let a = ring * stride + segment;
+                let b = a + stride;
+
+                indices.extend_from_slice(&[a, b, a + 1, a + 1, b, b + 1]);
+            }
+        }
+
+        Mesh::new(vertices, indices)
+    }
+
+    pub fn get_bounds(&self) -> Bounds {
+        Bounds::from_points(self.vertices.iter().map(|vertex| Vec3::from(vertex._pos)))
+    }
+
+    fn has_tangents(&self) -> bool {
+        self.vertices
+            .iter()
+            .any(|vertex| vertex._tangent[..3] != [0.0; 3])
+    }
+
+    // per vertex tangents from triangle uv gradients, accumulated over adjacent triangles
+    // then orthogonalized against the normal (Lengyel's method)
+    pub fn generate_tangents(&mut self) {
+        let mut tangents = vec![Vec3::zero(); self.vertices.len()];
+        let mut bitangents = vec![Vec3::zero(); self.vertices.len()];
+
+        for triangle in self.indices.chunks_exact(3) {
+            let [i0, i1, i2] = [
+                triangle[0] as usize,
+                triangle[1] as usize,
+                triangle[2] as usize,
+            ];
+            let (v0, v1, v2) = (&self.vertices[i0], &self.vertices[i1], &self.vertices[i2]);
+
+            let edge1 = Vec3::from(v1._pos) - Vec3::from(v0._pos);
+            let edge2 = Vec3::from(v2._pos) - Vec3::from(v0._pos);
+            let (du1, dv1) = (v1._uv[0] - v0._uv[0], v1._uv[1] - v0._uv[1]);
+            let (du2, dv2) = (v2._uv[0] - v0._uv[0], v2._uv[1] - v0._uv[1]);
+
+            // degenerate uvs
+            let determinant = du1 * dv2 - du2 * dv1;
+            if determinant.abs() < f32::EPSILON {
+                continue;
+            }
+
+            let r = 1.0 / determinant;
+            let tangent = (edge1 * dv2 - edge2 * dv1) * r;
+            let bitangent = (edge2 * du1 - edge1 * du2) * r;
+
+            for &index in &[i0, i1, i2] {
+                tangents[index] += tangent;
+                bitangents[index] += bitangent;
+            }
+        }
+
+        for ((vertex, tangent), bitangent) in self.vertices.iter_mut().zip(tangents).zip(bitangents)
+        {
+            let normal = Vec3::from(vertex._normal);
+
+            // gram-schmidt orthogonalize, fall back to any perpendicular vector
+            let mut orthogonal = tangent - normal * normal.dot(tangent);
+            if orthogonal.mag_sq() < f32::EPSILON {
+                orthogonal = normal.cross(Vec3::unit_x());
+                if orthogonal.mag_sq() < f32::EPSILON {
+                    orthogonal = normal.cross(Vec3::unit_y());
+                }
+            }
+            let orthogonal = orthogonal.normalized();
+
+            let handedness = if normal.cross(orthogonal).dot(bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+
+            vertex._tangent = [orthogonal.x, orthogonal.y, orthogonal.z, handedness];
+        }
+    }
+}
+
+// one level of detail's indices in the shared index buffer
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LodRange {
+    pub first_index: u32,
+    pub index_count: u32,
+}
+
+// where a mesh sits in the shared vertex & index buffers
+#[derive(Debug, Clone, Copy)]
+pub struct MeshRange {
+    // full detail first, only lod_count are used
+    pub lods: [LodRange; MAX_LODS],
+    pub lod_count: usize,
+    pub vertex_offset: i32,
+    // object space, computed when the mesh is loaded
+    pub bounds: Bounds,
+}
+
+impl MeshRange {
+    // clamped to the levels the mesh has
+    pub fn get_lod(&self, lod: usize) -> LodRange {
+        self.lods[lod.min(self.lod_count - 1)]
+    }
+}
+
+// every mesh goes in one vertex & one index buffer, drawn by range
+pub fn merge_meshes(meshes: &[Mesh]) -> (Vec<Vertex>, Vec<Index>, Vec<MeshRange>) {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+
+    let ranges = meshes
+        .iter()
+        .map(|mesh| {
+            let mut range = MeshRange {
+                lods: [LodRange::default(); MAX_LODS],
+                lod_count: 0,
+                vertex_offset: vertices.len() as i32,
+                bounds: mesh.get_bounds(),
+            };
+
+            // every level after one another
+            for lod in Some(&mesh.indices).into_iter().chain(&mesh.lods) {
+                range.lods[range.lod_count] = LodRange {
+                    first_index: indices.len() as u32,
+                    index_count: lod.len() as u32,
+                };
+                range.lod_count += 1;
+
+                indices.extend_from_slice(lod);
+            }
+
+            vertices.extend_from_slice(&mesh.vertices);
+
+            range
+        })
+        .collect();
+
+    (vertices, indices, ranges)
+}
+
+// per-instance data read from vertex binding 1
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct InstanceData {
+    pub transform: Mat4,
+    pub color: Vec4,
+    // written by the culling pass for its draws, which push LOD_FROM_INSTANCE, unused otherwise
+    pub lod: u32,
+    _padding: [u32; 3],
+}
+
+impl InstanceData {
+    pub fn new(transform: Mat4, color: Vec4) -> InstanceData {
+        InstanceData {
+            transform,
+            color,
+            lod: 0,
+            _padding: [0; 3],
+        }
+    }
+
+    pub fn get_binding_descriptions() -> vk::VertexInputBindingDescriptionBuilder<'static> {
+        vk::VertexInputBindingDescriptionBuilder::new()
+            .binding(1)
+            .stride(size_of::<InstanceData>() as u32)
+            .input_rate(vk::VertexInputRate::INSTANCE)
+    }
+
+    pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescriptionBuilder<'static>; 6]
+    {
+        let column_size = size_of::<Vec4>() as u32;
+
+        // a mat4 attribute takes up 4 locations, one per column
+        let column = |index: u32| {
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(1)
+                .location(5 + index)
+                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .offset(index * column_size)
+        };
+
+        [
+            // transform
+            column(0),
+            column(1),
+            column(2),
+            column(3),
+            // color
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(1)
+                .location(9)
+                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .offset(size_of::<Mat4>() as u32),
+            // lod
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(1)
+                .location(10)
+                .format(vk::Format::R32_UINT)
+                .offset((size_of::<Mat4>() + size_of::<Vec4>()) as u32),
+        ]
+    }
+}
+
+impl Default for InstanceData {
+    fn default() -> Self {
+        InstanceData::new(Mat4::identity(), Vec4::one())
+    }
+}
+
+// demo of many instances, count quads laid out in a square grid
+pub fn create_instance_grid(count: usize) -> Vec<InstanceData> {
+    let side = (count as f32).sqrt().ceil().max(1.0) as usize;
+    let cell_size = 2.0 / side as f32;
+
+    (0..count)
+        .map(|index| {
+            let (x, y) = ((index % side) as f32, (index / side) as f32);
+
+            // cell centre in [-1, 1]
+            let translation = Vec3::new(
+                -1.0 + (x + 0.5) * cell_size,
+                -1.0 + (y + 0.5) * cell_size,
+                0.0,
+            );
+
+            // slightly smaller than cell so quads don't touch
+            let transform = Mat4::from_translation(translation) * Mat4::from_scale(cell_size * 0.8);
+
+            // color gradient across grid
+            let color = Vec4::new(x / side as f32, y / side as f32, 1.0 - x / side as f32, 1.0);
+
+            InstanceData::new(transform, color)
+        })
+        .collect()
+}
+
 #[repr(C, align(16))]
 pub struct UniformBufferObject {
-    pub model: Mat4,
     pub view: Mat4,
     pub proj: Mat4,
+    // see ViewMode::shader_index
+    pub view_mode: u32,
+    _padding: [u32; 3],
 }
 
 impl UniformBufferObject {
     pub fn new() -> UniformBufferObject {
         UniformBufferObject {
-            model: Mat4::identity(),
             view: Mat4::identity(),
             proj: Mat4::identity(),
+            view_mode: 0,
+            _padding: [0; 3],
         }
     }
 }
+
+// per object data, each draw binds its own block in the uniform ring by dynamic offset
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct ObjectUniformBlock {
+    pub model: Mat4,
+}
+
+impl ObjectUniformBlock {
+    pub fn new(model: Mat4) -> ObjectUniformBlock {
+        ObjectUniformBlock { model }
+    }
+}
+
+impl Default for ObjectUniformBlock {
+    fn default() -> Self {
+        ObjectUniformBlock::new(Mat4::identity())
+    }
+}
+
+// per-draw data sent through push constants (must fit in the guaranteed 128 bytes)
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct DrawConstants {
+    pub tint: Vec4,
+    pub material_index: u32,
+    // only shown by the lod view mode, LOD_FROM_INSTANCE for indirect draws
+    pub lod: u32,
+    _padding: [u32; 2],
+}
+
+// must match LOD_FROM_INSTANCE in shader.vert, the level is read from InstanceData::lod instead
+pub const LOD_FROM_INSTANCE: u32 = u32::MAX;
+
+impl DrawConstants {
+    pub fn new(tint: Vec4, material_index: u32, lod: u32) -> DrawConstants {
+        DrawConstants {
+            tint,
+            material_index,
+            lod,
+            _padding: [0; 2],
+        }
+    }
+
+    // both shader stages read the same block
+    pub fn get_push_constant_range() -> vk::PushConstantRangeBuilder<'static> {
+        vk::PushConstantRangeBuilder::new()
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(size_of::<DrawConstants>() as u32)
+    }
+}
+
+impl Default for DrawConstants {
+    fn default() -> Self {
+        DrawConstants::new(Vec4::one(), 0, 0)
+    }
+}
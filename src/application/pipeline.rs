@@ -2,17 +2,23 @@ use std::os::raw::c_char;
 
 use erupt::{cstr, utils, vk, DeviceLoader};
 
-use crate::application::model;
+use crate::application::handle::{self, Owned};
+use crate::application::{debug, lighting, model, shadow};
 
 use std::ffi::CStr;
+use std::mem::size_of;
+use std::rc::Rc;
 
 // shader spvs
 pub const SHADER_VERT: &[u8] = include_bytes!("../../res/shaders/vert.spv");
 pub const SHADER_FRAG: &[u8] = include_bytes!("../../res/shaders/frag.spv");
+pub const SHADER_WIRE_FRAG: &[u8] = include_bytes!("../../res/shaders/wire.spv");
+pub const SHADER_SKYBOX_VERT: &[u8] = include_bytes!("../../res/shaders/skybox_vert.spv");
+pub const SHADER_SKYBOX_FRAG: &[u8] = include_bytes!("../../res/shaders/skybox_frag.spv");
 
 pub const SHADER_ENTRY: *const c_char = cstr!("main");
 
-pub fn create_shader_modules<'a>(device: &DeviceLoader) -> (vk::ShaderModule, vk::ShaderModule) {
+pub fn create_shader_modules(device: &DeviceLoader) -> (vk::ShaderModule, vk::ShaderModule) {
     // vertex shader
     let vert_decoded = utils::decode_spv(SHADER_VERT).expect("Failed to decode vertex shader spv");
     let module_info = vk::ShaderModuleCreateInfoBuilder::new().code(&vert_decoded);
@@ -29,35 +35,42 @@ pub fn create_shader_modules<'a>(device: &DeviceLoader) -> (vk::ShaderModule, vk
     (shader_vert, shader_frag)
 }
 
-// stuff for graphics pipeline bar render pass, pipeline layout (WIP) and shader stages
+pub fn create_shader_module(device: &DeviceLoader, spv: &[u8]) -> vk::ShaderModule {
+    let decoded = utils::decode_spv(spv).expect("Failed to decode shader spv");
+    let module_info = vk::ShaderModuleCreateInfoBuilder::new().code(&decoded);
+    unsafe { device.create_shader_module(&module_info, None, None) }
+        .expect("Failed to create shader module!")
+}
+
+// stuff for graphics pipeline bar render pass, pipeline layout and shader stages
 fn create_fixed_functions<'a>(
-    device: &'a DeviceLoader,
-    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+    topology: vk::PrimitiveTopology,
+    polygon_mode: vk::PolygonMode,
+    samples: vk::SampleCountFlagBits,
 ) -> (
     vk::PipelineInputAssemblyStateCreateInfoBuilder<'a>,
     vk::PipelineRasterizationStateCreateInfoBuilder<'a>,
     vk::PipelineMultisampleStateCreateInfoBuilder<'a>,
     Vec<vk::PipelineColorBlendAttachmentStateBuilder<'a>>,
-    vk::PipelineLayout,
 ) {
     // how vertices are interpreted, TRIANGLE_LIST is just regular triangles not triangle strip
     let input_assembly = vk::PipelineInputAssemblyStateCreateInfoBuilder::new()
-        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .topology(topology)
         .primitive_restart_enable(false);
 
     // pretty normal setttings, no backface culling, clockwise front facing
     let rasterizer = vk::PipelineRasterizationStateCreateInfoBuilder::new()
         .depth_clamp_enable(false)
         .rasterizer_discard_enable(false)
-        .polygon_mode(vk::PolygonMode::FILL)
+        .polygon_mode(polygon_mode)
         .line_width(1.0)
         .cull_mode(vk::CullModeFlags::NONE)
         .front_face(vk::FrontFace::COUNTER_CLOCKWISE);
 
-    // disabled for now
+    // matches the render pass attachments, no sample shading so only edges get extra samples
     let multisampling = vk::PipelineMultisampleStateCreateInfoBuilder::new()
         .sample_shading_enable(false)
-        .rasterization_samples(vk::SampleCountFlagBits::_1);
+        .rasterization_samples(samples);
 
     // alpha blending (src is new color) i.e.
     // finalColor.rgb = newAlpha * newColor + (1 - newAlpha) * oldColor;
@@ -77,80 +90,189 @@ fn create_fixed_functions<'a>(
         .color_blend_op(vk::BlendOp::ADD)
         .alpha_blend_op(vk::BlendOp::ADD)];
 
-    let pipeline_layout_info =
-        vk::PipelineLayoutCreateInfoBuilder::new().set_layouts(descriptor_set_layouts);
-    let pipeline_layout =
-        unsafe { device.create_pipeline_layout(&pipeline_layout_info, None, None) }
-            .expect("Failed to create pipeline layout!");
-
     (
         input_assembly,
         rasterizer,
         multisampling,
         color_blend_attachments,
-        pipeline_layout,
     )
 }
 
-fn create_render_pass(format: vk::SurfaceFormatKHR, device: &DeviceLoader) -> vk::RenderPass {
-    // clear framebuffer before render & optimize final_layout for presentation in swapchain
-    let attachments = vec![vk::AttachmentDescriptionBuilder::new()
-        .format(format.format)
-        .samples(vk::SampleCountFlagBits::_1)
-        .load_op(vk::AttachmentLoadOp::CLEAR)
-        .store_op(vk::AttachmentStoreOp::STORE)
-        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-        .initial_layout(vk::ImageLayout::UNDEFINED)
-        .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)];
-
-    // one only attachment & subpass used
+fn create_pipeline_layout(
+    device: &DeviceLoader,
+    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+) -> vk::PipelineLayout {
+    // per-draw data, the object's transform is bound through set 0's dynamic offsets
+    let push_constant_ranges = [model::DrawConstants::get_push_constant_range()];
+
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfoBuilder::new()
+        .set_layouts(descriptor_set_layouts)
+        .push_constant_ranges(&push_constant_ranges);
+
+    unsafe { device.create_pipeline_layout(&pipeline_layout_info, None, None) }
+        .expect("Failed to create pipeline layout!")
+}
+
+// highest sample count the lit pass' color & depth both support, up to the requested one
+pub fn get_msaa_samples(
+    limits: &vk::PhysicalDeviceLimits,
+    requested: u32,
+) -> vk::SampleCountFlagBits {
+    let supported = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+
+    let mut samples = vk::SampleCountFlagBits(requested);
+    while samples != vk::SampleCountFlagBits::_1 && !supported.contains(samples.bitmask()) {
+        samples = vk::SampleCountFlagBits(samples.0 / 2);
+    }
+
+    samples
+}
+
+fn create_render_pass(
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    samples: vk::SampleCountFlagBits,
+    device: &DeviceLoader,
+) -> vk::RenderPass {
+    let multisampled = samples != vk::SampleCountFlagBits::_1;
+
+    // multisampled color is only needed until it's resolved
+    let color_store_op = if multisampled {
+        vk::AttachmentStoreOp::DONT_CARE
+    } else {
+        vk::AttachmentStoreOp::STORE
+    };
+
+    // clear framebuffer before render, layout transitions & presentation barriers are
+    // inserted by the render graph so attachments stay in their attachment layouts
+    let mut attachments = vec![
+        vk::AttachmentDescriptionBuilder::new()
+            .format(color_format)
+            .samples(samples)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(color_store_op)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+        // depth isn't needed after the pass
+        vk::AttachmentDescriptionBuilder::new()
+            .format(depth_format)
+            .samples(samples)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
+    ];
+
+    // one subpass drawing to both attachments
     let color_attachment_references = vec![vk::AttachmentReferenceBuilder::new()
         .attachment(0)
         .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
 
-    let subpasses =
-        vec![vk::SubpassDescriptionBuilder::new().color_attachments(&color_attachment_references)];
+    let depth_attachment_reference = vk::AttachmentReferenceBuilder::new()
+        .attachment(1)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+    // single sampled color the samples are averaged into at the end of the subpass
+    let resolve_attachment_references = vec![vk::AttachmentReferenceBuilder::new()
+        .attachment(2)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+
+    let mut subpass = vk::SubpassDescriptionBuilder::new()
+        .color_attachments(&color_attachment_references)
+        .depth_stencil_attachment(&depth_attachment_reference);
+
+    if multisampled {
+        attachments.push(
+            vk::AttachmentDescriptionBuilder::new()
+                .format(color_format)
+                .samples(vk::SampleCountFlagBits::_1)
+                .load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+        );
+        subpass = subpass.resolve_attachments(&resolve_attachment_references);
+    }
 
-    // subpass dependency to trigger render_finished_semaphore
-    let dependencies = vec![vk::SubpassDependencyBuilder::new()
-        .src_subpass(vk::SUBPASS_EXTERNAL)
-        .dst_subpass(0)
-        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-        .src_access_mask(vk::AccessFlags::empty())
-        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)];
+    let subpasses = vec![subpass];
 
     let render_pass_info = vk::RenderPassCreateInfoBuilder::new()
         .attachments(&attachments)
-        .subpasses(&subpasses)
-        .dependencies(&dependencies);
-
-    let render_pass = unsafe { device.create_render_pass(&render_pass_info, None, None) }
-        .expect("Failed to expect render pass!");
+        .subpasses(&subpasses);
 
-    render_pass
+    unsafe { device.create_render_pass(&render_pass_info, None, None) }
+        .expect("Failed to expect render pass!")
 }
 
-pub fn create_descriptor_pool(device: &DeviceLoader, swapchain_length: u32) -> vk::DescriptorPool {
-    let pool_size = &[vk::DescriptorPoolSizeBuilder::new()
-        ._type(vk::DescriptorType::UNIFORM_BUFFER)
-        .descriptor_count(swapchain_length)];
+// one set per frame in flight
+pub fn create_descriptor_pool(device: &DeviceLoader, frames_in_flight: u32) -> vk::DescriptorPool {
+    // ubo, light, shadow & object uniform blocks plus the shadow map per set
+    let pool_size = &[
+        vk::DescriptorPoolSizeBuilder::new()
+            ._type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+            .descriptor_count(frames_in_flight * 4),
+        vk::DescriptorPoolSizeBuilder::new()
+            ._type(vk::DescriptorType::SAMPLED_IMAGE)
+            .descriptor_count(frames_in_flight),
+        vk::DescriptorPoolSizeBuilder::new()
+            ._type(vk::DescriptorType::SAMPLER)
+            .descriptor_count(frames_in_flight),
+    ];
 
     let pool_info = vk::DescriptorPoolCreateInfoBuilder::new()
         .pool_sizes(pool_size)
-        .max_sets(swapchain_length);
+        .max_sets(frames_in_flight);
 
     unsafe { device.create_descriptor_pool(&pool_info, None, None) }
         .expect("Failed to create descriptor set!")
 }
 
+// the uniform blocks are bound at their offsets in the uniform ring
 pub fn create_descriptor_set_layout(device: &DeviceLoader) -> vk::DescriptorSetLayout {
-    let binding = &[vk::DescriptorSetLayoutBindingBuilder::new()
-        .binding(0)
-        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-        .descriptor_count(1)
-        .stage_flags(vk::ShaderStageFlags::VERTEX)];
+    let binding = &[
+        vk::DescriptorSetLayoutBindingBuilder::new()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+            .descriptor_count(1)
+            // fragment shader reads view mode
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT),
+        // lights
+        vk::DescriptorSetLayoutBindingBuilder::new()
+            .binding(1)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        // shadow matrices, vertex shader of the shadow pass reads them too
+        vk::DescriptorSetLayoutBindingBuilder::new()
+            .binding(2)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT),
+        // shadow map array
+        vk::DescriptorSetLayoutBindingBuilder::new()
+            .binding(3)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        // depth compare sampler
+        vk::DescriptorSetLayoutBindingBuilder::new()
+            .binding(4)
+            .descriptor_type(vk::DescriptorType::SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        // per object transform, rebound at a new offset for every draw
+        vk::DescriptorSetLayoutBindingBuilder::new()
+            .binding(5)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX),
+    ];
 
     let create_info = vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(binding);
 
@@ -162,10 +284,11 @@ pub fn create_descriptor_sets(
     device: &DeviceLoader,
     layout: &vk::DescriptorSetLayout,
     pool: &vk::DescriptorPool,
-    uniform_buffer: &Vec<vk::Buffer>,
-    swapchain_length: usize,
+    uniform_buffers: &[vk::Buffer],
+    shadow_map: vk::ImageView,
+    shadow_sampler: vk::Sampler,
 ) -> Vec<vk::DescriptorSet> {
-    let layouts: &Vec<vk::DescriptorSetLayout> = &vec![*layout; swapchain_length];
+    let layouts: &Vec<vk::DescriptorSetLayout> = &vec![*layout; uniform_buffers.len()];
 
     let alloc_info = vk::DescriptorSetAllocateInfoBuilder::new()
         .set_layouts(layouts)
@@ -174,41 +297,243 @@ pub fn create_descriptor_sets(
     let descriptor_sets = unsafe { device.allocate_descriptor_sets(&alloc_info) }
         .expect("Failed to allocate descriptor sets!");
 
-    for (index, set) in descriptor_sets.iter().enumerate() {
-        let buffer_info = &[vk::DescriptorBufferInfoBuilder::new()
-            .buffer(uniform_buffer[index])
-            .range(vk::WHOLE_SIZE)];
-        let descriptor_write = &[vk::WriteDescriptorSetBuilder::new()
-            .dst_set(*set)
-            .dst_binding(0)
-            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-            .buffer_info(buffer_info)];
+    for (set, uniform_buffer) in descriptor_sets.iter().zip(uniform_buffers) {
+        write_uniform_descriptors(device, *set, *uniform_buffer);
+
+        let shadow_map_info = &[vk::DescriptorImageInfoBuilder::new()
+            .image_view(shadow_map)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+        let shadow_sampler_info = &[vk::DescriptorImageInfoBuilder::new().sampler(shadow_sampler)];
+        let descriptor_write = &[
+            vk::WriteDescriptorSetBuilder::new()
+                .dst_set(*set)
+                .dst_binding(3)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .image_info(shadow_map_info),
+            vk::WriteDescriptorSetBuilder::new()
+                .dst_set(*set)
+                .dst_binding(4)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .image_info(shadow_sampler_info),
+        ];
 
         unsafe {
             device.update_descriptor_sets(descriptor_write, &[]);
         }
     }
+
+    debug::set_object_names(device, &descriptor_sets, "frame descriptor set");
+
     descriptor_sets
 }
 
-pub fn create_graphics_pipeline<'a>(
+// points the set's uniform bindings at the frame's region of the ring, the dynamic offsets pick
+// the blocks, the set mustn't be in use by the gpu
+pub fn write_uniform_descriptors(
     device: &DeviceLoader,
+    set: vk::DescriptorSet,
+    uniform_buffer: vk::Buffer,
+) {
+    let buffer_info = &[vk::DescriptorBufferInfoBuilder::new()
+        .buffer(uniform_buffer)
+        .range(size_of::<model::UniformBufferObject>() as u64)];
+    let light_buffer_info = &[vk::DescriptorBufferInfoBuilder::new()
+        .buffer(uniform_buffer)
+        .range(size_of::<lighting::LightUniformBlock>() as u64)];
+    let shadow_buffer_info = &[vk::DescriptorBufferInfoBuilder::new()
+        .buffer(uniform_buffer)
+        .range(size_of::<shadow::ShadowUniformBlock>() as u64)];
+    let object_buffer_info = &[vk::DescriptorBufferInfoBuilder::new()
+        .buffer(uniform_buffer)
+        .range(size_of::<model::ObjectUniformBlock>() as u64)];
+    let descriptor_write = &[
+        vk::WriteDescriptorSetBuilder::new()
+            .dst_set(set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+            .buffer_info(buffer_info),
+        vk::WriteDescriptorSetBuilder::new()
+            .dst_set(set)
+            .dst_binding(1)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+            .buffer_info(light_buffer_info),
+        vk::WriteDescriptorSetBuilder::new()
+            .dst_set(set)
+            .dst_binding(2)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+            .buffer_info(shadow_buffer_info),
+        vk::WriteDescriptorSetBuilder::new()
+            .dst_set(set)
+            .dst_binding(5)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+            .buffer_info(object_buffer_info),
+    ];
+
+    unsafe {
+        device.update_descriptor_sets(descriptor_write, &[]);
+    }
+}
+
+// one pipeline per way of drawing the mesh, all sharing a layout & render pass
+pub struct Pipelines {
+    pub shaded: Owned<vk::Pipeline>,
+    // None if fillModeNonSolid isn't supported
+    pub wireframe: Option<Owned<vk::Pipeline>>,
+    pub points: Owned<vk::Pipeline>,
+    // fullscreen triangle drawn before the mesh
+    pub skybox: Owned<vk::Pipeline>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn create_graphics_pipeline(
+    device: &Rc<handle::Device>,
+    pipeline_cache: vk::PipelineCache,
     shader_vert: vk::ShaderModule,
     shader_frag: vk::ShaderModule,
-    descriptor_set_layout: &vk::DescriptorSetLayout,
-    format: vk::SurfaceFormatKHR,
-) -> (vk::Pipeline, vk::PipelineLayout, vk::RenderPass) {
-    // vertex info
-    let binding_descriptions = [model::Vertex::get_binding_descriptions()];
-    let attribute_descriptions = model::Vertex::get_attribute_descriptions();
+    shader_wire_frag: vk::ShaderModule,
+    shader_skybox_vert: vk::ShaderModule,
+    shader_skybox_frag: vk::ShaderModule,
+    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    samples: vk::SampleCountFlagBits,
+    features: &vk::PhysicalDeviceFeatures,
+) -> (Pipelines, Owned<vk::PipelineLayout>, Owned<vk::RenderPass>) {
+    let pipeline_layout = Owned::new(
+        device,
+        create_pipeline_layout(device, descriptor_set_layouts),
+        "scene pipeline layout",
+    );
+
+    // create render_pass
+    let render_pass = Owned::new(
+        device,
+        create_render_pass(color_format, depth_format, samples, device),
+        "scene render pass",
+    );
+
+    // mesh vertices & per-instance data
+    let binding_descriptions = [
+        model::Vertex::get_binding_descriptions(),
+        model::InstanceData::get_binding_descriptions(),
+    ];
+    let attribute_descriptions: Vec<_> = model::Vertex::get_attribute_descriptions()
+        .iter()
+        .chain(model::InstanceData::get_attribute_descriptions().iter())
+        .copied()
+        .collect();
 
     let vertex_input = vk::PipelineVertexInputStateCreateInfoBuilder::new()
         .vertex_binding_descriptions(&binding_descriptions)
         .vertex_attribute_descriptions(&attribute_descriptions);
 
+    let shaded = create_pipeline(
+        device,
+        pipeline_cache,
+        shader_vert,
+        shader_frag,
+        &vertex_input,
+        *pipeline_layout,
+        *render_pass,
+        vk::PrimitiveTopology::TRIANGLE_LIST,
+        vk::PolygonMode::FILL,
+        samples,
+        &create_depth_stencil(true, true),
+    );
+
+    // LINE polygon mode requires fillModeNonSolid
+    let wireframe = if features.fill_mode_non_solid == vk::TRUE {
+        Some(create_pipeline(
+            device,
+            pipeline_cache,
+            shader_vert,
+            shader_wire_frag,
+            &vertex_input,
+            *pipeline_layout,
+            *render_pass,
+            vk::PrimitiveTopology::TRIANGLE_LIST,
+            vk::PolygonMode::LINE,
+            samples,
+            // drawn over the shaded mesh at the same depth
+            &create_depth_stencil(true, false),
+        ))
+    } else {
+        None
+    };
+
+    // point list topology draws every index as a point so doesn't need fillModeNonSolid
+    let points = create_pipeline(
+        device,
+        pipeline_cache,
+        shader_vert,
+        shader_wire_frag,
+        &vertex_input,
+        *pipeline_layout,
+        *render_pass,
+        vk::PrimitiveTopology::POINT_LIST,
+        vk::PolygonMode::FILL,
+        samples,
+        &create_depth_stencil(true, true),
+    );
+
+    // vertices are generated from gl_VertexIndex, always behind everything
+    let skybox = create_pipeline(
+        device,
+        pipeline_cache,
+        shader_skybox_vert,
+        shader_skybox_frag,
+        &vk::PipelineVertexInputStateCreateInfoBuilder::new(),
+        *pipeline_layout,
+        *render_pass,
+        vk::PrimitiveTopology::TRIANGLE_LIST,
+        vk::PolygonMode::FILL,
+        samples,
+        &create_depth_stencil(false, false),
+    );
+
+    (
+        Pipelines {
+            shaded: Owned::new(device, shaded, "shaded pipeline"),
+            wireframe: wireframe
+                .map(|wireframe| Owned::new(device, wireframe, "wireframe pipeline")),
+            points: Owned::new(device, points, "points pipeline"),
+            skybox: Owned::new(device, skybox, "skybox pipeline"),
+        },
+        pipeline_layout,
+        render_pass,
+    )
+}
+
+// less or equal so passes drawing the same geometry again pass the test
+fn create_depth_stencil<'a>(
+    depth_test: bool,
+    depth_write: bool,
+) -> vk::PipelineDepthStencilStateCreateInfoBuilder<'a> {
+    vk::PipelineDepthStencilStateCreateInfoBuilder::new()
+        .depth_test_enable(depth_test)
+        .depth_write_enable(depth_write)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+        .depth_bounds_test_enable(false)
+        .stencil_test_enable(false)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_pipeline(
+    device: &DeviceLoader,
+    pipeline_cache: vk::PipelineCache,
+    shader_vert: vk::ShaderModule,
+    shader_frag: vk::ShaderModule,
+    vertex_input: &vk::PipelineVertexInputStateCreateInfoBuilder,
+    pipeline_layout: vk::PipelineLayout,
+    render_pass: vk::RenderPass,
+    topology: vk::PrimitiveTopology,
+    polygon_mode: vk::PolygonMode,
+    samples: vk::SampleCountFlagBits,
+    depth_stencil: &vk::PipelineDepthStencilStateCreateInfoBuilder,
+) -> vk::Pipeline {
     // create fixed functions
-    let (input_assembly, rasterizer, multisampling, color_blend_attachments, pipeline_layout) =
-        create_fixed_functions(device, &[*descriptor_set_layout]);
+    let (input_assembly, rasterizer, multisampling, color_blend_attachments) =
+        create_fixed_functions(topology, polygon_mode, samples);
 
     // make the borrow checker happy and create it here :)
     let viewport_state = vk::PipelineViewportStateCreateInfoBuilder::new()
@@ -232,9 +557,6 @@ pub fn create_graphics_pipeline<'a>(
             .name(unsafe { CStr::from_ptr(SHADER_ENTRY) }),
     ];
 
-    // create render_pass
-    let render_pass = create_render_pass(format, device);
-
     // dynamic states (for resizing)
     let dynamic_states = vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
 
@@ -244,11 +566,12 @@ pub fn create_graphics_pipeline<'a>(
     // le big info struct
     let pipeline_info = vk::GraphicsPipelineCreateInfoBuilder::new()
         .stages(&shader_stages)
-        .vertex_input_state(&vertex_input)
+        .vertex_input_state(vertex_input)
         .input_assembly_state(&input_assembly)
         .viewport_state(&viewport_state)
         .rasterization_state(&rasterizer)
         .multisample_state(&multisampling)
+        .depth_stencil_state(depth_stencil)
         .color_blend_state(&color_blending)
         .dynamic_state(&dynamic_states_info)
         .layout(pipeline_layout)
@@ -256,8 +579,25 @@ pub fn create_graphics_pipeline<'a>(
         .subpass(0);
 
     // graphics pipeline
-    let pipeline = unsafe { device.create_graphics_pipelines(None, &[pipeline_info], None) }
-        .expect("Failed to create graphics pipeline!")[0];
+    unsafe { device.create_graphics_pipelines(Some(pipeline_cache), &[pipeline_info], None) }
+        .expect("Failed to create graphics pipeline!")[0]
+}
 
-    (pipeline, pipeline_layout, render_pass)
+pub fn create_compute_pipeline(
+    device: &DeviceLoader,
+    pipeline_cache: vk::PipelineCache,
+    shader: vk::ShaderModule,
+    pipeline_layout: vk::PipelineLayout,
+) -> vk::Pipeline {
+    let stage = vk::PipelineShaderStageCreateInfoBuilder::new()
+        .stage(vk::ShaderStageFlagBits::COMPUTE)
+        .module(shader)
+        .name(unsafe { CStr::from_ptr(SHADER_ENTRY) });
+
+    let pipeline_info = vk::ComputePipelineCreateInfoBuilder::new()
+        .stage(*stage)
+        .layout(pipeline_layout);
+
+    unsafe { device.create_compute_pipelines(Some(pipeline_cache), &[pipeline_info], None) }
+        .expect("Failed to create compute pipeline!")[0]
 }
@@ -0,0 +1,974 @@
+use crate::application::graph::{Access, RenderGraph, ResourceId};
+use crate::application::handle::{self, Owned};
+use crate::application::pipeline::{self, SHADER_ENTRY};
+use crate::application::presentation::OutputMode;
+use crate::application::upload::Uploader;
+use crate::application::{debug, render, texture};
+use crate::Application;
+
+use erupt::{vk, DeviceLoader, InstanceLoader};
+
+use core::ffi::c_void;
+use std::ffi::CStr;
+use std::mem::size_of;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::str::FromStr;
+
+// shader spvs
+pub const SHADER_POST_VERT: &[u8] = include_bytes!("../../res/shaders/post_vert.spv");
+pub const SHADER_BLOOM_THRESHOLD: &[u8] = include_bytes!("../../res/shaders/bloom_threshold.spv");
+pub const SHADER_BLUR: &[u8] = include_bytes!("../../res/shaders/blur.spv");
+pub const SHADER_TONEMAP: &[u8] = include_bytes!("../../res/shaders/tonemap.spv");
+pub const SHADER_FXAA: &[u8] = include_bytes!("../../res/shaders/fxaa.spv");
+
+// the scene is lit into this before post processing
+pub const HDR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+// bloom only picks up what's brighter than white
+const BLOOM_THRESHOLD: f32 = 1.0;
+const BLOOM_INTENSITY: f32 = 0.3;
+
+// identity lut used when no color grading lut is given
+const DEFAULT_LUT_SIZE: u32 = 16;
+
+// must match the FLAG_* constants in the post shaders
+const FLAG_BLOOM: u32 = 1;
+const FLAG_COLOR_GRADING: u32 = 2;
+const FLAG_LINEAR_OUTPUT: u32 = 4;
+const FLAG_HDR10: u32 = 8;
+const FLAG_SCRGB: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tonemapper {
+    Aces,
+    Reinhard,
+    // no curve, anything over 1 is clipped
+    Clamp,
+}
+
+impl Tonemapper {
+    // must match the TONEMAPPER_* constants in tonemap.frag
+    fn shader_index(self) -> u32 {
+        match self {
+            Tonemapper::Aces => 0,
+            Tonemapper::Reinhard => 1,
+            Tonemapper::Clamp => 2,
+        }
+    }
+}
+
+impl FromStr for Tonemapper {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "aces" => Ok(Tonemapper::Aces),
+            "reinhard" => Ok(Tonemapper::Reinhard),
+            "clamp" | "none" => Ok(Tonemapper::Clamp),
+            _ => Err(format!(
+                "Unknown tonemapper {:?}, expected aces, reinhard or clamp",
+                name
+            )),
+        }
+    }
+}
+
+// which passes make up the chain & their parameters
+#[derive(Debug, Clone)]
+pub struct PostSettings {
+    pub tonemapper: Tonemapper,
+    pub exposure: f32,
+    pub gamma: f32,
+    pub bloom: bool,
+    pub fxaa: bool,
+    // size * size wide, size high png, one square per slice of blue
+    pub color_lut: Option<PathBuf>,
+    // color space the swapchain ended up in
+    pub output: OutputMode,
+    // hdr outputs only, nits that 1.0 in the scene maps to & the display's peak
+    pub paper_white: f32,
+    pub max_luminance: f32,
+}
+
+// same block is used by every post shader
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PostConstants {
+    // of the image being sampled
+    texel_size: [f32; 2],
+    // blur direction
+    direction: [f32; 2],
+    exposure: f32,
+    gamma: f32,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    tonemapper: u32,
+    flags: u32,
+    paper_white: f32,
+    max_luminance: f32,
+}
+
+// graph images used by the chain, None if the pass writing them is disabled
+pub struct PostImages {
+    // bright parts, blurred horizontally, blurred both ways
+    pub bloom: Option<[ResourceId; 3]>,
+    pub display: Option<ResourceId>,
+}
+
+// objects that don't depend on the swapchain size
+pub struct PostProcess {
+    pub settings: PostSettings,
+    threshold_pipeline: Owned<vk::Pipeline>,
+    blur_pipeline: Owned<vk::Pipeline>,
+    tonemap_pipeline: Owned<vk::Pipeline>,
+    fxaa_pipeline: Owned<vk::Pipeline>,
+    pipeline_layout: Owned<vk::PipelineLayout>,
+    set_layout: Owned<vk::DescriptorSetLayout>,
+    hdr_render_pass: Owned<vk::RenderPass>,
+    display_render_pass: Owned<vk::RenderPass>,
+    output_render_pass: Owned<vk::RenderPass>,
+    _shaders: Vec<Owned<vk::ShaderModule>>,
+    sampler: Owned<vk::Sampler>,
+    color_lut: texture::Texture,
+    // swapchain is srgb so does the display encoding itself
+    srgb_output: bool,
+}
+
+// everything needed to record one fullscreen pass, the render pass & pipeline belong to
+// PostProcess
+struct FullscreenPass {
+    render_pass: vk::RenderPass,
+    pipeline: vk::Pipeline,
+    // one per swapchain image for the pass writing to the swapchain, otherwise one
+    framebuffers: Vec<Owned<vk::Framebuffer>>,
+    descriptor_set: vk::DescriptorSet,
+    extent: vk::Extent2D,
+    input_extent: vk::Extent2D,
+    direction: [f32; 2],
+    linear_output: bool,
+}
+
+// framebuffers & descriptor sets, recreated with the swapchain
+pub struct PostTargets {
+    // threshold, horizontal & vertical blur
+    bloom: Option<[FullscreenPass; 3]>,
+    tonemap: FullscreenPass,
+    fxaa: Option<FullscreenPass>,
+    // frees the passes' descriptor sets
+    _descriptor_pool: Owned<vk::DescriptorPool>,
+}
+
+fn is_srgb(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::B8G8R8A8_SRGB | vk::Format::R8G8B8A8_SRGB | vk::Format::A8B8G8R8_SRGB_PACK32
+    )
+}
+
+// tonemapped image read by fxaa, already display encoded so unorm rather than srgb,
+// hdr outputs need the extra precision & range of the swapchain formats
+fn get_display_format(output: OutputMode) -> vk::Format {
+    match output {
+        OutputMode::Sdr => vk::Format::R8G8B8A8_UNORM,
+        OutputMode::Hdr10 => vk::Format::A2B10G10R10_UNORM_PACK32,
+        OutputMode::ScRgb => vk::Format::R16G16B16A16_SFLOAT,
+    }
+}
+
+fn half_extent(extent: vk::Extent2D) -> vk::Extent2D {
+    vk::Extent2D {
+        width: (extent.width / 2).max(1),
+        height: (extent.height / 2).max(1),
+    }
+}
+
+// adds the chain after the lit pass, the last pass writes the swapchain image
+pub fn add_passes(
+    graph: &mut RenderGraph,
+    settings: &PostSettings,
+    hdr: ResourceId,
+    swapchain_image: ResourceId,
+    extent: vk::Extent2D,
+) -> PostImages {
+    let bloom = if settings.bloom {
+        let bloom_extent = half_extent(extent);
+        let bright = graph.add_transient_image(
+            "bloom",
+            HDR_FORMAT,
+            bloom_extent,
+            vk::ImageAspectFlags::COLOR,
+        );
+        let blurred_x = graph.add_transient_image(
+            "bloom blur x",
+            HDR_FORMAT,
+            bloom_extent,
+            vk::ImageAspectFlags::COLOR,
+        );
+        let blurred = graph.add_transient_image(
+            "bloom blur",
+            HDR_FORMAT,
+            bloom_extent,
+            vk::ImageAspectFlags::COLOR,
+        );
+
+        let threshold = graph.add_pass("bloom threshold", record_bloom_threshold);
+        graph.read(threshold, hdr, Access::SampledFragment);
+        graph.write(threshold, bright, Access::ColorAttachment);
+
+        let blur_x = graph.add_pass("bloom blur x", record_bloom_blur_x);
+        graph.read(blur_x, bright, Access::SampledFragment);
+        graph.write(blur_x, blurred_x, Access::ColorAttachment);
+
+        let blur_y = graph.add_pass("bloom blur y", record_bloom_blur_y);
+        graph.read(blur_y, blurred_x, Access::SampledFragment);
+        graph.write(blur_y, blurred, Access::ColorAttachment);
+
+        Some([bright, blurred_x, blurred])
+    } else {
+        None
+    };
+
+    let display = if settings.fxaa {
+        Some(graph.add_transient_image(
+            "display color",
+            get_display_format(settings.output),
+            extent,
+            vk::ImageAspectFlags::COLOR,
+        ))
+    } else {
+        None
+    };
+
+    // tonemapping, gamma & color grading in one pass
+    let tonemap = graph.add_pass("tonemap", record_tonemap);
+    graph.read(tonemap, hdr, Access::SampledFragment);
+    if let Some([_, _, blurred]) = bloom {
+        graph.read(tonemap, blurred, Access::SampledFragment);
+    }
+    graph.write(
+        tonemap,
+        display.unwrap_or(swapchain_image),
+        Access::ColorAttachment,
+    );
+
+    if let Some(display) = display {
+        let fxaa = graph.add_pass("fxaa", record_fxaa);
+        graph.read(fxaa, display, Access::SampledFragment);
+        graph.write(fxaa, swapchain_image, Access::ColorAttachment);
+    }
+
+    PostImages { bloom, display }
+}
+
+// contents are always fully overwritten, the graph handles layouts
+fn create_fullscreen_render_pass(device: &DeviceLoader, format: vk::Format) -> vk::RenderPass {
+    let attachments = vec![vk::AttachmentDescriptionBuilder::new()
+        .format(format)
+        .samples(vk::SampleCountFlagBits::_1)
+        .load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+
+    let color_attachment_references = vec![vk::AttachmentReferenceBuilder::new()
+        .attachment(0)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+
+    let subpasses =
+        vec![vk::SubpassDescriptionBuilder::new().color_attachments(&color_attachment_references)];
+
+    let render_pass_info = vk::RenderPassCreateInfoBuilder::new()
+        .attachments(&attachments)
+        .subpasses(&subpasses);
+
+    unsafe { device.create_render_pass(&render_pass_info, None, None) }
+        .expect("Failed to create post render pass!")
+}
+
+fn create_post_set_layout(device: &DeviceLoader) -> vk::DescriptorSetLayout {
+    let binding = |binding: u32, descriptor_type: vk::DescriptorType| {
+        vk::DescriptorSetLayoutBindingBuilder::new()
+            .binding(binding)
+            .descriptor_type(descriptor_type)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+    };
+
+    let bindings = &[
+        binding(0, vk::DescriptorType::SAMPLER),
+        // previous pass' output
+        binding(1, vk::DescriptorType::SAMPLED_IMAGE),
+        // blurred bloom
+        binding(2, vk::DescriptorType::SAMPLED_IMAGE),
+        // color grading lut
+        binding(3, vk::DescriptorType::SAMPLED_IMAGE),
+    ];
+
+    let create_info = vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(bindings);
+
+    unsafe { device.create_descriptor_set_layout(&create_info, None, None) }
+        .expect("Failed to create post descriptor set layout!")
+}
+
+fn create_post_pipeline_layout(
+    device: &DeviceLoader,
+    set_layout: vk::DescriptorSetLayout,
+) -> vk::PipelineLayout {
+    let set_layouts = &[set_layout];
+
+    let push_constant_ranges = &[vk::PushConstantRangeBuilder::new()
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        .offset(0)
+        .size(size_of::<PostConstants>() as u32)];
+
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfoBuilder::new()
+        .set_layouts(set_layouts)
+        .push_constant_ranges(push_constant_ranges);
+
+    unsafe { device.create_pipeline_layout(&pipeline_layout_info, None, None) }
+        .expect("Failed to create post pipeline layout!")
+}
+
+// fullscreen triangle without blending or depth, viewport is dynamic as passes differ in size
+fn create_fullscreen_pipeline(
+    device: &DeviceLoader,
+    pipeline_cache: vk::PipelineCache,
+    shader_vert: vk::ShaderModule,
+    shader_frag: vk::ShaderModule,
+    pipeline_layout: vk::PipelineLayout,
+    render_pass: vk::RenderPass,
+) -> vk::Pipeline {
+    let vertex_input = vk::PipelineVertexInputStateCreateInfoBuilder::new();
+
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfoBuilder::new()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+
+    let viewport_state = vk::PipelineViewportStateCreateInfoBuilder::new()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterizer = vk::PipelineRasterizationStateCreateInfoBuilder::new()
+        .depth_clamp_enable(false)
+        .rasterizer_discard_enable(false)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE);
+
+    let multisampling = vk::PipelineMultisampleStateCreateInfoBuilder::new()
+        .sample_shading_enable(false)
+        .rasterization_samples(vk::SampleCountFlagBits::_1);
+
+    let color_blend_attachments = vec![vk::PipelineColorBlendAttachmentStateBuilder::new()
+        .color_write_mask(
+            vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B
+                | vk::ColorComponentFlags::A,
+        )
+        .blend_enable(false)];
+
+    let color_blending = vk::PipelineColorBlendStateCreateInfoBuilder::new()
+        .logic_op_enable(false)
+        .attachments(&color_blend_attachments);
+
+    let shader_stages = vec![
+        vk::PipelineShaderStageCreateInfoBuilder::new()
+            .stage(vk::ShaderStageFlagBits::VERTEX)
+            .module(shader_vert)
+            .name(unsafe { CStr::from_ptr(SHADER_ENTRY) }),
+        vk::PipelineShaderStageCreateInfoBuilder::new()
+            .stage(vk::ShaderStageFlagBits::FRAGMENT)
+            .module(shader_frag)
+            .name(unsafe { CStr::from_ptr(SHADER_ENTRY) }),
+    ];
+
+    let dynamic_states = vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+
+    let dynamic_states_info =
+        vk::PipelineDynamicStateCreateInfoBuilder::new().dynamic_states(&dynamic_states);
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfoBuilder::new()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input)
+        .input_assembly_state(&input_assembly)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterizer)
+        .multisample_state(&multisampling)
+        .color_blend_state(&color_blending)
+        .dynamic_state(&dynamic_states_info)
+        .layout(pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(0);
+
+    unsafe { device.create_graphics_pipelines(Some(pipeline_cache), &[pipeline_info], None) }
+        .expect("Failed to create post pipeline!")[0]
+}
+
+// bilinear & clamped so taps past the edge repeat the border
+fn create_post_sampler(device: &DeviceLoader) -> vk::Sampler {
+    let sampler_info = vk::SamplerCreateInfoBuilder::new()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .anisotropy_enable(false)
+        .max_anisotropy(1.0)
+        .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+        .unnormalized_coordinates(false)
+        .compare_enable(false)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+        .min_lod(0.0)
+        .max_lod(0.0);
+
+    unsafe { device.create_sampler(&sampler_info, None, None) }
+        .expect("Failed to create post sampler!")
+}
+
+// strip where every color maps to itself
+fn create_identity_lut() -> (vk::Extent2D, Vec<u8>) {
+    let size = DEFAULT_LUT_SIZE;
+    let value = |index: u32| (index * 255 / (size - 1)) as u8;
+
+    let pixels = (0..size)
+        .flat_map(|green| {
+            (0..size * size).flat_map(move |x| {
+                let (blue, red) = (x / size, x % size);
+                [value(red), value(green), value(blue), 255]
+            })
+        })
+        .collect();
+
+    let extent = vk::Extent2D {
+        width: size * size,
+        height: size,
+    };
+
+    (extent, pixels)
+}
+
+fn load_color_lut(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    device: &Rc<handle::Device>,
+    uploader: &mut Uploader,
+    path: Option<&PathBuf>,
+) -> Option<texture::Texture> {
+    let path = path?;
+
+    let dimensions = match image::image_dimensions(path) {
+        Ok(dimensions) => dimensions,
+        Err(error) => {
+            log::warn!(
+                "Failed to load color lut {:?}, using identity - {}",
+                path,
+                error
+            );
+            return None;
+        }
+    };
+
+    if dimensions.0 != dimensions.1 * dimensions.1 {
+        log::warn!(
+            "Color lut {:?} must be size * size wide & size high, got {}x{}, using identity",
+            path,
+            dimensions.0,
+            dimensions.1
+        );
+        return None;
+    }
+
+    // lut values are display encoded already
+    match texture::load_texture(
+        instance,
+        physical_device,
+        device,
+        uploader,
+        path,
+        vk::Format::R8G8B8A8_UNORM,
+        false,
+        "color lut",
+    ) {
+        Ok(texture) => Some(texture),
+        Err(error) => {
+            log::warn!(
+                "Failed to load color lut {:?}, using identity - {}",
+                path,
+                error
+            );
+            None
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn create_post_process(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    device: &Rc<handle::Device>,
+    uploader: &mut Uploader,
+    pipeline_cache: vk::PipelineCache,
+    output_format: vk::Format,
+    mut settings: PostSettings,
+) -> PostProcess {
+    let render_pass = |format: vk::Format, name: &str| {
+        Owned::new(device, create_fullscreen_render_pass(device, format), name)
+    };
+    let hdr_render_pass = render_pass(HDR_FORMAT, "hdr fullscreen render pass");
+    let display_render_pass = render_pass(
+        get_display_format(settings.output),
+        "display fullscreen render pass",
+    );
+    let output_render_pass = render_pass(output_format, "output fullscreen render pass");
+
+    let set_layout = Owned::new(device, create_post_set_layout(device), "post set layout");
+    let pipeline_layout = create_post_pipeline_layout(device, *set_layout);
+    let pipeline_layout = Owned::new(device, pipeline_layout, "post pipeline layout");
+
+    let shaders: Vec<_> = [
+        (SHADER_POST_VERT, "post vertex shader"),
+        (SHADER_BLOOM_THRESHOLD, "bloom threshold shader"),
+        (SHADER_BLUR, "blur shader"),
+        (SHADER_TONEMAP, "tonemap shader"),
+        (SHADER_FXAA, "fxaa shader"),
+    ]
+    .iter()
+    .map(|(spv, name)| Owned::new(device, pipeline::create_shader_module(device, spv), name))
+    .collect();
+
+    let pipeline =
+        |shader_frag: &Owned<vk::ShaderModule>, render_pass: &Owned<vk::RenderPass>, name: &str| {
+            let pipeline = create_fullscreen_pipeline(
+                device,
+                pipeline_cache,
+                *shaders[0],
+                **shader_frag,
+                *pipeline_layout,
+                **render_pass,
+            );
+            Owned::new(device, pipeline, name)
+        };
+
+    // fxaa is last if enabled so tonemapping writes to the intermediate display image
+    let tonemap_render_pass = if settings.fxaa {
+        &display_render_pass
+    } else {
+        &output_render_pass
+    };
+
+    let threshold_pipeline = pipeline(&shaders[1], &hdr_render_pass, "bloom threshold pipeline");
+    let blur_pipeline = pipeline(&shaders[2], &hdr_render_pass, "blur pipeline");
+    let tonemap_pipeline = pipeline(&shaders[3], tonemap_render_pass, "tonemap pipeline");
+    let fxaa_pipeline = pipeline(&shaders[4], &output_render_pass, "fxaa pipeline");
+
+    let sampler = Owned::new(device, create_post_sampler(device), "post sampler");
+
+    // color grading is skipped without a valid lut but something still has to be bound
+    let color_lut = load_color_lut(
+        instance,
+        physical_device,
+        device,
+        uploader,
+        settings.color_lut.as_ref(),
+    );
+    if color_lut.is_none() {
+        settings.color_lut = None;
+    }
+
+    // luts are authored for sdr display encoded color
+    if settings.output.is_hdr() && settings.color_lut.is_some() {
+        println!("Color grading is only supported with sdr output, ignoring lut.");
+        settings.color_lut = None;
+    }
+    let color_lut = color_lut.unwrap_or_else(|| {
+        let (extent, pixels) = create_identity_lut();
+        texture::create_texture(
+            instance,
+            physical_device,
+            device,
+            uploader,
+            extent,
+            vk::Format::R8G8B8A8_UNORM,
+            &pixels,
+            false,
+            "color lut",
+        )
+    });
+
+    PostProcess {
+        settings,
+        threshold_pipeline,
+        blur_pipeline,
+        tonemap_pipeline,
+        fxaa_pipeline,
+        pipeline_layout,
+        set_layout,
+        hdr_render_pass,
+        display_render_pass,
+        output_render_pass,
+        _shaders: shaders,
+        sampler,
+        color_lut,
+        srgb_output: is_srgb(output_format),
+    }
+}
+
+fn create_post_descriptor_pool(device: &DeviceLoader, max_sets: u32) -> vk::DescriptorPool {
+    let pool_sizes = &[
+        vk::DescriptorPoolSizeBuilder::new()
+            ._type(vk::DescriptorType::SAMPLER)
+            .descriptor_count(max_sets),
+        vk::DescriptorPoolSizeBuilder::new()
+            ._type(vk::DescriptorType::SAMPLED_IMAGE)
+            .descriptor_count(max_sets * 3),
+    ];
+
+    let pool_info = vk::DescriptorPoolCreateInfoBuilder::new()
+        .pool_sizes(pool_sizes)
+        .max_sets(max_sets);
+
+    unsafe { device.create_descriptor_pool(&pool_info, None, None) }
+        .expect("Failed to create post descriptor pool!")
+}
+
+// bloom is only read by tonemapping, other passes bind their input there too
+fn create_post_descriptor_set(
+    device: &DeviceLoader,
+    post: &PostProcess,
+    pool: vk::DescriptorPool,
+    input: vk::ImageView,
+    bloom: vk::ImageView,
+) -> vk::DescriptorSet {
+    let set_layouts = &[*post.set_layout];
+    let alloc_info = vk::DescriptorSetAllocateInfoBuilder::new()
+        .set_layouts(set_layouts)
+        .descriptor_pool(pool);
+
+    let descriptor_set = unsafe { device.allocate_descriptor_sets(&alloc_info) }
+        .expect("Failed to allocate post descriptor set!")[0];
+
+    let sampler_info = &[vk::DescriptorImageInfoBuilder::new().sampler(*post.sampler)];
+    let image_info = |view: vk::ImageView| {
+        [vk::DescriptorImageInfoBuilder::new()
+            .image_view(view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)]
+    };
+    let input_info = &image_info(input);
+    let bloom_info = &image_info(bloom);
+    let lut_info = &image_info(*post.color_lut.view);
+
+    let descriptor_writes = &[
+        vk::WriteDescriptorSetBuilder::new()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::SAMPLER)
+            .image_info(sampler_info),
+        vk::WriteDescriptorSetBuilder::new()
+            .dst_set(descriptor_set)
+            .dst_binding(1)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(input_info),
+        vk::WriteDescriptorSetBuilder::new()
+            .dst_set(descriptor_set)
+            .dst_binding(2)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(bloom_info),
+        vk::WriteDescriptorSetBuilder::new()
+            .dst_set(descriptor_set)
+            .dst_binding(3)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(lut_info),
+    ];
+
+    unsafe { device.update_descriptor_sets(descriptor_writes, &[]) };
+
+    descriptor_set
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn create_post_targets(
+    device: &Rc<handle::Device>,
+    post: &PostProcess,
+    graph: &RenderGraph,
+    hdr: ResourceId,
+    images: &PostImages,
+    swapchain_image_views: &[Owned<vk::ImageView>],
+    extent: vk::Extent2D,
+) -> PostTargets {
+    let descriptor_pool = create_post_descriptor_pool(device, 5);
+    let descriptor_pool = Owned::new(device, descriptor_pool, "post descriptor pool");
+    let hdr_view = graph.get_image_view(hdr);
+    let swapchain_image_views: Vec<_> = swapchain_image_views.iter().map(|view| **view).collect();
+
+    let fullscreen_pass = |name: &str,
+                           render_pass: &Owned<vk::RenderPass>,
+                           pipeline: &Owned<vk::Pipeline>,
+                           outputs: &[vk::ImageView],
+                           extent: vk::Extent2D,
+                           (input, input_extent): (vk::ImageView, vk::Extent2D),
+                           bloom: vk::ImageView,
+                           direction: [f32; 2],
+                           linear_output: bool| {
+        let framebuffers = outputs
+            .iter()
+            .map(|&output| render::create_framebuffer(device, **render_pass, &[output], extent))
+            .collect();
+        let descriptor_set =
+            create_post_descriptor_set(device, post, *descriptor_pool, input, bloom);
+        debug::set_object_name(device, descriptor_set, &format!("{} descriptor set", name));
+
+        FullscreenPass {
+            render_pass: **render_pass,
+            pipeline: **pipeline,
+            framebuffers: Owned::new_each(device, framebuffers, &format!("{} framebuffer", name)),
+            descriptor_set,
+            extent,
+            input_extent,
+            direction,
+            linear_output,
+        }
+    };
+
+    let bloom = images.bloom.map(|ids| {
+        let views = ids.map(|id| graph.get_image_view(id));
+        let bloom_extent = half_extent(extent);
+
+        [
+            fullscreen_pass(
+                "bloom threshold",
+                &post.hdr_render_pass,
+                &post.threshold_pipeline,
+                &[views[0]],
+                bloom_extent,
+                (hdr_view, extent),
+                hdr_view,
+                [0.0, 0.0],
+                false,
+            ),
+            fullscreen_pass(
+                "bloom blur x",
+                &post.hdr_render_pass,
+                &post.blur_pipeline,
+                &[views[1]],
+                bloom_extent,
+                (views[0], bloom_extent),
+                views[0],
+                [1.0, 0.0],
+                false,
+            ),
+            fullscreen_pass(
+                "bloom blur y",
+                &post.hdr_render_pass,
+                &post.blur_pipeline,
+                &[views[2]],
+                bloom_extent,
+                (views[1], bloom_extent),
+                views[1],
+                [0.0, 1.0],
+                false,
+            ),
+        ]
+    });
+
+    let bloom_view = images
+        .bloom
+        .map(|ids| graph.get_image_view(ids[2]))
+        .unwrap_or(hdr_view);
+
+    let (tonemap, fxaa) = match images.display {
+        Some(display) => {
+            let display_view = graph.get_image_view(display);
+            (
+                fullscreen_pass(
+                    "tonemap",
+                    &post.display_render_pass,
+                    &post.tonemap_pipeline,
+                    &[display_view],
+                    extent,
+                    (hdr_view, extent),
+                    bloom_view,
+                    [0.0, 0.0],
+                    false,
+                ),
+                Some(fullscreen_pass(
+                    "fxaa",
+                    &post.output_render_pass,
+                    &post.fxaa_pipeline,
+                    &swapchain_image_views,
+                    extent,
+                    (display_view, extent),
+                    display_view,
+                    [0.0, 0.0],
+                    post.srgb_output,
+                )),
+            )
+        }
+        None => (
+            fullscreen_pass(
+                "tonemap",
+                &post.output_render_pass,
+                &post.tonemap_pipeline,
+                &swapchain_image_views,
+                extent,
+                (hdr_view, extent),
+                bloom_view,
+                [0.0, 0.0],
+                post.srgb_output,
+            ),
+            None,
+        ),
+    };
+
+    PostTargets {
+        bloom,
+        tonemap,
+        fxaa,
+        _descriptor_pool: descriptor_pool,
+    }
+}
+
+fn record_fullscreen_pass(
+    app: &Application,
+    command_buffer: vk::CommandBuffer,
+    image_index: usize,
+    pass: &FullscreenPass,
+) {
+    let post = &app.post;
+    let settings = &post.settings;
+
+    let mut flags = 0;
+    if settings.bloom {
+        flags |= FLAG_BLOOM;
+    }
+    if settings.color_lut.is_some() {
+        flags |= FLAG_COLOR_GRADING;
+    }
+    if pass.linear_output {
+        flags |= FLAG_LINEAR_OUTPUT;
+    }
+    match settings.output {
+        OutputMode::Sdr => (),
+        OutputMode::Hdr10 => flags |= FLAG_HDR10,
+        OutputMode::ScRgb => flags |= FLAG_SCRGB,
+    }
+
+    let constants = PostConstants {
+        texel_size: [
+            1.0 / pass.input_extent.width as f32,
+            1.0 / pass.input_extent.height as f32,
+        ],
+        direction: pass.direction,
+        exposure: settings.exposure,
+        gamma: settings.gamma,
+        bloom_threshold: BLOOM_THRESHOLD,
+        bloom_intensity: BLOOM_INTENSITY,
+        tonemapper: settings.tonemapper.shader_index(),
+        flags,
+        paper_white: settings.paper_white,
+        max_luminance: settings.max_luminance,
+    };
+
+    // single framebuffer unless drawing to the swapchain
+    let framebuffer = *pass.framebuffers[image_index % pass.framebuffers.len()];
+
+    let render_area = vk::Rect2D {
+        offset: vk::Offset2D { x: 0, y: 0 },
+        extent: pass.extent,
+    };
+
+    let render_pass_begin_info = vk::RenderPassBeginInfoBuilder::new()
+        .render_pass(pass.render_pass)
+        .framebuffer(framebuffer)
+        .render_area(render_area);
+
+    let viewports = vec![vk::ViewportBuilder::new()
+        .x(0.0)
+        .y(0.0)
+        .width(pass.extent.width as f32)
+        .height(pass.extent.height as f32)
+        .min_depth(0.0)
+        .max_depth(1.0)];
+
+    let scissors = vec![vk::Rect2DBuilder::new()
+        .offset(vk::Offset2D { x: 0, y: 0 })
+        .extent(pass.extent)];
+
+    unsafe {
+        app.device.cmd_begin_render_pass(
+            command_buffer,
+            &render_pass_begin_info,
+            vk::SubpassContents::INLINE,
+        );
+
+        app.device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            pass.pipeline,
+        );
+        app.device.cmd_set_viewport(command_buffer, 0, &viewports);
+        app.device.cmd_set_scissor(command_buffer, 0, &scissors);
+
+        app.device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            *post.pipeline_layout,
+            0,
+            &[pass.descriptor_set],
+            &[],
+        );
+
+        app.device.cmd_push_constants(
+            command_buffer,
+            *post.pipeline_layout,
+            vk::ShaderStageFlags::FRAGMENT,
+            0,
+            size_of::<PostConstants>() as u32,
+            &constants as *const PostConstants as *const c_void,
+        );
+
+        app.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+        app.device.cmd_end_render_pass(command_buffer);
+    }
+}
+
+fn get_bloom_pass(app: &Application, index: usize) -> &FullscreenPass {
+    &app.post_targets
+        .bloom
+        .as_ref()
+        .expect("Failed to find bloom targets!")[index]
+}
+
+fn record_bloom_threshold(
+    app: &Application,
+    command_buffer: vk::CommandBuffer,
+    image_index: usize,
+) {
+    record_fullscreen_pass(app, command_buffer, image_index, get_bloom_pass(app, 0));
+}
+
+fn record_bloom_blur_x(app: &Application, command_buffer: vk::CommandBuffer, image_index: usize) {
+    record_fullscreen_pass(app, command_buffer, image_index, get_bloom_pass(app, 1));
+}
+
+fn record_bloom_blur_y(app: &Application, command_buffer: vk::CommandBuffer, image_index: usize) {
+    record_fullscreen_pass(app, command_buffer, image_index, get_bloom_pass(app, 2));
+}
+
+fn record_tonemap(app: &Application, command_buffer: vk::CommandBuffer, image_index: usize) {
+    record_fullscreen_pass(app, command_buffer, image_index, &app.post_targets.tonemap);
+}
+
+fn record_fxaa(app: &Application, command_buffer: vk::CommandBuffer, image_index: usize) {
+    let fxaa = app
+        .post_targets
+        .fxaa
+        .as_ref()
+        .expect("Failed to find fxaa target!");
+    record_fullscreen_pass(app, command_buffer, image_index, fxaa);
+}
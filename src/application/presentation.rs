@@ -1,17 +1,124 @@
+use crate::application::debug;
+use crate::application::handle::{self, Owned};
+
 use erupt::vk;
 use erupt::vk::{Image, ImageView, SurfaceCapabilitiesKHR, SwapchainKHR};
 use erupt::{DeviceLoader, InstanceLoader};
 
 use std::cmp::{max, min};
+use std::rc::Rc;
+use std::str::FromStr;
+
+// how the swapchain is presented, hdr modes need VK_EXT_swapchain_colorspace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    Sdr,
+    // rec 2020 primaries, st 2084 (pq) encoded absolute luminance
+    Hdr10,
+    // rec 709 primaries, linear with 1.0 at 80 nits & values outside 0-1 allowed
+    ScRgb,
+}
+
+impl OutputMode {
+    // color space & formats the swapchain can use for this mode
+    pub fn get_surface_formats(self) -> (vk::ColorSpaceKHR, &'static [vk::Format]) {
+        match self {
+            OutputMode::Sdr => (
+                vk::ColorSpaceKHR::SRGB_NONLINEAR_KHR,
+                &[vk::Format::B8G8R8A8_SRGB, vk::Format::R8G8B8A8_SRGB],
+            ),
+            OutputMode::Hdr10 => (
+                vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+                &[
+                    vk::Format::A2B10G10R10_UNORM_PACK32,
+                    vk::Format::A2R10G10B10_UNORM_PACK32,
+                ],
+            ),
+            OutputMode::ScRgb => (
+                vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+                &[vk::Format::R16G16B16A16_SFLOAT],
+            ),
+        }
+    }
 
+    // mode the picked surface format ended up with
+    pub fn from_color_space(color_space: vk::ColorSpaceKHR) -> OutputMode {
+        match color_space {
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT => OutputMode::Hdr10,
+            vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => OutputMode::ScRgb,
+            _ => OutputMode::Sdr,
+        }
+    }
+
+    pub fn is_hdr(self) -> bool {
+        self != OutputMode::Sdr
+    }
+}
+
+impl FromStr for OutputMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "sdr" => Ok(OutputMode::Sdr),
+            "hdr10" => Ok(OutputMode::Hdr10),
+            "scrgb" => Ok(OutputMode::ScRgb),
+            _ => Err(format!(
+                "Unknown output {:?}, expected sdr, hdr10 or scrgb",
+                name
+            )),
+        }
+    }
+}
+
+// preferred present mode, FIFO is always supported so is the fallback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentMode {
+    // vsync
+    Fifo,
+    // vsync without blocking, newest frame replaces the queued one
+    Mailbox,
+    // uncapped fps, may tear
+    Immediate,
+}
+
+impl PresentMode {
+    pub fn get_present_mode(self) -> vk::PresentModeKHR {
+        match self {
+            PresentMode::Fifo => vk::PresentModeKHR::FIFO_KHR,
+            PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX_KHR,
+            PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE_KHR,
+        }
+    }
+}
+
+impl FromStr for PresentMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "fifo" => Ok(PresentMode::Fifo),
+            "mailbox" => Ok(PresentMode::Mailbox),
+            "immediate" => Ok(PresentMode::Immediate),
+            _ => Err(format!(
+                "Unknown present mode {:?}, expected fifo, mailbox or immediate",
+                name
+            )),
+        }
+    }
+}
+
+// old_swapchain is retired by the new one & can be dropped once its images are no longer in use
+#[allow(clippy::too_many_arguments)]
 pub fn create_swapchain_and_images(
     instance: &InstanceLoader,
     physical_device: &vk::PhysicalDevice,
     surface: vk::SurfaceKHR,
     format: vk::SurfaceFormatKHR,
     present_mode: vk::PresentModeKHR,
-    device: &DeviceLoader,
-) -> (SwapchainKHR, Vec<Image>, SurfaceCapabilitiesKHR) {
+    device: &Rc<handle::Device>,
+    old_swapchain: Option<SwapchainKHR>,
+) -> (Owned<SwapchainKHR>, Vec<Image>, SurfaceCapabilitiesKHR) {
     // get surface capabilities
     let surface_capabilities = unsafe {
         instance.get_physical_device_surface_capabilities_khr(*physical_device, surface, None)
@@ -41,21 +148,24 @@ pub fn create_swapchain_and_images(
         .composite_alpha(vk::CompositeAlphaFlagBitsKHR::OPAQUE_KHR)
         .present_mode(present_mode)
         .clipped(true)
-        .old_swapchain(vk::SwapchainKHR::null());
+        .old_swapchain(old_swapchain.unwrap_or(vk::SwapchainKHR::null()));
 
     let swapchain = unsafe { device.create_swapchain_khr(&swapchain_info, None, None) }
         .expect("Failed to create swapchain!");
-    let swapchain_images = unsafe { device.get_swapchain_images_khr(swapchain, None) }
+    let swapchain = Owned::new(device, swapchain, "swapchain");
+    let swapchain_images = unsafe { device.get_swapchain_images_khr(*swapchain, None) }
         .expect("Failed to get swapchain images!");
 
+    debug::set_object_names(device, &swapchain_images, "swapchain image");
+
     (swapchain, swapchain_images, surface_capabilities)
 }
 
 pub fn get_image_views(
-    swapchain_images: &Vec<Image>,
-    device: &DeviceLoader,
+    swapchain_images: &[Image],
+    device: &Rc<handle::Device>,
     format: vk::SurfaceFormatKHR,
-) -> Vec<ImageView> {
+) -> Vec<Owned<ImageView>> {
     // don't remap components
     let component_mapping = vk::ComponentMapping {
         r: vk::ComponentSwizzle::IDENTITY,
@@ -89,5 +199,36 @@ pub fn get_image_views(
         })
         .collect();
 
-    swapchain_image_views
+    Owned::new_each(device, swapchain_image_views, "swapchain image view")
+}
+
+// tells the display the mastering primaries & luminance range of what's presented, needs
+// VK_EXT_hdr_metadata & is set again whenever the swapchain is recreated
+pub fn set_hdr_metadata(
+    device: &DeviceLoader,
+    swapchain: SwapchainKHR,
+    output_mode: OutputMode,
+    paper_white: f32,
+    max_luminance: f32,
+) {
+    let xy = |x: f32, y: f32| vk::XYColorEXT { x, y };
+
+    // primaries of the color space, both use a d65 white point
+    let (red, green, blue) = match output_mode {
+        OutputMode::Hdr10 => (xy(0.708, 0.292), xy(0.170, 0.797), xy(0.131, 0.046)),
+        _ => (xy(0.640, 0.330), xy(0.300, 0.600), xy(0.150, 0.060)),
+    };
+
+    let metadata = vk::HdrMetadataEXTBuilder::new()
+        .display_primary_red(red)
+        .display_primary_green(green)
+        .display_primary_blue(blue)
+        .white_point(xy(0.3127, 0.3290))
+        .max_luminance(max_luminance)
+        .min_luminance(0.0)
+        // tonemapping never goes over the peak, most of the frame is around paper white
+        .max_content_light_level(max_luminance)
+        .max_frame_average_light_level(paper_white);
+
+    unsafe { device.set_hdr_metadata_ext(&[swapchain], &[metadata]) };
 }
@@ -1,41 +1,51 @@
 use erupt::vk;
-use erupt::vk::{Framebuffer, ImageView, SurfaceCapabilitiesKHR};
-use erupt::DeviceLoader;
+use erupt::vk::{Framebuffer, ImageView};
+use erupt::{DeviceLoader, InstanceLoader};
 
-use crate::application::model::INDICIES;
+use core::ffi::c_void;
+use std::mem::size_of;
+use std::rc::Rc;
 
-pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
+use crate::application::debug;
+use crate::application::deletion::DeletionQueue;
+use crate::application::environment::ENVIRONMENT_SET;
+use crate::application::frame;
+use crate::application::graph::{Access, RenderGraph, ResourceId};
+use crate::application::handle::{self, Owned};
+use crate::application::indirect;
+use crate::application::material::MATERIAL_SET;
+use crate::application::model::{DrawConstants, MeshRange};
+use crate::application::post::{self, PostImages, PostSettings};
+use crate::application::shadow::{self, ShadowConstants};
+use crate::application::view::ViewMode;
+use crate::Application;
 
-pub fn create_framebuffers(
+pub fn create_framebuffer(
     device: &DeviceLoader,
-    image_views: &Vec<ImageView>,
-    render_pass: &vk::RenderPass,
-    surface_capabilities: &SurfaceCapabilitiesKHR,
-) -> Vec<Framebuffer> {
-    // create framebuffers from single image views
-    let framebuffers: Vec<_> = image_views
-        .into_iter()
-        .map(|view| {
-            let attachments = vec![*view];
-            let framebuffer_info = vk::FramebufferCreateInfoBuilder::new()
-                .render_pass(*render_pass)
-                .attachments(&attachments)
-                .width(surface_capabilities.current_extent.width)
-                .height(surface_capabilities.current_extent.height)
-                .layers(1);
-            unsafe { device.create_framebuffer(&framebuffer_info, None, None) }
-                .expect("Failed to create create framebuffer!")
-        })
-        .collect();
+    render_pass: vk::RenderPass,
+    attachments: &[ImageView],
+    extent: vk::Extent2D,
+) -> Framebuffer {
+    let framebuffer_info = vk::FramebufferCreateInfoBuilder::new()
+        .render_pass(render_pass)
+        .attachments(attachments)
+        .width(extent.width)
+        .height(extent.height)
+        .layers(1);
 
-    // cannot return directly from function due to type inference?
-    framebuffers
+    unsafe { device.create_framebuffer(&framebuffer_info, None, None) }
+        .expect("Failed to create create framebuffer!")
 }
 
-pub fn create_command_pool(device: &DeviceLoader, queue_family: u32) -> vk::CommandPool {
-    // command pool for main graphics queue family
-    let command_pool_info =
-        vk::CommandPoolCreateInfoBuilder::new().queue_family_index(queue_family);
+// command pool for main graphics queue family
+pub fn create_command_pool(
+    device: &DeviceLoader,
+    queue_family: u32,
+    flags: vk::CommandPoolCreateFlags,
+) -> vk::CommandPool {
+    let command_pool_info = vk::CommandPoolCreateInfoBuilder::new()
+        .queue_family_index(queue_family)
+        .flags(flags);
 
     unsafe { device.create_command_pool(&command_pool_info, None, None) }
         .expect("Failed to create command pool!")
@@ -44,152 +54,515 @@ pub fn create_command_pool(device: &DeviceLoader, queue_family: u32) -> vk::Comm
 pub fn allocate_command_buffers(
     device: &DeviceLoader,
     command_pool: &vk::CommandPool,
-    framebuffers: &Vec<Framebuffer>,
+    count: usize,
 ) -> Vec<vk::CommandBuffer> {
     let command_buffer_allocation_info = vk::CommandBufferAllocateInfoBuilder::new()
         .command_pool(*command_pool)
-        .command_buffer_count(framebuffers.len() as u32);
+        .command_buffer_count(count as u32);
 
     unsafe { device.allocate_command_buffers(&command_buffer_allocation_info) }
         .expect("Failed to allocate command buffers!")
 }
 
-pub fn record_command_buffers(
-    device: &DeviceLoader,
-    pipeline: &vk::Pipeline,
-    command_buffers: &Vec<vk::CommandBuffer>,
-    framebuffers: &Vec<Framebuffer>,
-    descriptor_sets: &Vec<vk::DescriptorSet>,
-    pipeline_layout: &vk::PipelineLayout,
-    render_pass: &vk::RenderPass,
-    surface_capabilities: &SurfaceCapabilitiesKHR,
-    vertex_buffer: &vk::Buffer,
-    index_buffer: &vk::Buffer,
-) {
-    // command buffer for each framebuffer
-    for ((command_buffer, framebuffer), descriptor_set) in command_buffers
-        .iter()
-        .zip(framebuffers.iter())
-        .zip(descriptor_sets.iter())
-    {
-        let command_buffer_begin_info = vk::CommandBufferBeginInfoBuilder::new();
-
-        unsafe { device.begin_command_buffer(*command_buffer, &command_buffer_begin_info) }
-            .expect("Failed to begin recording command buffer!");
-
-        // greenish clear color cause black is boring
-        let clear_color = vk::ClearColorValue {
-            float32: [0.1961, 0.6588, 0.3216, 1.0],
-        };
-
-        //  only one attachment (vk::ImageView) in framebuffer
-        let clear_colors = vec![vk::ClearValue { color: clear_color }];
-
-        // do render on the entire screen
-        let screen_size = vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: surface_capabilities.current_extent,
-        };
+// graph images framebuffers & descriptor sets are created from
+pub struct GraphImages {
+    pub depth: ResourceId,
+    pub hdr: ResourceId,
+    // drawn to instead of hdr & resolved into it when multisampling
+    pub msaa: Option<ResourceId>,
+    pub post: PostImages,
+}
+
+impl GraphImages {
+    // in the order of the lit render pass' attachments
+    pub fn get_lit_attachments(&self, graph: &RenderGraph) -> Vec<ImageView> {
+        match self.msaa {
+            Some(msaa) => vec![
+                graph.get_image_view(msaa),
+                graph.get_image_view(self.depth),
+                graph.get_image_view(self.hdr),
+            ],
+            None => vec![
+                graph.get_image_view(self.hdr),
+                graph.get_image_view(self.depth),
+            ],
+        }
+    }
+}
+
+// shadow maps, the lit pass then post processing, rebuilt when the swapchain or lit view
+// modes change, previous is reused where possible & the rest freed after frame last_used
+#[allow(clippy::too_many_arguments)]
+pub fn create_render_graph(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    device: &Rc<handle::Device>,
+    depth_format: vk::Format,
+    extent: vk::Extent2D,
+    view_mode: ViewMode,
+    post_settings: &PostSettings,
+    samples: vk::SampleCountFlagBits,
+    previous: Option<RenderGraph>,
+    deletion_queue: &mut DeletionQueue,
+    last_used: u64,
+) -> (RenderGraph, GraphImages) {
+    let mut graph = RenderGraph::new();
+
+    // presented after the frame so the only output
+    let swapchain_image = graph.import_image(
+        "swapchain",
+        vk::ImageAspectFlags::COLOR,
+        1,
+        |app, image_index| app.swapchain_images[image_index],
+        Access::Acquired,
+        Some(Access::Present),
+    );
+
+    // kept sampleable between frames, layers not drawn this frame are still read
+    let shadow_map = graph.import_image(
+        "shadow map",
+        vk::ImageAspectFlags::DEPTH,
+        shadow::SHADOW_MAP_LAYERS as u32,
+        |app, _| *app.shadow_maps.texture.image,
+        Access::SampledFragment,
+        None,
+    );
+
+    let hdr_color = graph.add_transient_image(
+        "hdr color",
+        post::HDR_FORMAT,
+        extent,
+        vk::ImageAspectFlags::COLOR,
+    );
+
+    let msaa_color = if samples != vk::SampleCountFlagBits::_1 {
+        Some(graph.add_multisampled_image(
+            "msaa color",
+            post::HDR_FORMAT,
+            extent,
+            vk::ImageAspectFlags::COLOR,
+            samples,
+        ))
+    } else {
+        None
+    };
+
+    let depth_buffer = graph.add_multisampled_image(
+        "depth buffer",
+        depth_format,
+        extent,
+        vk::ImageAspectFlags::DEPTH,
+        samples,
+    );
+
+    let shadows = graph.add_pass("shadows", record_shadow_passes);
+    graph.write(shadows, shadow_map, Access::DepthAttachment);
+
+    // unlit debug views don't read the shadow maps so the shadow pass gets culled
+    let lit = graph.add_pass("lit", record_lit_pass);
+    if view_mode.is_lit() {
+        graph.read(lit, shadow_map, Access::SampledFragment);
+    }
+    graph.write(lit, hdr_color, Access::ColorAttachment);
+    graph.write(lit, depth_buffer, Access::DepthAttachment);
+    if let Some(msaa_color) = msaa_color {
+        graph.write(lit, msaa_color, Access::ColorAttachment);
+    }
+
+    let post_images = post::add_passes(
+        &mut graph,
+        post_settings,
+        hdr_color,
+        swapchain_image,
+        extent,
+    );
+
+    graph.compile(
+        instance,
+        physical_device,
+        device,
+        previous,
+        deletion_queue,
+        last_used,
+    );
 
+    let images = GraphImages {
+        depth: depth_buffer,
+        hdr: hdr_color,
+        msaa: msaa_color,
+        post: post_images,
+    };
+
+    (graph, images)
+}
+
+// re-recorded every frame so view mode changes apply immediately
+pub fn record_command_buffer(app: &Application, image_index: usize) {
+    let frame = frame::get_current(app);
+    let command_buffer = frame.command_buffer;
+
+    // the frame's whole command pool was reset, recorded for a single submit
+    unsafe { app.device.reset_command_pool(*frame.command_pool, None) }
+        .expect("Failed to reset frame command pool!");
+
+    let command_buffer_begin_info = vk::CommandBufferBeginInfoBuilder::new()
+        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+    unsafe {
+        app.device
+            .begin_command_buffer(command_buffer, &command_buffer_begin_info)
+    }
+    .expect("Failed to begin recording command buffer!");
+
+    // gpu culling writes the lit pass's draws
+    if let Some(frame) = indirect::get_frame(app) {
+        debug::begin_label(&app.device, command_buffer, "gpu culling");
+        indirect::record_cull(app, command_buffer, frame);
+        debug::end_label(&app.device, command_buffer);
+    }
+
+    app.render_graph.execute(app, command_buffer, image_index);
+
+    unsafe { app.device.end_command_buffer(command_buffer) }
+        .expect("Failed to end recording command buffer!");
+}
+
+// one depth only render pass per shadow map layer in use
+fn record_shadow_passes(app: &Application, command_buffer: vk::CommandBuffer, _image_index: usize) {
+    let shadow_maps = &app.shadow_maps;
+
+    let clear_values = vec![vk::ClearValue {
+        depth_stencil: vk::ClearDepthStencilValue {
+            depth: 1.0,
+            stencil: 0,
+        },
+    }];
+
+    let render_area = vk::Rect2D {
+        offset: vk::Offset2D { x: 0, y: 0 },
+        extent: vk::Extent2D {
+            width: shadow::SHADOW_MAP_SIZE,
+            height: shadow::SHADOW_MAP_SIZE,
+        },
+    };
+
+    for layer in shadow::get_active_layers(&app.lights) {
         let render_pass_begin_info = vk::RenderPassBeginInfoBuilder::new()
-            .render_pass(*render_pass)
-            .framebuffer(*framebuffer)
-            .render_area(screen_size)
-            .clear_values(&clear_colors);
-
-        // updated viewport & scissor
-        let viewports = vec![vk::ViewportBuilder::new()
-            .x(0.0)
-            .y(0.0)
-            .width(surface_capabilities.current_extent.width as f32)
-            .height(surface_capabilities.current_extent.height as f32)
-            .min_depth(0.0)
-            .max_depth(1.0)];
-
-        let scissors = vec![vk::Rect2DBuilder::new()
-            .offset(vk::Offset2D { x: 0, y: 0 })
-            .extent(surface_capabilities.current_extent)];
-
-        // render triangle (which is stored in vertex shader code)
+            .render_pass(*shadow_maps.render_pass)
+            .framebuffer(*shadow_maps.framebuffers[layer as usize])
+            .render_area(render_area)
+            .clear_values(&clear_values);
+
+        debug::begin_label(
+            &app.device,
+            command_buffer,
+            &format!("shadow layer {}", layer),
+        );
+
         unsafe {
-            device.cmd_begin_render_pass(
-                *command_buffer,
+            app.device.cmd_begin_render_pass(
+                command_buffer,
                 &render_pass_begin_info,
                 vk::SubpassContents::INLINE,
             );
 
-            device.cmd_bind_pipeline(*command_buffer, vk::PipelineBindPoint::GRAPHICS, *pipeline);
-
-            // set vertex buffer
-            let vertex_buffers = &[*vertex_buffer];
-            let offsets = [0];
-            device.cmd_bind_vertex_buffers(*command_buffer, 0, vertex_buffers, &offsets);
-
-            device.cmd_bind_index_buffer(*command_buffer, *index_buffer, 0, vk::IndexType::UINT32);
+            app.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                *shadow_maps.pipeline,
+            );
 
-            // set viewport & scissors (incase of resize)
-            device.cmd_set_viewport(*command_buffer, 0, &viewports);
-            device.cmd_set_scissor(*command_buffer, 0, &scissors);
+            let vertex_buffers = &[*app.vertex_buffer, *frame::get_current(app).instance_buffer];
+            app.device
+                .cmd_bind_vertex_buffers(command_buffer, 0, vertex_buffers, &[0, 0]);
+            app.device.cmd_bind_index_buffer(
+                command_buffer,
+                *app.index_buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
 
-            // bind uniform buffer
-            device.cmd_bind_descriptor_sets(
-                *command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                *pipeline_layout,
+            let constants = ShadowConstants::new(layer);
+            app.device.cmd_push_constants(
+                command_buffer,
+                *shadow_maps.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
                 0,
-                &[*descriptor_set],
-                &[],
+                size_of::<ShadowConstants>() as u32,
+                &constants as *const ShadowConstants as *const c_void,
             );
 
-            device.cmd_draw_indexed(*command_buffer, INDICIES.len() as u32, 1, 0, 0, 0);
-            device.cmd_end_render_pass(*command_buffer);
+            // frustum culling doesn't apply, objects out of view can still shadow what's in view
+            let frame = frame::get_current(app);
+            for item in app.scene.get_visible_items() {
+                // light matrices & the object's model matrix
+                app.device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    *shadow_maps.pipeline_layout,
+                    0,
+                    &[frame.descriptor_set],
+                    &frame.get_dynamic_offsets(frame.object_offsets[item.node]),
+                );
+
+                draw_mesh(app, command_buffer, &app.meshes[item.mesh], item.lod);
+            }
 
-            device
-                .end_command_buffer(*command_buffer)
-                .expect("Failed to end recording command buffer!");
+            app.device.cmd_end_render_pass(command_buffer);
         }
+
+        debug::end_label(&app.device, command_buffer);
     }
 }
 
-pub fn create_sync_primitives(
+// sky, then the mesh lit & shadowed into the hdr color image
+fn record_lit_pass(app: &Application, command_buffer: vk::CommandBuffer, _image_index: usize) {
+    let framebuffer = *app.framebuffer;
+    let frame = frame::get_current(app);
+    let extent = app.surface_capabilities.current_extent;
+
+    let clear_color = vk::ClearColorValue {
+        float32: app.clear_color,
+    };
+
+    // hdr color & depth buffer
+    let clear_colors = vec![
+        vk::ClearValue { color: clear_color },
+        vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        },
+    ];
+
+    // do render on the entire screen
+    let screen_size = vk::Rect2D {
+        offset: vk::Offset2D { x: 0, y: 0 },
+        extent,
+    };
+
+    let render_pass_begin_info = vk::RenderPassBeginInfoBuilder::new()
+        .render_pass(*app.render_pass)
+        .framebuffer(framebuffer)
+        .render_area(screen_size)
+        .clear_values(&clear_colors);
+
+    // updated viewport & scissor
+    let viewports = vec![vk::ViewportBuilder::new()
+        .x(0.0)
+        .y(0.0)
+        .width(extent.width as f32)
+        .height(extent.height as f32)
+        .min_depth(0.0)
+        .max_depth(1.0)];
+
+    let scissors = vec![vk::Rect2DBuilder::new()
+        .offset(vk::Offset2D { x: 0, y: 0 })
+        .extent(extent)];
+
+    // shaded pass always drawn bar points mode, wireframe is drawn over the top
+    let pipelines = match app.view_mode {
+        ViewMode::Points => vec![*app.pipelines.points],
+        ViewMode::Wireframe => {
+            let mut pipelines = vec![*app.pipelines.shaded];
+            pipelines.extend(app.pipelines.wireframe.as_deref().copied());
+            pipelines
+        }
+        _ => vec![*app.pipelines.shaded],
+    };
+
+    unsafe {
+        app.device.cmd_begin_render_pass(
+            command_buffer,
+            &render_pass_begin_info,
+            vk::SubpassContents::INLINE,
+        );
+
+        // set vertex buffer & this frame's instance buffer
+        let vertex_buffers = &[*app.vertex_buffer, *frame::get_current(app).instance_buffer];
+        let offsets = [0, 0];
+        app.device
+            .cmd_bind_vertex_buffers(command_buffer, 0, vertex_buffers, &offsets);
+
+        app.device.cmd_bind_index_buffer(
+            command_buffer,
+            *app.index_buffer,
+            0,
+            vk::IndexType::UINT32,
+        );
+
+        // set viewport & scissors (incase of resize)
+        app.device.cmd_set_viewport(command_buffer, 0, &viewports);
+        app.device.cmd_set_scissor(command_buffer, 0, &scissors);
+
+        // bind uniform buffer, the sky reads no object block so any offset will do
+        app.device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            *app.pipeline_layout,
+            0,
+            &[frame.descriptor_set],
+            &frame.get_dynamic_offsets(0),
+        );
+
+        // bind image based lighting
+        app.device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            *app.pipeline_layout,
+            ENVIRONMENT_SET,
+            &[app.environment.descriptor_set],
+            &[],
+        );
+
+        // sky first so the mesh is drawn over it
+        debug::insert_label(&app.device, command_buffer, "skybox");
+        app.device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            *app.pipelines.skybox,
+        );
+        app.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+        for pipeline in pipelines {
+            app.device
+                .cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+
+            if let Some(indirect_frame) = indirect::get_frame(app) {
+                debug::insert_label(&app.device, command_buffer, "indirect draws");
+                indirect::record_draws(app, command_buffer, indirect_frame);
+                continue;
+            }
+
+            debug::insert_label(&app.device, command_buffer, "scene draws");
+
+            // one draw per visible node, material only rebound when it changes
+            let mut bound_material = None;
+            for item in app.scene.get_draw_items() {
+                let material_index = item.constants.material_index as usize;
+                if bound_material != Some(material_index) {
+                    app.device.cmd_bind_descriptor_sets(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        *app.pipeline_layout,
+                        MATERIAL_SET,
+                        &[*app.materials[material_index].descriptor_set],
+                        &[],
+                    );
+                    bound_material = Some(material_index);
+                }
+
+                app.device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    *app.pipeline_layout,
+                    0,
+                    &[frame.descriptor_set],
+                    &frame.get_dynamic_offsets(frame.object_offsets[item.node]),
+                );
+                push_draw_constants(
+                    &app.device,
+                    command_buffer,
+                    *app.pipeline_layout,
+                    &item.constants,
+                );
+                draw_mesh(app, command_buffer, &app.meshes[item.mesh], item.lod);
+            }
+        }
+
+        app.device.cmd_end_render_pass(command_buffer);
+    }
+}
+
+// every instance of one level of detail of a mesh range
+fn draw_mesh(app: &Application, command_buffer: vk::CommandBuffer, mesh: &MeshRange, lod: usize) {
+    let lod = mesh.get_lod(lod);
+
+    unsafe {
+        app.device.cmd_draw_indexed(
+            command_buffer,
+            lod.index_count,
+            app.instances.len() as u32,
+            lod.first_index,
+            mesh.vertex_offset,
+            0,
+        );
+    }
+}
+
+pub fn push_draw_constants(
     device: &DeviceLoader,
-    swapchain_size: usize,
-) -> (
-    Vec<vk::Semaphore>,
-    Vec<vk::Semaphore>,
-    Vec<vk::Fence>,
-    Vec<vk::Fence>,
+    command_buffer: vk::CommandBuffer,
+    pipeline_layout: vk::PipelineLayout,
+    draw_constants: &DrawConstants,
 ) {
+    let range = DrawConstants::get_push_constant_range();
+
+    unsafe {
+        device.cmd_push_constants(
+            command_buffer,
+            pipeline_layout,
+            range.stage_flags,
+            range.offset,
+            size_of::<DrawConstants>() as u32,
+            draw_constants as *const DrawConstants as *const c_void,
+        );
+    }
+}
+
+// image available & render finished semaphores, in flight fences
+type SyncPrimitives = (
+    Vec<Owned<vk::Semaphore>>,
+    Vec<Owned<vk::Semaphore>>,
+    Vec<Owned<vk::Fence>>,
+);
+
+// one of each per frame in flight, fences aren't needed with a frame timeline semaphore
+pub fn create_sync_primitives(
+    device: &Rc<handle::Device>,
+    frames_in_flight: usize,
+    fences: bool,
+) -> SyncPrimitives {
     let semaphore_info = vk::SemaphoreCreateInfoBuilder::new();
     let fence_info = vk::FenceCreateInfoBuilder::new().flags(vk::FenceCreateFlags::SIGNALED);
 
-    let image_available_semaphore: Vec<_> = (0..MAX_FRAMES_IN_FLIGHT)
+    let image_available_semaphore: Vec<_> = (0..frames_in_flight)
         .map(|_| {
             unsafe { device.create_semaphore(&semaphore_info, None, None) }
                 .expect("Failed to create image_available_semaphore!")
         })
         .collect();
 
-    let render_finished_semaphore: Vec<_> = (0..MAX_FRAMES_IN_FLIGHT)
+    let render_finished_semaphore: Vec<_> = (0..frames_in_flight)
         .map(|_| {
             unsafe { device.create_semaphore(&semaphore_info, None, None) }
                 .expect("Failed to create render_finished_semaphore!")
         })
         .collect();
 
-    let in_flight_fences = (0..MAX_FRAMES_IN_FLIGHT)
+    let fence_count = match fences {
+        true => frames_in_flight,
+        false => 0,
+    };
+    let in_flight_fences: Vec<_> = (0..fence_count)
         .map(|_| {
             unsafe { device.create_fence(&fence_info, None, None) }
                 .expect("Failed to create in_flight_fences!")
         })
         .collect();
 
-    let images_in_flight = (0..swapchain_size).map(|_| vk::Fence::null()).collect();
-
     (
-        image_available_semaphore,
-        render_finished_semaphore,
-        in_flight_fences,
-        images_in_flight,
+        Owned::new_each(
+            device,
+            image_available_semaphore,
+            "image available semaphore",
+        ),
+        Owned::new_each(
+            device,
+            render_finished_semaphore,
+            "render finished semaphore",
+        ),
+        Owned::new_each(device, in_flight_fences, "in flight fence"),
     )
 }
@@ -0,0 +1,216 @@
+use crate::application::culling::{Bounds, CullStats, Frustum};
+use crate::application::lod;
+use crate::application::model::{DrawConstants, MeshRange, ObjectUniformBlock};
+
+use ultraviolet::mat::Mat4;
+use ultraviolet::rotor::Rotor3;
+use ultraviolet::vec::{Vec3, Vec4};
+
+// index into Scene::nodes
+pub type NodeId = usize;
+
+// local to parent space, applied scale then rotation then translation
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Rotor3,
+    pub scale: Vec3,
+}
+
+impl Transform {
+    pub fn new(translation: Vec3, rotation: Rotor3, scale: Vec3) -> Transform {
+        Transform {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn get_matrix(&self) -> Mat4 {
+        Mat4::from_translation(self.translation)
+            * self.rotation.into_matrix().into_homogeneous()
+            * Mat4::from_nonuniform_scale(self.scale)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::new(Vec3::zero(), Rotor3::identity(), Vec3::one())
+    }
+}
+
+pub struct Node {
+    pub transform: Transform,
+    // index into the application's meshes, None for nodes that only group children
+    pub mesh: Option<usize>,
+    // index into the application's materials
+    pub material: usize,
+    pub tint: Vec4,
+    // hidden nodes hide their children too
+    pub visible: bool,
+    children: Vec<NodeId>,
+    // parent world matrix * local transform, updated by Scene::update
+    world: Mat4,
+    world_visible: bool,
+    // set by Scene::cull, nodes outside the camera frustum still cast shadows
+    in_frustum: bool,
+    // set by Scene::select_lods
+    lod: usize,
+}
+
+// one draw of a mesh with its per-object data
+pub struct DrawItem {
+    // indexes the frame's object offsets
+    pub node: NodeId,
+    pub mesh: usize,
+    pub lod: usize,
+    pub object: ObjectUniformBlock,
+    pub constants: DrawConstants,
+}
+
+// tree of nodes, a node's transform is relative to its parent
+#[derive(Default)]
+pub struct Scene {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene::default()
+    }
+
+    pub fn add_node(&mut self, parent: Option<NodeId>, transform: Transform) -> NodeId {
+        let id = self.nodes.len();
+
+        self.nodes.push(Node {
+            transform,
+            mesh: None,
+            material: 0,
+            tint: Vec4::one(),
+            visible: true,
+            children: Vec::new(),
+            world: Mat4::identity(),
+            world_visible: true,
+            in_frustum: true,
+            lod: 0,
+        });
+
+        match parent {
+            Some(parent) => self.nodes[parent].children.push(id),
+            None => self.roots.push(id),
+        }
+
+        id
+    }
+
+    // node drawing a mesh with the given material
+    pub fn add_mesh_node(
+        &mut self,
+        parent: Option<NodeId>,
+        transform: Transform,
+        mesh: usize,
+        material: usize,
+        tint: Vec4,
+    ) -> NodeId {
+        let id = self.add_node(parent, transform);
+
+        let node = &mut self.nodes[id];
+        node.mesh = Some(mesh);
+        node.material = material;
+        node.tint = tint;
+
+        id
+    }
+
+    pub fn get_node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id]
+    }
+
+    pub fn get_node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // propagates world matrices & visibility down from the roots, called once per frame
+    pub fn update(&mut self) {
+        let mut stack: Vec<_> = self
+            .roots
+            .iter()
+            .map(|&root| (root, Mat4::identity(), true))
+            .collect();
+
+        while let Some((id, parent_world, parent_visible)) = stack.pop() {
+            let node = &mut self.nodes[id];
+            node.world = parent_world * node.transform.get_matrix();
+            node.world_visible = parent_visible && node.visible;
+
+            let (world, visible) = (node.world, node.world_visible);
+            stack.extend(node.children.iter().map(|&child| (child, world, visible)));
+        }
+    }
+
+    // tests world space bounds of visible mesh nodes against the camera, after update
+    pub fn cull(&mut self, frustum: &Frustum, mesh_bounds: &[Bounds]) -> CullStats {
+        let mut stats = CullStats::default();
+
+        for node in &mut self.nodes {
+            node.in_frustum = match node.mesh {
+                Some(mesh) if node.world_visible => {
+                    let bounds = mesh_bounds[mesh].transformed(&node.world);
+                    frustum.intersects(&bounds)
+                }
+                _ => true,
+            };
+
+            if node.mesh.is_some() && node.world_visible {
+                if node.in_frustum {
+                    stats.drawn += 1;
+                } else {
+                    stats.culled += 1;
+                }
+            }
+        }
+
+        stats
+    }
+
+    // picks each mesh node's level of detail from its projected size, after update
+    pub fn select_lods(&mut self, view: &Mat4, projection: &Mat4, meshes: &[MeshRange]) {
+        let (w_row, projection_scale) = lod::get_camera_terms(view, projection);
+
+        for node in &mut self.nodes {
+            if let Some(mesh) = node.mesh {
+                let mesh = &meshes[mesh];
+                let screen_size =
+                    lod::get_screen_size(&mesh.bounds, &node.world, w_row, projection_scale);
+                node.lod = lod::select_lod(screen_size, mesh.lod_count);
+            }
+        }
+    }
+
+    // visible nodes with a mesh inside the camera frustum, as of the last update & cull
+    pub fn get_draw_items(&self) -> impl Iterator<Item = DrawItem> + '_ {
+        self.get_items(true)
+    }
+
+    // visible nodes with a mesh, whether or not they're in the camera frustum
+    pub fn get_visible_items(&self) -> impl Iterator<Item = DrawItem> + '_ {
+        self.get_items(false)
+    }
+
+    fn get_items(&self, frustum_culled: bool) -> impl Iterator<Item = DrawItem> + '_ {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(move |(_, node)| node.world_visible && (node.in_frustum || !frustum_culled))
+            .filter_map(|(id, node)| {
+                node.mesh.map(|mesh| DrawItem {
+                    node: id,
+                    mesh,
+                    lod: node.lod,
+                    object: ObjectUniformBlock::new(node.world),
+                    constants: DrawConstants::new(node.tint, node.material as u32, node.lod as u32),
+                })
+            })
+    }
+}
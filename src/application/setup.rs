@@ -1,7 +1,9 @@
-use crate::application::Opt;
+use crate::application::config::{Config, DeviceConfig};
+use crate::application::presentation::OutputMode;
+use crate::application::validation::{self, Validation, ValidationFeature};
 
 use erupt::{vk, cstr, utils::surface};
-use erupt::{InstanceLoader, DefaultEntryLoader, DeviceLoader};
+use erupt::{InstanceLoader, DefaultEntryLoader, DeviceLoader, ExtendableFrom};
 use erupt::vk::{Queue, SurfaceKHR};
 
 use winit::window::Window;
@@ -9,16 +11,11 @@ use winit::window::Window;
 use std::os::raw::c_char;
 use std::ffi::{CStr, CString, c_void};
 
-use structopt::StructOpt;
-
 
 pub const LAYER_KHRONOS_VALIDATION: *const c_char = cstr!("VK_LAYER_KHRONOS_validation");
 
-pub fn create_instance(window: &Window, entry: &DefaultEntryLoader) -> InstanceLoader {
-    // cmd arguments
-    let opt = Opt::from_args();
-
-    let application_name = CString::new("WIP").expect("Failed to create CString for application name!");
+pub fn create_instance(window: &Window, entry: &DefaultEntryLoader, config: &Config) -> InstanceLoader {
+    let application_name = CString::new(config.window.title.as_str()).expect("Failed to create CString for application name!");
     let engine_name = CString::new("No Engine").expect("Failed to create CString for engine name!");
 
     // generic application infomation
@@ -26,47 +23,80 @@ pub fn create_instance(window: &Window, entry: &DefaultEntryLoader) -> InstanceL
         .application_name(&application_name)
         .application_version(vk::make_version(1, 0, 0))
         .engine_name(&engine_name)
-        .engine_version(vk::make_version(1, 0, 0));
+        .engine_version(vk::make_version(1, 0, 0))
+        // highest version used, 1.2 for timeline semaphores, devices can still be older
+        .api_version(entry.instance_version().min(vk::make_version(1, 2, 0)));
 
     // instance extensions required by winit surface
     let mut instance_extensions = surface::enumerate_required_extensions(window).expect("Failed to enumerate require instance extensions by surface!");
 
-    // check for -v --validation flags and enable/disable validation layers
-    if opt.validation {
-        // extension for debug callback
+    // object names & labels for capture tools whenever available, the validation layer provides
+    // it too for the debug callback
+    let validation = config.debug.is_validation_enabled();
+    if validation || supports_instance_extension(entry, None, vk::EXT_DEBUG_UTILS_EXTENSION_NAME) {
         instance_extensions.push(vk::EXT_DEBUG_UTILS_EXTENSION_NAME);
+    }
 
+    // check for -v --validation flags and enable/disable validation layers
+    if validation {
         println!("Validation layers enabled.");
     }
 
+    // hdr color spaces for the swapchain, the picked surface format falls back to sdr without it
+    if config.post.output.is_hdr() {
+        if supports_instance_extension(entry, None, vk::EXT_SWAPCHAIN_COLOR_SPACE_EXTENSION_NAME) {
+            instance_extensions.push(vk::EXT_SWAPCHAIN_COLOR_SPACE_EXTENSION_NAME);
+        } else {
+            println!("VK_EXT_swapchain_colorspace not supported, hdr output unavailable.");
+        }
+    }
+
     // instance layers (pretty much just validation layers)
     let mut instance_layers = Vec::new();
-    if opt.validation {
+    if config.debug.is_validation_enabled() {
         // standard validation layer
         instance_layers.push(LAYER_KHRONOS_VALIDATION);
     }
 
+    // extra validation layer checks, the extension is provided by the layer itself
+    let validation_enables: Vec<_> = config.debug.validation_features.iter().flat_map(|feature| feature.get_enables().iter().copied()).collect();
+    let mut validation_features = vk::ValidationFeaturesEXTBuilder::new().enabled_validation_features(&validation_enables);
+    let use_validation_features = !validation_enables.is_empty() && supports_instance_extension(entry, Some(LAYER_KHRONOS_VALIDATION), vk::EXT_VALIDATION_FEATURES_EXTENSION_NAME);
+
+    if use_validation_features {
+        instance_extensions.push(vk::EXT_VALIDATION_FEATURES_EXTENSION_NAME);
+
+        println!("Validation features enabled - {:?}", config.debug.validation_features);
+    } else if !validation_enables.is_empty() {
+        println!("VK_EXT_validation_features not supported, using default validation.");
+    }
+
     // bundling all of the previous
-    let instance_info = vk::InstanceCreateInfoBuilder::new()
+    let mut instance_info = vk::InstanceCreateInfoBuilder::new()
         .application_info(&application_info)
         .enabled_extension_names(&instance_extensions)
         .enabled_layer_names(&instance_layers);
 
+    if use_validation_features {
+        instance_info = instance_info.extend_from(&mut *validation_features);
+    }
+
     // create the instance :)
-    match InstanceLoader::new(&entry, &instance_info, None) {
+    match InstanceLoader::new(entry, &instance_info, None) {
         Ok(instance) => instance,
         Err(e) => panic!("Le Instance creation failed! {:?}", e),
     }
 }
 
-pub fn setup_debug_messenger(instance: &InstanceLoader) -> vk::DebugUtilsMessengerEXT {
-    // cmd args
-    let opt = Opt::from_args();
-
-    if opt.validation {
+// validation has to outlive the messenger, it's the callback's user data
+pub fn setup_debug_messenger(instance: &InstanceLoader, validation: &Validation, config: &Config) -> vk::DebugUtilsMessengerEXT {
+    if config.debug.is_validation_enabled() {
+        // every severity, the log level filters out what isn't wanted
         let messenger_info = vk::DebugUtilsMessengerCreateInfoEXTBuilder::new()
             .message_severity(
-                vk::DebugUtilsMessageSeverityFlagsEXT::WARNING_EXT
+                vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE_EXT
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::INFO_EXT
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING_EXT
                     | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR_EXT,
             )
             .message_type(
@@ -74,7 +104,8 @@ pub fn setup_debug_messenger(instance: &InstanceLoader) -> vk::DebugUtilsMesseng
                     | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION_EXT
                     | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE_EXT,
             )
-            .pfn_user_callback(Some(debug_callback));
+            .pfn_user_callback(Some(validation::debug_callback))
+            .user_data(validation as *const Validation as *mut c_void);
 
         unsafe { instance.create_debug_utils_messenger_ext(&messenger_info, None, None) }.expect("Failed to create debug messenger!")
     } else {
@@ -87,7 +118,9 @@ pub fn setup_debug_messenger(instance: &InstanceLoader) -> vk::DebugUtilsMesseng
 pub fn pick_physical_device_and_queue_family(
     instance: &InstanceLoader,
     surface: &SurfaceKHR,
-    device_extensions: &Vec<*const i8>,
+    device_extensions: &[*const i8],
+    output_mode: OutputMode,
+    device_config: &DeviceConfig,
 ) -> (
     vk::PhysicalDevice,
     u32,
@@ -110,8 +143,7 @@ pub fn pick_physical_device_and_queue_family(
                 queue_family_properties.queue_flags.contains(vk::QueueFlags::GRAPHICS)
 
                 // need support for surface for window
-                && instance.get_physical_device_surface_support_khr(physical_device, index as u32, *surface, None).expect("Failed to check physical device support for surface!") 
-                == true}) {
+                && instance.get_physical_device_surface_support_khr(physical_device, index as u32, *surface, None).expect("Failed to check physical device support for surface!")}) {
                     Some(queue_family) => queue_family as u32,
                     None => return None,
                 };
@@ -119,20 +151,26 @@ pub fn pick_physical_device_and_queue_family(
             // get all formats supported by device
             let formats = instance.get_physical_device_surface_formats_khr(physical_device, *surface, None).expect("Failed to query physical device supported format!");
 
-            // prefer 32bit srgba
-            let format = match formats.iter().find(|surface_format| { 
+            // prefer a format in the requested output's color space
+            let (color_space, output_formats) = output_mode.get_surface_formats();
+            let format = match formats.iter().find(|surface_format| {
+                surface_format.color_space == color_space
+                && output_formats.contains(&surface_format.format)
+
+            // then 32bit srgba
+            }).or_else(|| formats.iter().find(|surface_format| {
                 surface_format.format == vk::Format::B8G8R8A8_SRGB
                 && surface_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR_KHR
 
             // worst case fall back to first supported format
-            }).or_else(|| formats.get(0)) {
-                Some(surface_format) => surface_format.clone(),
+            })).or_else(|| formats.first()) {
+                Some(surface_format) => *surface_format,
                 None => return None,
             };
 
             let present_mode = instance.get_physical_device_surface_present_modes_khr(physical_device, *surface, None)
-            // prefer uncapped fps
-                .expect("Failed to get physical device present modes").into_iter().find(|present_mode| present_mode == &vk::PresentModeKHR::IMMEDIATE_KHR)
+            // prefer the configured mode
+                .expect("Failed to get physical device present modes").into_iter().find(|present_mode| present_mode == &device_config.present_mode.get_present_mode())
                 // FIFO as fallback
                 .unwrap_or(vk::PresentModeKHR::FIFO_KHR);
 
@@ -158,42 +196,87 @@ pub fn pick_physical_device_and_queue_family(
             // return info for physical device
             Some((physical_device, queue_family, format, present_mode, device_properties))
         })
-        .max_by_key(|(_, _, _, _, properties)| match properties.device_type {
+        .max_by_key(|(_, _, _, _, properties)| {
+
+            // a device matching the configured name first
+            let name = unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }.to_string_lossy().to_lowercase();
+            let named = device_config.name.as_ref().is_some_and(|wanted| name.contains(&wanted.to_lowercase()));
 
-            // prefer discrete gpu but settle for integrated
-            vk::PhysicalDeviceType::DISCRETE_GPU => 2,
-            vk::PhysicalDeviceType::INTEGRATED_GPU => 1,
-            _ => 0,
+            (named, match properties.device_type {
+
+                // prefer discrete gpu but settle for integrated
+                vk::PhysicalDeviceType::DISCRETE_GPU => 2,
+                vk::PhysicalDeviceType::INTEGRATED_GPU => 1,
+                _ => 0,
+            })
     }).expect("Big sad no supported physical devices found :(")
 }
 
-pub fn get_logical_device_and_queue(instance: &InstanceLoader, physical_device: vk::PhysicalDevice, device_extensions: &Vec<*const i8>, device_layers: &Vec<*const i8>, queue_family: u32) -> (DeviceLoader, Queue) {
+// optional features, only enabled if the device supports them
+pub fn get_device_features(instance: &InstanceLoader, physical_device: vk::PhysicalDevice, config: &Config) -> vk::PhysicalDeviceFeatures {
+    let supported = unsafe { instance.get_physical_device_features(physical_device, None) };
+
+    // gpu assisted validation writes its results from every shader stage
+    let gpu_assisted = config.debug.validation_features.contains(&ValidationFeature::GpuAssisted);
+
+    vk::PhysicalDeviceFeaturesBuilder::new()
+        // wireframe view mode
+        .fill_mode_non_solid(supported.fill_mode_non_solid == vk::TRUE)
+        // material texture filtering
+        .sampler_anisotropy(supported.sampler_anisotropy == vk::TRUE)
+        // gpu culling, one indirect call per material & instances offset per object
+        .multi_draw_indirect(supported.multi_draw_indirect == vk::TRUE)
+        .draw_indirect_first_instance(supported.draw_indirect_first_instance == vk::TRUE)
+        .vertex_pipeline_stores_and_atomics(gpu_assisted && supported.vertex_pipeline_stores_and_atomics == vk::TRUE)
+        .fragment_stores_and_atomics(gpu_assisted && supported.fragment_stores_and_atomics == vk::TRUE)
+        .build()
+}
+
+// core in Vulkan 1.2 but still optional, needs the instance & device to both be 1.2
+pub fn supports_timeline_semaphores(instance: &InstanceLoader, physical_device: vk::PhysicalDevice, properties: &vk::PhysicalDeviceProperties) -> bool {
+    if !instance.enabled().vk1_2 || properties.api_version < vk::make_version(1, 2, 0) {
+        return false;
+    }
+
+    let mut vulkan_12_features = vk::PhysicalDeviceVulkan12FeaturesBuilder::new();
+    let features = vk::PhysicalDeviceFeatures2Builder::new().extend_from(&mut *vulkan_12_features);
+    unsafe { instance.get_physical_device_features2(physical_device, Some(features.build())) };
+
+    vulkan_12_features.timeline_semaphore == vk::TRUE
+}
+
+// layer is None for extensions provided by the implementation
+fn supports_instance_extension(entry: &DefaultEntryLoader, layer: Option<*const c_char>, extension: *const c_char) -> bool {
+    let layer = layer.map(|layer| unsafe { CStr::from_ptr(layer) });
+    let supported_instance_extensions = unsafe { entry.enumerate_instance_extension_properties(layer, None) }.expect("Failed to get supported instance extensions!");
+    let extension = unsafe { CStr::from_ptr(extension) };
+
+    supported_instance_extensions.iter().any(|properties| unsafe { CStr::from_ptr(properties.extension_name.as_ptr()) } == extension)
+}
+
+// optional device extensions, only enabled if the device supports them
+pub fn supports_device_extension(instance: &InstanceLoader, physical_device: vk::PhysicalDevice, extension: *const c_char) -> bool {
+    let supported_device_extensions = unsafe { instance.enumerate_device_extension_properties(physical_device, None, None) }.expect("Failed to get supported device extensions!");
+    let extension = unsafe { CStr::from_ptr(extension) };
+
+    supported_device_extensions.iter().any(|properties| unsafe { CStr::from_ptr(properties.extension_name.as_ptr()) } == extension)
+}
+
+pub fn get_logical_device_and_queue(instance: &InstanceLoader, physical_device: vk::PhysicalDevice, device_extensions: &[*const i8], device_layers: &[*const i8], features: &vk::PhysicalDeviceFeatures, timeline_semaphores: bool, queue_family: u32) -> (DeviceLoader, Queue) {
     let queue_infos = vec![vk::DeviceQueueCreateInfoBuilder::new().queue_family_index(queue_family).queue_priorities(&[1.0])];
 
-    let features = vk::PhysicalDeviceFeaturesBuilder::new();
+    // create device info with features queried with get device features
+    let mut device_info = vk::DeviceCreateInfoBuilder::new().queue_create_infos(&queue_infos)
+        .enabled_features(features).enabled_extension_names(device_extensions).enabled_layer_names(device_layers);
 
-    // create device info with features queried with pick physical device 
-    let device_info = vk::DeviceCreateInfoBuilder::new().queue_create_infos(&queue_infos)
-        .enabled_features(&features).enabled_extension_names(device_extensions).enabled_layer_names(device_layers);
+    // checked with supports timeline semaphores
+    let mut vulkan_12_features = vk::PhysicalDeviceVulkan12FeaturesBuilder::new().timeline_semaphore(true);
+    if timeline_semaphores {
+        device_info = device_info.extend_from(&mut *vulkan_12_features);
+    }
 
-    let device = DeviceLoader::new(&instance, physical_device, &device_info, None).expect("Failed to create DeviceLoader!");
+    let device = DeviceLoader::new(instance, physical_device, &device_info, None).expect("Failed to create DeviceLoader!");
     let queue = unsafe { device.get_device_queue(queue_family, 0, None)};
 
     (device, queue)
 }
-
-// debug callback signature
-unsafe extern "system" fn debug_callback(
-    _message_severity: vk::DebugUtilsMessageSeverityFlagBitsEXT,
-    _message_types: vk::DebugUtilsMessageTypeFlagsEXT,
-    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _p_user_data: *mut c_void,
-) -> vk::Bool32 {
-    // print to stderr not stdout
-    eprintln!(
-        "{}",
-        CStr::from_ptr((*p_callback_data).p_message).to_string_lossy()
-    );
-
-    vk::FALSE
-}
\ No newline at end of file
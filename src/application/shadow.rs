@@ -0,0 +1,508 @@
+use crate::application::handle::{self, Owned};
+use crate::application::lighting::{Lights, MAX_SPOT_LIGHTS};
+use crate::application::model::{InstanceData, UniformBufferObject, Vertex};
+use crate::application::upload::Uploader;
+use crate::application::{pipeline, texture};
+
+use erupt::{vk, DeviceLoader, InstanceLoader};
+
+use ultraviolet::mat::Mat4;
+use ultraviolet::projection::rh_yup::{orthographic_vk, perspective_vk};
+use ultraviolet::vec::{Vec2, Vec3, Vec4};
+
+use std::ffi::CStr;
+use std::mem::size_of;
+use std::rc::Rc;
+
+pub const SHADER_SHADOW_VERT: &[u8] = include_bytes!("../../res/shaders/shadow.spv");
+
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+
+// cascades for the first directional light, must match CASCADE_COUNT in shader.frag
+pub const CASCADE_COUNT: usize = 4;
+
+// one array layer per cascade then one per spot light, must match shader.frag & shadow.vert
+pub const SHADOW_MAP_LAYERS: usize = CASCADE_COUNT + MAX_SPOT_LIGHTS;
+
+// blend between logarithmic (1.0) & uniform (0.0) cascade splits
+const CASCADE_SPLIT_LAMBDA: f32 = 0.9;
+
+// near plane used if the camera's is at the eye
+const MIN_CAMERA_NEAR: f32 = 0.01;
+
+const SPOT_LIGHT_NEAR: f32 = 0.05;
+
+// slope scaled bias to avoid shadow acne
+const DEPTH_BIAS_CONSTANT: f32 = 1.25;
+const DEPTH_BIAS_SLOPE: f32 = 1.75;
+
+// std140 layout of the shadow uniform block (binding 2)
+#[repr(C, align(16))]
+pub struct ShadowUniformBlock {
+    // world to shadow map clip space for each layer
+    pub light_matrices: [Mat4; SHADOW_MAP_LAYERS],
+    // camera ndc depth at the far end of each cascade
+    pub cascade_splits: Vec4,
+    // x 1 if the first directional light casts shadows, y number of spot light shadows
+    pub counts: [u32; 4],
+}
+
+// per-pass data for the shadow pass, the model matrix is read from the object block
+#[repr(C)]
+pub struct ShadowConstants {
+    pub layer: u32,
+}
+
+impl ShadowConstants {
+    pub fn new(layer: u32) -> ShadowConstants {
+        ShadowConstants { layer }
+    }
+}
+
+// layered depth image & everything needed to render into it
+pub struct ShadowMaps {
+    pub framebuffers: Vec<Owned<vk::Framebuffer>>,
+    // one per layer, used by the framebuffers
+    _layer_views: Vec<Owned<vk::ImageView>>,
+    pub texture: texture::Texture,
+    pub render_pass: Owned<vk::RenderPass>,
+    pub pipeline: Owned<vk::Pipeline>,
+    pub pipeline_layout: Owned<vk::PipelineLayout>,
+    // depth compare sampler
+    pub sampler: Owned<vk::Sampler>,
+    _shader_vert: Owned<vk::ShaderModule>,
+}
+
+fn create_shadow_render_pass(device: &DeviceLoader, format: vk::Format) -> vk::RenderPass {
+    // the render graph moves the image in & out of the attachment layout
+    let attachments = vec![vk::AttachmentDescriptionBuilder::new()
+        .format(format)
+        .samples(vk::SampleCountFlagBits::_1)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)];
+
+    let depth_attachment_reference = vk::AttachmentReferenceBuilder::new()
+        .attachment(0)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+    let subpasses =
+        vec![vk::SubpassDescriptionBuilder::new()
+            .depth_stencil_attachment(&depth_attachment_reference)];
+
+    let render_pass_info = vk::RenderPassCreateInfoBuilder::new()
+        .attachments(&attachments)
+        .subpasses(&subpasses);
+
+    unsafe { device.create_render_pass(&render_pass_info, None, None) }
+        .expect("Failed to create shadow render pass!")
+}
+
+fn create_shadow_pipeline_layout(
+    device: &DeviceLoader,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+) -> vk::PipelineLayout {
+    // light & model matrices are read from set 0
+    let set_layouts = &[descriptor_set_layout];
+    let push_constant_ranges = &[vk::PushConstantRangeBuilder::new()
+        .stage_flags(vk::ShaderStageFlags::VERTEX)
+        .offset(0)
+        .size(size_of::<ShadowConstants>() as u32)];
+
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfoBuilder::new()
+        .set_layouts(set_layouts)
+        .push_constant_ranges(push_constant_ranges);
+
+    unsafe { device.create_pipeline_layout(&pipeline_layout_info, None, None) }
+        .expect("Failed to create shadow pipeline layout!")
+}
+
+// depth only, no fragment shader
+fn create_shadow_pipeline(
+    device: &DeviceLoader,
+    pipeline_cache: vk::PipelineCache,
+    shader_vert: vk::ShaderModule,
+    pipeline_layout: vk::PipelineLayout,
+    render_pass: vk::RenderPass,
+) -> vk::Pipeline {
+    // only position & instance transform are read
+    let binding_descriptions = [
+        Vertex::get_binding_descriptions(),
+        InstanceData::get_binding_descriptions(),
+    ];
+    let attribute_descriptions: Vec<_> = Vertex::get_attribute_descriptions()[..1]
+        .iter()
+        .chain(InstanceData::get_attribute_descriptions()[..4].iter())
+        .copied()
+        .collect();
+
+    let vertex_input = vk::PipelineVertexInputStateCreateInfoBuilder::new()
+        .vertex_binding_descriptions(&binding_descriptions)
+        .vertex_attribute_descriptions(&attribute_descriptions);
+
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfoBuilder::new()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+
+    // whole shadow map
+    let viewports = [vk::ViewportBuilder::new()
+        .x(0.0)
+        .y(0.0)
+        .width(SHADOW_MAP_SIZE as f32)
+        .height(SHADOW_MAP_SIZE as f32)
+        .min_depth(0.0)
+        .max_depth(1.0)];
+
+    let scissors = [vk::Rect2DBuilder::new()
+        .offset(vk::Offset2D { x: 0, y: 0 })
+        .extent(vk::Extent2D {
+            width: SHADOW_MAP_SIZE,
+            height: SHADOW_MAP_SIZE,
+        })];
+
+    let viewport_state = vk::PipelineViewportStateCreateInfoBuilder::new()
+        .viewports(&viewports)
+        .scissors(&scissors);
+
+    // mesh has no backfaces to cull (single sided quads)
+    let rasterizer = vk::PipelineRasterizationStateCreateInfoBuilder::new()
+        .depth_clamp_enable(false)
+        .rasterizer_discard_enable(false)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_bias_enable(true)
+        .depth_bias_constant_factor(DEPTH_BIAS_CONSTANT)
+        .depth_bias_slope_factor(DEPTH_BIAS_SLOPE);
+
+    let multisampling = vk::PipelineMultisampleStateCreateInfoBuilder::new()
+        .sample_shading_enable(false)
+        .rasterization_samples(vk::SampleCountFlagBits::_1);
+
+    let depth_stencil = vk::PipelineDepthStencilStateCreateInfoBuilder::new()
+        .depth_test_enable(true)
+        .depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+    // no color attachments to blend
+    let color_blending = vk::PipelineColorBlendStateCreateInfoBuilder::new().logic_op_enable(false);
+
+    let shader_stages = vec![vk::PipelineShaderStageCreateInfoBuilder::new()
+        .stage(vk::ShaderStageFlagBits::VERTEX)
+        .module(shader_vert)
+        .name(unsafe { CStr::from_ptr(pipeline::SHADER_ENTRY) })];
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfoBuilder::new()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input)
+        .input_assembly_state(&input_assembly)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterizer)
+        .multisample_state(&multisampling)
+        .depth_stencil_state(&depth_stencil)
+        .color_blend_state(&color_blending)
+        .layout(pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(0);
+
+    unsafe { device.create_graphics_pipelines(Some(pipeline_cache), &[pipeline_info], None) }
+        .expect("Failed to create shadow pipeline!")[0]
+}
+
+// out of range lookups are lit, hardware 2x2 pcf when linear filtering is supported
+fn create_shadow_sampler(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    device: &DeviceLoader,
+    format: vk::Format,
+) -> vk::Sampler {
+    let properties =
+        unsafe { instance.get_physical_device_format_properties(*physical_device, format, None) };
+    let filter = if properties
+        .optimal_tiling_features
+        .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+    {
+        vk::Filter::LINEAR
+    } else {
+        vk::Filter::NEAREST
+    };
+
+    let sampler_info = vk::SamplerCreateInfoBuilder::new()
+        .mag_filter(filter)
+        .min_filter(filter)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        .anisotropy_enable(false)
+        .max_anisotropy(1.0)
+        .border_color(vk::BorderColor::FLOAT_OPAQUE_WHITE)
+        .unnormalized_coordinates(false)
+        .compare_enable(true)
+        .compare_op(vk::CompareOp::LESS_OR_EQUAL)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+        .min_lod(0.0)
+        .max_lod(0.0);
+
+    unsafe { device.create_sampler(&sampler_info, None, None) }
+        .expect("Failed to create shadow sampler!")
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn create_shadow_maps(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    device: &Rc<handle::Device>,
+    uploader: &mut Uploader,
+    pipeline_cache: vk::PipelineCache,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+) -> ShadowMaps {
+    let format = texture::find_depth_format(instance, physical_device);
+    let layers = SHADOW_MAP_LAYERS as u32;
+    let extent = vk::Extent2D {
+        width: SHADOW_MAP_SIZE,
+        height: SHADOW_MAP_SIZE,
+    };
+
+    let (image, memory) = texture::create_image(
+        instance,
+        physical_device,
+        device,
+        extent,
+        1,
+        layers,
+        vk::SampleCountFlagBits::_1,
+        format,
+        vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+        vk::ImageCreateFlags::empty(),
+    );
+
+    let view = texture::create_image_view(
+        device,
+        image,
+        format,
+        vk::ImageViewType::_2D_ARRAY,
+        texture::depth_subresource_range(layers),
+    );
+
+    // layers that are never rendered to still need to be in the sampled layout
+    let (command_pool, command_buffer) = uploader.begin(device, "shadow map layout");
+    texture::transition_image_layout(
+        device,
+        command_buffer,
+        image,
+        texture::depth_subresource_range(layers),
+        vk::ImageLayout::UNDEFINED,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    );
+    uploader.end(device, command_pool, command_buffer, ());
+
+    let render_pass = create_shadow_render_pass(device, format);
+    let render_pass = Owned::new(device, render_pass, "shadow render pass");
+
+    let layer_views: Vec<_> = (0..layers)
+        .map(|layer| {
+            let mut subresource_range = texture::depth_subresource_range(1);
+            subresource_range.base_array_layer = layer;
+
+            texture::create_image_view(
+                device,
+                image,
+                format,
+                vk::ImageViewType::_2D,
+                subresource_range,
+            )
+        })
+        .collect();
+    let layer_views = Owned::new_each(device, layer_views, "shadow map layer");
+
+    let framebuffers: Vec<_> = layer_views
+        .iter()
+        .map(|layer_view| {
+            let attachments = [**layer_view];
+            let framebuffer_info = vk::FramebufferCreateInfoBuilder::new()
+                .render_pass(*render_pass)
+                .attachments(&attachments)
+                .width(SHADOW_MAP_SIZE)
+                .height(SHADOW_MAP_SIZE)
+                .layers(1);
+
+            unsafe { device.create_framebuffer(&framebuffer_info, None, None) }
+                .expect("Failed to create shadow framebuffer!")
+        })
+        .collect();
+    let framebuffers = Owned::new_each(device, framebuffers, "shadow framebuffer");
+
+    let shader_vert = pipeline::create_shader_module(device, SHADER_SHADOW_VERT);
+    let shader_vert = Owned::new(device, shader_vert, "shadow vertex shader");
+    let pipeline_layout = create_shadow_pipeline_layout(device, descriptor_set_layout);
+    let pipeline_layout = Owned::new(device, pipeline_layout, "shadow pipeline layout");
+    let pipeline = create_shadow_pipeline(
+        device,
+        pipeline_cache,
+        *shader_vert,
+        *pipeline_layout,
+        *render_pass,
+    );
+    let pipeline = Owned::new(device, pipeline, "shadow pipeline");
+
+    let sampler = create_shadow_sampler(instance, physical_device, device, format);
+    let sampler = Owned::new(device, sampler, "shadow sampler");
+
+    ShadowMaps {
+        framebuffers,
+        _layer_views: layer_views,
+        texture: texture::Texture::new(device, image, memory, view, "shadow map"),
+        render_pass,
+        pipeline,
+        pipeline_layout,
+        sampler,
+        _shader_vert: shader_vert,
+    }
+}
+
+// any vector not parallel to direction
+fn get_up_vector(direction: Vec3) -> Vec3 {
+    if direction.y.abs() > 0.99 {
+        Vec3::unit_z()
+    } else {
+        Vec3::unit_y()
+    }
+}
+
+// orthographic projection around a bounding sphere of the frustum slice, snapped to
+// shadow map texels so the shadows don't shimmer as the camera moves
+fn get_cascade_matrix(corners: &[Vec3; 8], direction: Vec3) -> Mat4 {
+    let center = corners
+        .iter()
+        .fold(Vec3::zero(), |sum, &corner| sum + corner)
+        / 8.0;
+    let radius = corners
+        .iter()
+        .map(|&corner| (corner - center).mag())
+        .fold(0.0, f32::max);
+    // quantized so the projection doesn't change size every frame
+    let radius = ((radius * 16.0).ceil() / 16.0).max(f32::EPSILON);
+
+    // pulled back so casters outside of the slice still cast into it
+    let eye = center - direction * radius * 2.0;
+    let view = Mat4::look_at(eye, center, get_up_vector(direction));
+    let mut projection = orthographic_vk(-radius, radius, -radius, radius, 0.0, radius * 3.0);
+
+    // offset projection so world origin lands on a texel
+    let origin = (projection * view) * Vec4::new(0.0, 0.0, 0.0, 1.0);
+    let texels = origin.xy() * (SHADOW_MAP_SIZE as f32 / 2.0);
+    let offset =
+        (Vec2::new(texels.x.round(), texels.y.round()) - texels) * (2.0 / SHADOW_MAP_SIZE as f32);
+    projection.cols[3].x += offset.x;
+    projection.cols[3].y += offset.y;
+
+    projection * view
+}
+
+// light matrices for every shadow map layer from the current lights & camera
+pub fn get_uniform_block(lights: &Lights, ubo: &UniformBufferObject) -> ShadowUniformBlock {
+    let mut light_matrices = [Mat4::identity(); SHADOW_MAP_LAYERS];
+    let mut cascade_splits = Vec4::one();
+
+    let camera = ubo.proj * ubo.view;
+    let inverse_camera = camera.inversed();
+
+    // frustum corners in world space on the near (z = 0) & far (z = 1) planes
+    let unproject = |x: f32, y: f32, z: f32| inverse_camera.transform_point3(Vec3::new(x, y, z));
+    let ndc_corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+    let near_corners: Vec<_> = ndc_corners
+        .iter()
+        .map(|&(x, y)| unproject(x, y, 0.0))
+        .collect();
+    let far_corners: Vec<_> = ndc_corners
+        .iter()
+        .map(|&(x, y)| unproject(x, y, 1.0))
+        .collect();
+
+    let near_center = unproject(0.0, 0.0, 0.0);
+    let far_center = unproject(0.0, 0.0, 1.0);
+    let near = (near_center - lights.camera_position)
+        .mag()
+        .max(MIN_CAMERA_NEAR);
+    let far = (far_center - lights.camera_position).mag().max(near * 2.0);
+
+    let directional_shadows = !lights.directional_lights().is_empty();
+    if let Some(light) = lights.directional_lights().first() {
+        let direction = light.direction.truncated();
+        let mut slice_start = 0.0;
+
+        for (cascade, matrix) in light_matrices[..CASCADE_COUNT].iter_mut().enumerate() {
+            // practical split scheme, fraction of the way from near to far
+            let fraction = (cascade + 1) as f32 / CASCADE_COUNT as f32;
+            let logarithmic = near * (far / near).powf(fraction);
+            let uniform = near + (far - near) * fraction;
+            let distance =
+                CASCADE_SPLIT_LAMBDA * logarithmic + (1.0 - CASCADE_SPLIT_LAMBDA) * uniform;
+            let slice_end = (distance - near) / (far - near);
+
+            let mut corners = [Vec3::zero(); 8];
+            for (index, (&near_corner, &far_corner)) in
+                near_corners.iter().zip(far_corners.iter()).enumerate()
+            {
+                let edge = far_corner - near_corner;
+                corners[index] = near_corner + edge * slice_start;
+                corners[index + 4] = near_corner + edge * slice_end;
+            }
+
+            *matrix = get_cascade_matrix(&corners, direction);
+
+            // compared against gl_FragCoord.z in shader.frag
+            let split_point = near_center + (far_center - near_center) * slice_end;
+            cascade_splits[cascade] = camera.transform_point3(split_point).z;
+
+            slice_start = slice_end;
+        }
+    }
+
+    // perspective projection covering the outer cone
+    for (light, matrix) in lights
+        .spot_lights()
+        .iter()
+        .zip(light_matrices[CASCADE_COUNT..].iter_mut())
+    {
+        let position = light.position.truncated();
+        let direction = light.direction.truncated();
+        let range = light.position.w;
+        let field_of_view = 2.0 * light.cone.y.acos();
+
+        let view = Mat4::look_at(position, position + direction, get_up_vector(direction));
+        let projection = perspective_vk(field_of_view, 1.0, SPOT_LIGHT_NEAR, range);
+
+        *matrix = projection * view;
+    }
+
+    ShadowUniformBlock {
+        light_matrices,
+        cascade_splits,
+        counts: [
+            directional_shadows as u32,
+            lights.spot_lights().len() as u32,
+            0,
+            0,
+        ],
+    }
+}
+
+// layers with a light to render, skips cascades if there's no directional light
+pub fn get_active_layers(lights: &Lights) -> Vec<u32> {
+    let cascades = if lights.directional_lights().is_empty() {
+        0..0
+    } else {
+        0..CASCADE_COUNT
+    };
+    let spot_lights = CASCADE_COUNT..CASCADE_COUNT + lights.spot_lights().len();
+
+    cascades
+        .chain(spot_lights)
+        .map(|layer| layer as u32)
+        .collect()
+}
@@ -1,23 +1,47 @@
-use crate::application::{buffer, render};
+use crate::application::{buffer, cache, culling, indirect, pipeline, render, shadow};
 use crate::Application;
 
 use erupt::vk;
 
+use ultraviolet::rotor::Rotor3;
+use ultraviolet::vec::Vec3;
+
+use std::time::Instant;
+
 pub fn main_events_cleared(app: &mut Application) {
-    // wait for image at current index to finish render to avoid submiting more than gpu can handle
-    // u64::MAX disables cooldown
-    unsafe {
-        app.device
-            .wait_for_fences(&[app.in_flight_fences[app.current_frame]], true, u64::MAX)
+    let presented = draw_frame(app);
+
+    // errors are counted against the frame they're reported in, including ones cut short by a
+    // resize
+    app.stats.validation_errors += app.instance.get_validation().end_frame();
+
+    if presented {
+        app.stats.end_frame();
+
+        // change current_frame to next frame
+        app.current_frame = (app.current_frame + 1) % app.frames.len();
     }
-    .expect("Failed on waiting for in_flight_fences[current_frame]!");
+}
+
+// records, submits & presents the current frame, false if the swapchain had to be recreated first
+fn draw_frame(app: &mut Application) -> bool {
+    let frame_index = app.current_frame;
+
+    // wait for this frame's last submission to finish to avoid submiting more than gpu can handle,
+    // after which all of its resources are free to reuse
+    app.frame_sync
+        .wait(&app.device, &app.frames, app.frames[frame_index].submitted);
+
+    // free whatever the frames that have finished were the last to use
+    let completed = app.frame_sync.get_completed(&app.device, &app.frames);
+    app.deletion_queue.collect(completed);
 
     // get index of next image in swapchain & check for invalid swapchain
     let result = unsafe {
         app.device.acquire_next_image_khr(
-            app.swapchain,
+            *app.swapchain,
             u64::MAX,
-            Some(app.image_available_semaphores[app.current_frame]),
+            Some(*app.frames[frame_index].image_available),
             None,
             None,
         )
@@ -29,61 +53,125 @@ pub fn main_events_cleared(app: &mut Application) {
         }
         vk::Result::ERROR_OUT_OF_DATE_KHR => {
             app.resize_window();
-            return;
+            return false;
         }
         _ => {
             panic!("Failed to aquire swap chain image!");
         }
     };
 
-    buffer::update_uniform_buffer(
-        &app.device,
-        &mut app.ubo,
-        &app.start,
-        &app.uniform_buffer_memory[image_index as usize],
-    );
-
-    // get fence for swapchain image use
-    let image_in_flight = app.images_in_flight[image_index as usize];
+    app.ubo.view_mode = app.view_mode.shader_index();
+
+    // spin the mesh, tumbling the cube attached to it & pulsing the sphere
+    let duration = Instant::now().duration_since(app.start).as_secs_f32();
+    let [spinner, cube, pivot, sphere] = app.demo_nodes;
+    app.scene.get_node_mut(spinner).transform.rotation = Rotor3::from_rotation_xy(duration);
+    app.scene.get_node_mut(cube).transform.rotation =
+        Rotor3::from_euler_angles(duration * 1.3, duration * 0.7, 0.0);
+    app.scene.get_node_mut(pivot).transform.rotation = Rotor3::from_rotation_yz(duration * 2.0);
+    app.scene.get_node_mut(sphere).transform.scale =
+        Vec3::broadcast(0.3 + 0.27 * (duration * 0.5).sin());
+    app.scene.update();
+
+    // levels of detail from the camera for the draws recorded on the cpu, the lit pass's are
+    // picked by the culling pass with gpu culling
+    app.scene
+        .select_lods(&app.ubo.view, &app.ubo.proj, &app.meshes);
+
+    // skip recording draws for nodes the camera can't see, done by a compute pass with gpu culling
+    if app.indirect.is_none() {
+        let frustum = culling::Frustum::from_matrix(&(app.ubo.proj * app.ubo.view));
+        app.stats.cull = app.scene.cull(&frustum, &app.mesh_bounds);
+    }
 
-    // check if image is in use and if so wait for image to become available
-    if !image_in_flight.is_null() {
-        unsafe {
-            app.device
-                .wait_for_fences(&[image_in_flight], true, u64::MAX)
+    // orbit the point light around the mesh & slowly sweep the key light from side to side
+    if let Some(demo_lights) = app.demo_lights {
+        if let Some(index) = demo_lights.point {
+            let position = Vec3::new(duration.cos() * 0.75, duration.sin() * 0.75, 0.25);
+            app.lights.move_point_light(index, position);
         }
-        .expect("Failed on wait for images_in_flight[image_index]!");
+        if let Some(index) = demo_lights.key {
+            let direction = Vec3::new(0.3 + 0.2 * (duration * 0.2).sin(), 0.5, 1.0);
+            app.lights.aim_directional_light(index, direction);
+        }
+    }
+
+    // the frame's region of the ring was last used by its previous submit, waited on above
+    app.uniforms.begin_frame(frame_index);
+    let uniform_offsets = [
+        app.uniforms.push(&app.ubo),
+        app.uniforms.push(&app.lights.get_uniform_block()),
+        app.uniforms
+            .push(&shadow::get_uniform_block(&app.lights, &app.ubo)),
+    ];
+    app.frames[frame_index].uniform_offsets = uniform_offsets;
+
+    // one block per node drawn by either pass, the lit pass draws a subset of them
+    let mut object_offsets = vec![0; app.scene.get_node_count()];
+    for item in app.scene.get_visible_items() {
+        object_offsets[item.node] = app.uniforms.push(&item.object);
     }
+    app.frames[frame_index].object_offsets = object_offsets;
+
+    let frame = &app.frames[frame_index];
+    buffer::update_instance_buffer(&app.device, &app.instances, &frame.instance_buffer_memory);
+
+    // wait for the frame last rendering to this swapchain image, 0 if none did
+    app.frame_sync.wait(
+        &app.device,
+        &app.frames,
+        app.images_in_flight[image_index as usize],
+    );
 
     // mark swapchain image for use with current frame
-    app.images_in_flight[image_index as usize] = app.in_flight_fences[app.current_frame];
-
-    // semaphores for current frame
-    let image_available_semaphore = vec![app.image_available_semaphores[app.current_frame]];
-    let render_finished_semaphore = vec![app.render_finished_semaphores[app.current_frame]];
-
-    // submit info takes &vec
-    let command_buffer = vec![app.command_buffers[image_index as usize]];
-
-    let submit_info = vk::SubmitInfoBuilder::new()
-        .wait_semaphores(&image_available_semaphore)
-        .wait_dst_stage_mask(&[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT])
-        .command_buffers(&command_buffer)
-        .signal_semaphores(&render_finished_semaphore);
-
-    // submit queue + fence reset
-    unsafe {
-        let in_flight_fence = app.in_flight_fences[app.current_frame];
-        app.device
-            .reset_fences(&[in_flight_fence])
-            .expect("failed on images_in_flight[current_frame] fence reset!");
-        app.device
-            .queue_submit(app.queue, &[submit_info], Some(in_flight_fence))
+    app.images_in_flight[image_index as usize] = app.frame_sync.next_value();
+
+    // objects for the culling pass, the counts read back are from this frame's last use
+    if let Some(indirect) = app.indirect.as_mut() {
+        app.stats.cull = indirect::update(
+            indirect,
+            &app.instance,
+            &app.physical_device,
+            &app.device,
+            frame_index,
+            &app.scene,
+            &app.meshes,
+            &app.mesh_bounds,
+            *app.frames[frame_index].instance_buffer,
+            app.instances.len(),
+            &mut app.uniforms,
+            &mut app.deletion_queue,
+            app.frame_sync.get_submitted(),
+        );
     }
-    .expect("Failed main queue submition!");
+
+    // a region that filled up was replaced, its set is pointed at the new one & the old one
+    // retired behind the frame's previous submit, the last to read it
+    let replaced = app.uniforms.take_replaced();
+    if !replaced.is_empty() {
+        pipeline::write_uniform_descriptors(
+            &app.device,
+            app.frames[frame_index].descriptor_set,
+            app.uniforms.get_buffer(frame_index),
+        );
+        app.deletion_queue
+            .push_buffers(app.frames[frame_index].submitted, replaced);
+    }
+
+    // every block of the frame is written
+    app.uniforms.flush(&app.device);
+
+    // command buffer is no longer in use by the gpu so can be re-recorded
+    render::record_command_buffer(app, image_index as usize);
+
+    app.frame_sync
+        .submit(&app.device, app.queue, &mut app.frames[frame_index]);
+
+    // present waits on the submit
+    let render_finished_semaphore = vec![*app.frames[frame_index].render_finished];
 
     // present info takes &vec[]
-    let swapchain = vec![app.swapchain];
+    let swapchain = vec![*app.swapchain];
 
     // present info tkaes &vec[]
     let image_index = vec![image_index];
@@ -98,13 +186,13 @@ pub fn main_events_cleared(app: &mut Application) {
 
     if app.resized {
         app.resize_window();
-        return;
+        return false;
     } else {
         match result.raw {
             vk::Result::SUCCESS => result.expect("Failed to unwrap queue presentation!"),
             vk::Result::ERROR_OUT_OF_DATE_KHR | vk::Result::SUBOPTIMAL_KHR => {
                 app.resize_window();
-                return;
+                return false;
             }
             _ => {
                 panic!("Failed to present swap chain image!")
@@ -112,63 +200,19 @@ pub fn main_events_cleared(app: &mut Application) {
         }
     }
 
-    // change current_frame to next frame
-    app.current_frame = (app.current_frame + 1) % render::MAX_FRAMES_IN_FLIGHT;
+    true
 }
 
+// every handle is owned & destroyed when the application's fields are dropped after this, the
+// gpu only has to be done with them
 pub fn loop_destroyed(app: &mut Application) {
-    unsafe {
-        // don't destroy in a non idle state
-        app.device
-            .device_wait_idle()
-            .expect("Device wait idle failed on resize window!");
-
-        // destroys objects that need change with window resize
-        app.destroy_swapchain_related_objects();
-
-        app.device
-            .destroy_descriptor_set_layout(Some(app.descriptor_set_layout), None);
-
-        app.device.destroy_buffer(Some(app.index_buffer), None);
-        app.device.free_memory(Some(app.index_buffer_memory), None);
-
-        app.device.destroy_buffer(Some(app.vertex_buffer), None);
-        app.device.free_memory(Some(app.vertex_buffer_memory), None);
-
-        // destroy all semaphores
-        for &semaphore in app
-            .image_available_semaphores
-            .iter()
-            .chain(app.render_finished_semaphores.iter())
-        {
-            app.device.destroy_semaphore(Some(semaphore), None);
-        }
-
-        // destroy fences (remember in_flight_fences[index_index] = frames_in_flight[current_frame])
-        for &fence in &app.in_flight_fences {
-            app.device.destroy_fence(Some(fence), None);
-        }
-
-        app.device
-            .destroy_command_pool(Some(app.command_pool), None);
+    // don't destroy in a non idle state
+    unsafe { app.device.device_wait_idle() }.expect("Device wait idle failed on loop destruction!");
 
-        app.device
-            .destroy_shader_module(Some(app.shader_vert), None);
-        app.device
-            .destroy_shader_module(Some(app.shader_frag), None);
-
-        app.device.destroy_device(None);
-
-        app.instance.destroy_surface_khr(Some(app.surface), None);
-
-        // messenger descruction
-        if !app.messenger.is_null() {
-            app.instance
-                .destroy_debug_utils_messenger_ext(Some(app.messenger), None);
-        }
-
-        app.instance.destroy_instance(None);
-
-        println!("All cleaned up!")
-    }
+    // write pipeline cache back to disk for next run
+    cache::save_pipeline_cache(
+        &app.device,
+        *app.pipeline_cache,
+        &app.physical_device_properties,
+    );
 }
@@ -0,0 +1,52 @@
+use crate::application::culling::CullStats;
+
+use std::time::{Duration, Instant};
+
+// how often --stats prints
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+// counters printed once per interval when --stats is given
+pub struct FrameStats {
+    enabled: bool,
+    frames: u32,
+    since: Instant,
+    pub cull: CullStats,
+    // summed over the interval
+    pub validation_errors: u32,
+}
+
+impl FrameStats {
+    pub fn new(enabled: bool) -> FrameStats {
+        FrameStats {
+            enabled,
+            frames: 0,
+            since: Instant::now(),
+            cull: CullStats::default(),
+            validation_errors: 0,
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        self.frames += 1;
+
+        let elapsed = self.since.elapsed();
+        if elapsed < REPORT_INTERVAL {
+            return;
+        }
+
+        if self.enabled {
+            println!(
+                "{:.1} fps ({:.2} ms) - {} objects drawn, {} culled, {} validation errors",
+                self.frames as f32 / elapsed.as_secs_f32(),
+                elapsed.as_secs_f32() * 1000.0 / self.frames as f32,
+                self.cull.drawn,
+                self.cull.culled,
+                self.validation_errors,
+            );
+        }
+
+        self.frames = 0;
+        self.validation_errors = 0;
+        self.since = Instant::now();
+    }
+}
@@ -0,0 +1,226 @@
+use crate::application::frame;
+use crate::application::handle::{self, Owned};
+
+use erupt::{vk, DeviceLoader, ExtendableFrom};
+
+use std::rc::Rc;
+
+// cpu-gpu frame pacing, frames are numbered from 1 in submission order so anything tied to a
+// frame number is free once that frame has finished, 0 is always finished, with a timeline
+// uploads take numbers between frames too, everything is submitted to the one graphics queue
+pub struct FrameSync {
+    // Vulkan 1.2, counts up to the number of the last finished frame, the frames' fences are
+    // waited on otherwise
+    timeline: Option<Owned<vk::Semaphore>>,
+    // number of the last submitted frame
+    submitted: u64,
+    // number of the last upload, waited on by the next upload & every frame
+    uploaded: u64,
+}
+
+impl FrameSync {
+    pub fn new(device: &Rc<handle::Device>, timeline: bool) -> FrameSync {
+        let timeline = match timeline {
+            true => {
+                let mut type_info = vk::SemaphoreTypeCreateInfoBuilder::new()
+                    .semaphore_type(vk::SemaphoreType::TIMELINE)
+                    .initial_value(0);
+                let semaphore_info =
+                    vk::SemaphoreCreateInfoBuilder::new().extend_from(&mut *type_info);
+
+                let semaphore = unsafe { device.create_semaphore(&semaphore_info, None, None) }
+                    .expect("Failed to create frame timeline semaphore!");
+
+                Some(Owned::new(device, semaphore, "frame timeline semaphore"))
+            }
+            false => None,
+        };
+
+        FrameSync {
+            timeline,
+            submitted: 0,
+            uploaded: 0,
+        }
+    }
+
+    pub fn is_timeline(&self) -> bool {
+        self.timeline.is_some()
+    }
+
+    // number the next submitted frame will signal
+    pub fn next_value(&self) -> u64 {
+        self.submitted + 1
+    }
+
+    // the last frame that could be using anything created so far
+    pub fn get_submitted(&self) -> u64 {
+        self.submitted
+    }
+
+    // number of the last frame known to have finished, doesn't block
+    pub fn get_completed(&self, device: &DeviceLoader, frames: &[frame::FrameContext]) -> u64 {
+        match self.timeline.as_deref() {
+            Some(&timeline) => unsafe { device.get_semaphore_counter_value(timeline, None) }
+                .expect("Failed to get frame timeline semaphore value!"),
+            None => {
+                // frames not held by a context were waited on before their context was reused,
+                // so the earliest unfinished frame in flight bounds what has finished
+                frames
+                    .iter()
+                    .filter(|frame| frame.submitted != 0)
+                    .filter(|frame| {
+                        frame.in_flight.as_deref().is_some_and(|&fence| {
+                            unsafe { device.get_fence_status(fence) }.raw == vk::Result::NOT_READY
+                        })
+                    })
+                    .map(|frame| frame.submitted - 1)
+                    .min()
+                    .unwrap_or(self.submitted)
+            }
+        }
+    }
+
+    // blocks until frame number value & every frame before it have finished
+    pub fn wait(&self, device: &DeviceLoader, frames: &[frame::FrameContext], value: u64) {
+        if value == 0 {
+            return;
+        }
+
+        match self.timeline.as_deref() {
+            Some(&timeline) => {
+                let semaphores = [timeline];
+                let values = [value];
+                let wait_info = vk::SemaphoreWaitInfoBuilder::new()
+                    .semaphores(&semaphores)
+                    .values(&values);
+
+                // u64::MAX disables cooldown
+                unsafe { device.wait_semaphores(&wait_info, u64::MAX) }
+                    .expect("Failed on waiting for frame timeline semaphore!");
+            }
+            None => {
+                // each fence was last submitted with its frame's number, a frame in flight with a
+                // later number doesn't need to be waited on
+                let fences: Vec<_> = frames
+                    .iter()
+                    .filter(|frame| frame.submitted != 0 && frame.submitted <= value)
+                    .filter_map(|frame| frame.in_flight.as_deref().copied())
+                    .collect();
+
+                if !fences.is_empty() {
+                    unsafe { device.wait_for_fences(&fences, true, u64::MAX) }
+                        .expect("Failed on waiting for frame in_flight fences!");
+                }
+            }
+        }
+    }
+
+    // one time commands on the graphics queue, with a timeline they wait on the previous upload
+    // & signal the next number, otherwise this blocks on a fence for just this submit & returns
+    // 0, returns the number
+    pub fn submit_upload(
+        &mut self,
+        device: &Rc<handle::Device>,
+        queue: vk::Queue,
+        command_buffer: vk::CommandBuffer,
+    ) -> u64 {
+        let command_buffers = vec![command_buffer];
+
+        let timeline = match self.timeline.as_deref() {
+            Some(&timeline) => timeline,
+            None => {
+                let fence_info = vk::FenceCreateInfoBuilder::new();
+                let fence = unsafe { device.create_fence(&fence_info, None, None) }
+                    .expect("Failed to create upload fence!");
+                let fence = Owned::new(device, fence, "upload fence");
+
+                let submit_info = vk::SubmitInfoBuilder::new().command_buffers(&command_buffers);
+
+                unsafe { device.queue_submit(queue, &[submit_info], Some(*fence)) }
+                    .expect("Failed to submit upload command buffer!");
+                unsafe { device.wait_for_fences(&[*fence], true, u64::MAX) }
+                    .expect("Failed on waiting for upload fence!");
+
+                return 0;
+            }
+        };
+
+        let value = self.next_value();
+
+        // later uploads can read what earlier ones wrote, waiting on 0 doesn't wait
+        let semaphores = vec![timeline];
+        let wait_values = vec![self.uploaded];
+        let signal_values = vec![value];
+        let mut timeline_info = vk::TimelineSemaphoreSubmitInfoBuilder::new()
+            .wait_semaphore_values(&wait_values)
+            .signal_semaphore_values(&signal_values);
+
+        let submit_info = vk::SubmitInfoBuilder::new()
+            .wait_semaphores(&semaphores)
+            .wait_dst_stage_mask(&[vk::PipelineStageFlags::ALL_COMMANDS])
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&semaphores)
+            .extend_from(&mut *timeline_info);
+
+        unsafe { device.queue_submit(queue, &[submit_info], None) }
+            .expect("Failed to submit upload command buffer!");
+
+        self.submitted = value;
+        self.uploaded = value;
+        value
+    }
+
+    // submits the frame's command buffer once its swapchain image is acquired & the uploads
+    // have finished, signals the present & the frame's number
+    pub fn submit(
+        &mut self,
+        device: &DeviceLoader,
+        queue: vk::Queue,
+        frame: &mut frame::FrameContext,
+    ) {
+        let value = self.next_value();
+
+        // submit info takes &vec
+        let mut wait_semaphores = vec![*frame.image_available];
+        let mut wait_stages = vec![vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
+        let command_buffers = vec![frame.command_buffer];
+        let mut signal_semaphores = vec![*frame.render_finished];
+        signal_semaphores.extend(self.timeline.as_deref());
+
+        // already reached after the first frame
+        if let Some(&timeline) = self.timeline.as_deref() {
+            wait_semaphores.push(timeline);
+            wait_stages.push(vk::PipelineStageFlags::ALL_COMMANDS);
+        }
+
+        // binary semaphores ignore their values
+        let wait_values = vec![0, self.uploaded];
+        let signal_values = vec![0, value];
+        let mut timeline_info = vk::TimelineSemaphoreSubmitInfoBuilder::new()
+            .wait_semaphore_values(&wait_values)
+            .signal_semaphore_values(&signal_values);
+
+        let mut submit_info = vk::SubmitInfoBuilder::new()
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_stages)
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&signal_semaphores);
+
+        if self.timeline.is_some() {
+            submit_info = submit_info.extend_from(&mut *timeline_info);
+        }
+
+        // the fence was waited on before the frame was recorded
+        let in_flight = frame.in_flight.as_deref().copied();
+        if let Some(in_flight) = in_flight {
+            unsafe { device.reset_fences(&[in_flight]) }
+                .expect("failed on frames[current_frame].in_flight fence reset!");
+        }
+
+        unsafe { device.queue_submit(queue, &[submit_info], in_flight) }
+            .expect("Failed main queue submition!");
+
+        frame.submitted = value;
+        self.submitted = value;
+    }
+}
@@ -0,0 +1,521 @@
+use crate::application::buffer;
+use crate::application::handle::{self, Owned};
+use crate::application::upload::Uploader;
+
+use erupt::{vk, DeviceLoader, InstanceLoader};
+
+use std::path::Path;
+use std::rc::Rc;
+
+// a view of the whole image, the image & its backing memory, dropped in that order
+pub struct Texture {
+    pub view: Owned<vk::ImageView>,
+    pub image: Owned<vk::Image>,
+    _memory: Owned<vk::DeviceMemory>,
+}
+
+impl Texture {
+    // takes ownership of all three under one name
+    pub fn new(
+        device: &Rc<handle::Device>,
+        image: vk::Image,
+        memory: vk::DeviceMemory,
+        view: vk::ImageView,
+        name: &str,
+    ) -> Texture {
+        Texture {
+            view: Owned::new(device, view, name),
+            image: Owned::new(device, image, name),
+            _memory: Owned::new(device, memory, name),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn create_image(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    device: &DeviceLoader,
+    extent: vk::Extent2D,
+    mip_levels: u32,
+    array_layers: u32,
+    samples: vk::SampleCountFlagBits,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+    flags: vk::ImageCreateFlags,
+) -> (vk::Image, vk::DeviceMemory) {
+    let image_info = vk::ImageCreateInfoBuilder::new()
+        .flags(flags)
+        .image_type(vk::ImageType::_2D)
+        .extent(vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        })
+        .mip_levels(mip_levels)
+        .array_layers(array_layers)
+        .format(format)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .usage(usage)
+        .samples(samples)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+    let image =
+        unsafe { device.create_image(&image_info, None, None) }.expect("Failed to create image!");
+
+    let memory_requirements = unsafe { device.get_image_memory_requirements(image, None) };
+
+    // transient attachments only skip being backed by memory in lazily allocated memory, which
+    // desktop gpus usually don't have
+    let lazy_type = match usage.contains(vk::ImageUsageFlags::TRANSIENT_ATTACHMENT) {
+        true => buffer::find_memory_type(
+            instance,
+            physical_device,
+            memory_requirements.memory_type_bits,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::LAZILY_ALLOCATED,
+        ),
+        false => None,
+    };
+    let memory_type = lazy_type.unwrap_or_else(|| {
+        buffer::find_physical_device_memory(
+            instance,
+            physical_device,
+            memory_requirements.memory_type_bits,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )
+    });
+
+    let allocation_info = vk::MemoryAllocateInfoBuilder::new()
+        .allocation_size(memory_requirements.size)
+        .memory_type_index(memory_type);
+
+    let memory = unsafe { device.allocate_memory(&allocation_info, None, None) }
+        .expect("Failed to allocate image memory!");
+
+    unsafe { device.bind_image_memory(image, memory, 0) }.expect("Failed to bind image memory!");
+
+    (image, memory)
+}
+
+pub fn create_image_view(
+    device: &DeviceLoader,
+    image: vk::Image,
+    format: vk::Format,
+    view_type: vk::ImageViewType,
+    subresource_range: vk::ImageSubresourceRange,
+) -> vk::ImageView {
+    let image_view_info = vk::ImageViewCreateInfoBuilder::new()
+        .image(image)
+        .view_type(view_type)
+        .format(format)
+        .subresource_range(subresource_range);
+
+    unsafe { device.create_image_view(&image_view_info, None, None) }
+        .expect("Failed to create image view!")
+}
+
+pub fn color_subresource_range(mip_levels: u32, array_layers: u32) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRangeBuilder::new()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(mip_levels)
+        .base_array_layer(0)
+        .layer_count(array_layers)
+        .build()
+}
+
+pub fn depth_subresource_range(array_layers: u32) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRangeBuilder::new()
+        .aspect_mask(vk::ImageAspectFlags::DEPTH)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(array_layers)
+        .build()
+}
+
+// first depth format that can be rendered to & sampled, in order of preference
+pub fn find_depth_format(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+) -> vk::Format {
+    let required =
+        vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT | vk::FormatFeatureFlags::SAMPLED_IMAGE;
+
+    [
+        vk::Format::D32_SFLOAT,
+        vk::Format::X8_D24_UNORM_PACK32,
+        vk::Format::D16_UNORM,
+    ]
+    .iter()
+    .copied()
+    .find(|&format| {
+        let properties = unsafe {
+            instance.get_physical_device_format_properties(*physical_device, format, None)
+        };
+        properties.optimal_tiling_features.contains(required)
+    })
+    .expect("Failed to find supported depth format!")
+}
+
+// access masks & stages for the layout transitions this crate does
+fn get_layout_access(layout: vk::ImageLayout) -> (vk::AccessFlags, vk::PipelineStageFlags) {
+    match layout {
+        vk::ImageLayout::UNDEFINED => (
+            vk::AccessFlags::empty(),
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+        ),
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL => (
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::PipelineStageFlags::TRANSFER,
+        ),
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => (
+            vk::AccessFlags::TRANSFER_READ,
+            vk::PipelineStageFlags::TRANSFER,
+        ),
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => (
+            vk::AccessFlags::SHADER_READ,
+            vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+        ),
+        vk::ImageLayout::GENERAL => (
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+        ),
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => (
+            vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        ),
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => (
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+        ),
+        _ => (
+            vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+        ),
+    }
+}
+
+pub fn transition_image_layout(
+    device: &DeviceLoader,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    subresource_range: vk::ImageSubresourceRange,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+) {
+    let (src_access_mask, src_stage_mask) = get_layout_access(old_layout);
+    let (dst_access_mask, dst_stage_mask) = get_layout_access(new_layout);
+
+    let barrier = vk::ImageMemoryBarrierBuilder::new()
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(subresource_range)
+        .src_access_mask(src_access_mask)
+        .dst_access_mask(dst_access_mask);
+
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            src_stage_mask,
+            dst_stage_mask,
+            None,
+            &[],
+            &[],
+            &[barrier],
+        )
+    };
+}
+
+// levels down to 1x1
+pub fn get_mip_levels(extent: vk::Extent2D) -> u32 {
+    32 - extent.width.max(extent.height).max(1).leading_zeros()
+}
+
+// mips are generated by linearly filtered blits, formats that can't be blitted that way only get
+// the base level
+pub fn supports_linear_blit(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    format: vk::Format,
+) -> bool {
+    let required = vk::FormatFeatureFlags::BLIT_SRC
+        | vk::FormatFeatureFlags::BLIT_DST
+        | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+
+    let properties =
+        unsafe { instance.get_physical_device_format_properties(*physical_device, format, None) };
+    properties.optimal_tiling_features.contains(required)
+}
+
+// every level & layer must be in transfer dst with the base level filled, each level is blitted
+// from the one above it & every level ends up in shader read
+pub fn generate_mipmaps(
+    device: &DeviceLoader,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    extent: vk::Extent2D,
+    mip_levels: u32,
+    array_layers: u32,
+) {
+    let level_range = |level| {
+        vk::ImageSubresourceRangeBuilder::new()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(level)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(array_layers)
+            .build()
+    };
+    let level_layers = |level| {
+        vk::ImageSubresourceLayersBuilder::new()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(level)
+            .base_array_layer(0)
+            .layer_count(array_layers)
+            .build()
+    };
+    let level_corner = |level: u32| vk::Offset3D {
+        x: (extent.width >> level).max(1) as i32,
+        y: (extent.height >> level).max(1) as i32,
+        z: 1,
+    };
+
+    for level in 1..mip_levels {
+        // the level above has been written, read it for this one then it's done
+        transition_image_layout(
+            device,
+            command_buffer,
+            image,
+            level_range(level - 1),
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        );
+
+        let blit = vk::ImageBlitBuilder::new()
+            .src_subresource(level_layers(level - 1))
+            .src_offsets([vk::Offset3D { x: 0, y: 0, z: 0 }, level_corner(level - 1)])
+            .dst_subresource(level_layers(level))
+            .dst_offsets([vk::Offset3D { x: 0, y: 0, z: 0 }, level_corner(level)]);
+
+        unsafe {
+            device.cmd_blit_image(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[blit],
+                vk::Filter::LINEAR,
+            )
+        };
+
+        transition_image_layout(
+            device,
+            command_buffer,
+            image,
+            level_range(level - 1),
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+    }
+
+    // the smallest level is only ever written
+    transition_image_layout(
+        device,
+        command_buffer,
+        image,
+        level_range(mip_levels - 1),
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    );
+}
+
+// upload tightly packed pixels to a sampled device local 2d texture, with a full mip chain if
+// mipmapped & the format supports it
+#[allow(clippy::too_many_arguments)]
+pub fn create_texture(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    device: &Rc<handle::Device>,
+    uploader: &mut Uploader,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    pixels: &[u8],
+    mipmapped: bool,
+    name: &str,
+) -> Texture {
+    // create temp staging buffer
+    let (staging_buffer, staging_buffer_memory) = buffer::create_buffer(
+        instance,
+        physical_device,
+        device,
+        pixels.len() as u64,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        vk::SharingMode::EXCLUSIVE,
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        &format!("{} staging buffer", name),
+    );
+
+    buffer::copy_to_staging_buffer(
+        instance,
+        device,
+        physical_device,
+        &staging_buffer,
+        &staging_buffer_memory,
+        pixels,
+        pixels.len(),
+    );
+
+    let mip_levels = match mipmapped && supports_linear_blit(instance, physical_device, format) {
+        true => get_mip_levels(extent),
+        false => 1,
+    };
+
+    let (image, memory) = create_image(
+        instance,
+        physical_device,
+        device,
+        extent,
+        mip_levels,
+        1,
+        vk::SampleCountFlagBits::_1,
+        format,
+        vk::ImageUsageFlags::TRANSFER_SRC
+            | vk::ImageUsageFlags::TRANSFER_DST
+            | vk::ImageUsageFlags::SAMPLED,
+        vk::ImageCreateFlags::empty(),
+    );
+
+    let subresource_range = color_subresource_range(mip_levels, 1);
+
+    // undefined -> transfer dst -> copy to the base level -> blit down the chain -> shader read
+    let (command_pool, command_buffer) = uploader.begin(device, name);
+
+    transition_image_layout(
+        device,
+        command_buffer,
+        image,
+        subresource_range,
+        vk::ImageLayout::UNDEFINED,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+    );
+
+    let region = vk::BufferImageCopyBuilder::new()
+        .buffer_offset(0)
+        // tightly packed
+        .buffer_row_length(0)
+        .buffer_image_height(0)
+        .image_subresource(
+            vk::ImageSubresourceLayersBuilder::new()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .mip_level(0)
+                .base_array_layer(0)
+                .layer_count(1)
+                .build(),
+        )
+        .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+        .image_extent(vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        });
+
+    unsafe {
+        device.cmd_copy_buffer_to_image(
+            command_buffer,
+            *staging_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[region],
+        )
+    };
+
+    generate_mipmaps(device, command_buffer, image, extent, mip_levels, 1);
+
+    // the staging buffer is dropped once the upload has finished
+    uploader.end(
+        device,
+        command_pool,
+        command_buffer,
+        (staging_buffer, staging_buffer_memory),
+    );
+
+    let view = create_image_view(
+        device,
+        image,
+        format,
+        vk::ImageViewType::_2D,
+        subresource_range,
+    );
+
+    Texture::new(device, image, memory, view, name)
+}
+
+// loads any image format supported by the image crate as rgba8
+#[allow(clippy::too_many_arguments)]
+pub fn load_texture(
+    instance: &InstanceLoader,
+    physical_device: &vk::PhysicalDevice,
+    device: &Rc<handle::Device>,
+    uploader: &mut Uploader,
+    path: &Path,
+    format: vk::Format,
+    mipmapped: bool,
+    name: &str,
+) -> Result<Texture, image::ImageError> {
+    let pixels = image::open(path)?.to_rgba8();
+
+    let extent = vk::Extent2D {
+        width: pixels.width(),
+        height: pixels.height(),
+    };
+
+    Ok(create_texture(
+        instance,
+        physical_device,
+        device,
+        uploader,
+        extent,
+        format,
+        pixels.as_raw(),
+        mipmapped,
+        name,
+    ))
+}
+
+pub fn create_sampler(
+    device: &DeviceLoader,
+    features: &vk::PhysicalDeviceFeatures,
+    limits: &vk::PhysicalDeviceLimits,
+) -> vk::Sampler {
+    // anisotropic filtering when supported
+    let anisotropy = features.sampler_anisotropy == vk::TRUE;
+
+    let sampler_info = vk::SamplerCreateInfoBuilder::new()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::REPEAT)
+        .address_mode_v(vk::SamplerAddressMode::REPEAT)
+        .address_mode_w(vk::SamplerAddressMode::REPEAT)
+        .anisotropy_enable(anisotropy)
+        .max_anisotropy(if anisotropy {
+            limits.max_sampler_anisotropy
+        } else {
+            1.0
+        })
+        .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+        .unnormalized_coordinates(false)
+        .compare_enable(false)
+        .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+        .min_lod(0.0)
+        .max_lod(vk::LOD_CLAMP_NONE);
+
+    unsafe { device.create_sampler(&sampler_info, None, None) }.expect("Failed to create sampler!")
+}
@@ -0,0 +1,318 @@
+use crate::application::buffer;
+use crate::application::handle::{self, Owned};
+
+use core::ffi::c_void;
+use erupt::{vk, DeviceLoader, InstanceLoader};
+
+use std::mem::{self, size_of};
+use std::rc::Rc;
+
+// a persistently mapped buffer per frame in flight, a frame allocates its uniform blocks linearly
+// from the start of its region every time it's recorded & binds them through dynamic offsets, a
+// region that fills up is replaced by a larger one
+pub struct UniformRing {
+    device: Rc<handle::Device>,
+    regions: Vec<Region>,
+    // regions replaced this frame, retired through the deletion queue by the caller
+    replaced: Vec<buffer::OwnedBuffer>,
+    memory_type: u32,
+    // writes are flushed before the frame is submitted otherwise
+    coherent: bool,
+    // minUniformBufferOffsetAlignment, every block starts on a multiple of it
+    alignment: u64,
+    // nonCoherentAtomSize, flushed ranges start & end on multiples of it
+    atom_size: u64,
+    // region allocated from & how much of it is used
+    region: usize,
+    head: u64,
+}
+
+struct Region {
+    buffer: buffer::OwnedBuffer,
+    // start of the buffer in host memory, stays mapped until the memory is freed
+    mapped: *mut u8,
+    size: u64,
+}
+
+impl UniformRing {
+    // block_sizes are the sizes of the blocks a frame allocates, each region starts out fitting
+    // them all
+    pub fn new(
+        instance: &InstanceLoader,
+        physical_device: &vk::PhysicalDevice,
+        device: &Rc<handle::Device>,
+        limits: &vk::PhysicalDeviceLimits,
+        block_sizes: &[usize],
+        frames_in_flight: usize,
+    ) -> UniformRing {
+        let alignment = limits.min_uniform_buffer_offset_alignment.max(1);
+        let atom_size = limits.non_coherent_atom_size.max(1);
+
+        let region_size = get_region_size(block_sizes, alignment, atom_size);
+
+        let buffers: Vec<_> = (0..frames_in_flight)
+            .map(|index| create_buffer(device, region_size, index))
+            .collect();
+
+        // buffers with the same usage share memory type bits, so the type suits later regions too
+        let memory_requirements =
+            unsafe { device.get_buffer_memory_requirements(*buffers[0], None) };
+
+        // fall back to flushing when no host visible memory is coherent
+        let coherent_type = buffer::find_memory_type(
+            instance,
+            physical_device,
+            memory_requirements.memory_type_bits,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        );
+        let coherent = coherent_type.is_some();
+        let memory_type = coherent_type.unwrap_or_else(|| {
+            buffer::find_physical_device_memory(
+                instance,
+                physical_device,
+                memory_requirements.memory_type_bits,
+                vk::MemoryPropertyFlags::HOST_VISIBLE,
+            )
+        });
+
+        let regions = buffers
+            .into_iter()
+            .enumerate()
+            .map(|(index, buffer)| create_region(device, buffer, memory_type, region_size, index))
+            .collect();
+
+        UniformRing {
+            device: device.clone(),
+            regions,
+            replaced: Vec::new(),
+            memory_type,
+            coherent,
+            alignment,
+            atom_size,
+            region: 0,
+            head: 0,
+        }
+    }
+
+    pub fn is_coherent(&self) -> bool {
+        self.coherent
+    }
+
+    // buffer the frame's descriptor set has to point at
+    pub fn get_buffer(&self, frame_index: usize) -> vk::Buffer {
+        *self.regions[frame_index].buffer.0
+    }
+
+    // starts allocating from the start of the frame's region, its last submit must have finished
+    pub fn begin_frame(&mut self, frame_index: usize) {
+        self.region = frame_index;
+        self.head = 0;
+    }
+
+    // copies the block into the frame's region, returns the dynamic offset to bind it at
+    pub fn push<T>(&mut self, block: &T) -> u32 {
+        let size = size_of::<T>() as u64;
+        if self.head + size > self.regions[self.region].size {
+            self.grow(self.head + size);
+        }
+
+        let offset = self.head;
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                block as *const T as *const u8,
+                self.regions[self.region].mapped.add(offset as usize),
+                size as usize,
+            );
+        }
+
+        self.head = get_next_head(self.head, size, self.alignment);
+
+        offset as u32
+    }
+
+    // replaces the frame's region with one at least twice the size, the blocks pushed so far are
+    // copied over so their offsets stay valid
+    fn grow(&mut self, required: u64) {
+        let region = &self.regions[self.region];
+        let size = get_grown_size(required, region.size, self.alignment, self.atom_size);
+
+        let buffer = create_buffer(&self.device, size, self.region);
+        let grown = create_region(&self.device, buffer, self.memory_type, size, self.region);
+        unsafe { core::ptr::copy_nonoverlapping(region.mapped, grown.mapped, self.head as usize) };
+
+        let replaced = mem::replace(&mut self.regions[self.region], grown);
+        self.replaced.push(replaced.buffer);
+    }
+
+    // regions replaced since the last call, the frame's descriptor set still points at them
+    pub fn take_replaced(&mut self) -> Vec<buffer::OwnedBuffer> {
+        mem::take(&mut self.replaced)
+    }
+
+    // makes the frame's blocks visible to the gpu, nothing to do for coherent memory
+    pub fn flush(&self, device: &DeviceLoader) {
+        if self.coherent || self.head == 0 {
+            return;
+        }
+
+        let range = vk::MappedMemoryRangeBuilder::new()
+            .memory(*self.regions[self.region].buffer.1)
+            .offset(0)
+            .size(self.head.next_multiple_of(self.atom_size));
+
+        unsafe { device.flush_mapped_memory_ranges(&[range]) }
+            .expect("Failed to flush uniform ring memory!");
+    }
+}
+
+// both are powers of two, so regions aligned to the larger suit offsets & flushes
+fn get_region_size(block_sizes: &[usize], alignment: u64, atom_size: u64) -> u64 {
+    block_sizes
+        .iter()
+        .map(|&size| (size as u64).next_multiple_of(alignment))
+        .sum::<u64>()
+        .next_multiple_of(alignment.max(atom_size))
+}
+
+// where the block after one of size pushed at head starts, region sizes are multiples of the
+// alignment so this never passes the region's end
+fn get_next_head(head: u64, size: u64, alignment: u64) -> u64 {
+    (head + size).next_multiple_of(alignment)
+}
+
+// at least doubles so a frame pushing more every time only grows a few times
+fn get_grown_size(required: u64, size: u64, alignment: u64, atom_size: u64) -> u64 {
+    required
+        .max(size * 2)
+        .next_multiple_of(alignment.max(atom_size))
+}
+
+fn create_buffer(device: &Rc<handle::Device>, size: u64, index: usize) -> Owned<vk::Buffer> {
+    let buffer_info = vk::BufferCreateInfoBuilder::new()
+        .size(size)
+        .usage(vk::BufferUsageFlags::UNIFORM_BUFFER)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+    let buffer = unsafe { device.create_buffer(&buffer_info, None, None) }
+        .expect("Failed to create uniform ring buffer!");
+    Owned::new(device, buffer, &format!("uniform ring {}", index))
+}
+
+fn create_region(
+    device: &Rc<handle::Device>,
+    buffer: Owned<vk::Buffer>,
+    memory_type: u32,
+    size: u64,
+    index: usize,
+) -> Region {
+    let memory_requirements = unsafe { device.get_buffer_memory_requirements(*buffer, None) };
+
+    let allocation_info = vk::MemoryAllocateInfoBuilder::new()
+        .allocation_size(memory_requirements.size)
+        .memory_type_index(memory_type);
+
+    let memory = unsafe { device.allocate_memory(&allocation_info, None, None) }
+        .expect("Failed to allocate uniform ring memory!");
+    let memory = Owned::new(device, memory, &format!("uniform ring {}", index));
+
+    unsafe { device.bind_buffer_memory(*buffer, *memory, 0) }
+        .expect("Failed to bind uniform ring memory!");
+
+    let mut data: *mut c_void = core::ptr::null_mut();
+    unsafe { device.map_memory(*memory, 0, vk::WHOLE_SIZE, None, &mut data) }
+        .expect("Failed to map uniform ring memory!");
+
+    Region {
+        buffer: (buffer, memory),
+        mapped: data as *mut u8,
+        size,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // pushes blocks back to back the way push does, returns their offsets & the final head
+    fn push_all(sizes: &[usize], alignment: u64) -> (Vec<u64>, u64) {
+        let mut head = 0;
+        let offsets = sizes
+            .iter()
+            .map(|&size| {
+                let offset = head;
+                head = get_next_head(head, size as u64, alignment);
+                offset
+            })
+            .collect();
+        (offsets, head)
+    }
+
+    #[test]
+    fn offsets_are_aligned() {
+        for &alignment in &[1, 4, 64, 256] {
+            let (offsets, _) = push_all(&[64, 80, 4, 1, 256, 3], alignment);
+            assert!(offsets.iter().all(|offset| offset % alignment == 0));
+        }
+    }
+
+    #[test]
+    fn blocks_do_not_overlap() {
+        let sizes = [64, 80, 4, 1, 256, 3];
+        let (offsets, _) = push_all(&sizes, 256);
+
+        assert_eq!(offsets, vec![0, 256, 512, 768, 1024, 1280]);
+        for (pair, size) in offsets.windows(2).zip(sizes.iter()) {
+            assert!(pair[0] + *size as u64 <= pair[1]);
+        }
+    }
+
+    #[test]
+    fn aligned_blocks_are_packed() {
+        let (offsets, head) = push_all(&[64, 64, 128], 64);
+        assert_eq!(offsets, vec![0, 64, 128]);
+        assert_eq!(head, 256);
+    }
+
+    #[test]
+    fn region_fits_every_block() {
+        for &(alignment, atom_size) in &[(1, 1), (16, 64), (256, 64), (64, 256)] {
+            let sizes = [64, 80, 4, 1, 256, 3];
+            let region_size = get_region_size(&sizes, alignment, atom_size);
+            let (_, head) = push_all(&sizes, alignment);
+
+            assert!(head <= region_size);
+            assert_eq!(region_size % alignment, 0);
+            assert_eq!(region_size % atom_size, 0);
+        }
+    }
+
+    #[test]
+    fn empty_region_is_empty() {
+        assert_eq!(get_region_size(&[], 256, 64), 0);
+    }
+
+    #[test]
+    fn growth_at_least_doubles() {
+        assert_eq!(get_grown_size(300, 256, 256, 64), 512);
+        assert_eq!(get_grown_size(513, 512, 256, 64), 1024);
+    }
+
+    #[test]
+    fn growth_fits_large_blocks() {
+        let grown = get_grown_size(5000, 256, 256, 64);
+        assert!(grown >= 5000);
+        assert_eq!(grown, 5120);
+    }
+
+    #[test]
+    fn grown_size_is_aligned() {
+        for &(alignment, atom_size) in &[(1, 1), (16, 64), (256, 64), (64, 256)] {
+            for &required in &[1, 100, 257, 1000, 4097] {
+                let grown = get_grown_size(required, 96, alignment, atom_size);
+                assert!(grown >= required && grown >= 192);
+                assert_eq!(grown % alignment, 0);
+                assert_eq!(grown % atom_size, 0);
+            }
+        }
+    }
+}
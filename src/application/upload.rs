@@ -0,0 +1,80 @@
+use crate::application::deletion::DeletionQueue;
+use crate::application::handle::{self, Owned};
+use crate::application::sync::FrameSync;
+use crate::application::{debug, render};
+
+use erupt::vk;
+
+use std::rc::Rc;
+
+// one time transfer & compute work on the graphics queue, each submit is numbered on the frame
+// timeline & what it used is retired through the deletion queue once that number has finished,
+// without a timeline each submit is waited on by its own fence
+pub struct Uploader<'a> {
+    queue: vk::Queue,
+    queue_family: u32,
+    frame_sync: &'a mut FrameSync,
+    deletion_queue: &'a mut DeletionQueue,
+}
+
+impl<'a> Uploader<'a> {
+    pub fn new(
+        queue: vk::Queue,
+        queue_family: u32,
+        frame_sync: &'a mut FrameSync,
+        deletion_queue: &'a mut DeletionQueue,
+    ) -> Uploader<'a> {
+        Uploader {
+            queue,
+            queue_family,
+            frame_sync,
+            deletion_queue,
+        }
+    }
+
+    // a command buffer from a transient pool of its own, freed with the pool after the submit
+    pub fn begin(
+        &self,
+        device: &Rc<handle::Device>,
+        name: &str,
+    ) -> (Owned<vk::CommandPool>, vk::CommandBuffer) {
+        let command_pool = Owned::new(
+            device,
+            render::create_command_pool(
+                device,
+                self.queue_family,
+                vk::CommandPoolCreateFlags::TRANSIENT,
+            ),
+            &format!("{} command pool", name),
+        );
+
+        let command_buffer = render::allocate_command_buffers(device, &command_pool, 1)[0];
+        debug::set_object_name(device, command_buffer, &format!("{} command buffer", name));
+
+        let begin_info = vk::CommandBufferBeginInfoBuilder::new()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        unsafe { device.begin_command_buffer(command_buffer, &begin_info) }
+            .expect("Failed to begin recording upload command buffer!");
+
+        (command_pool, command_buffer)
+    }
+
+    // submits without waiting, the pool & whatever the commands read are dropped once they've
+    // finished
+    pub fn end<T: 'static>(
+        &mut self,
+        device: &Rc<handle::Device>,
+        command_pool: Owned<vk::CommandPool>,
+        command_buffer: vk::CommandBuffer,
+        retired: T,
+    ) {
+        unsafe { device.end_command_buffer(command_buffer) }
+            .expect("Failed to end recording upload command buffer!");
+
+        let value = self
+            .frame_sync
+            .submit_upload(device, self.queue, command_buffer);
+        self.deletion_queue.push(value, (command_pool, retired));
+    }
+}
@@ -0,0 +1,216 @@
+use erupt::vk;
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+use std::slice;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// log target prefix, filter with RUST_LOG=vulkan=info etc.
+const LOG_TARGET: &str = "vulkan";
+
+// state the debug callback reports into, passed to it as the messenger's user data so has to
+// outlive the messenger
+pub struct Validation {
+    // message id names (VUID-...) or numbers (decimal or 0x hex) that aren't reported
+    suppressed: Vec<String>,
+    // panic on the first validation error, at the end of the frame, startup or teardown it was
+    // reported in
+    fatal: bool,
+    // message id name of the first error while fatal, the callback can't unwind into the driver
+    first_error: Mutex<Option<String>>,
+    // errors reported since the last end_frame
+    errors: AtomicU32,
+    frame: AtomicU64,
+}
+
+impl Validation {
+    pub fn new(suppressed: Vec<String>, fatal: bool) -> Box<Validation> {
+        Box::new(Validation {
+            suppressed,
+            fatal,
+            first_error: Mutex::new(None),
+            errors: AtomicU32::new(0),
+            frame: AtomicU64::new(0),
+        })
+    }
+
+    fn is_suppressed(&self, id_name: &str, id_number: i32) -> bool {
+        self.suppressed.iter().any(|suppressed| {
+            let number = match suppressed.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => suppressed.parse::<i64>().ok().map(|number| number as u32),
+            };
+
+            suppressed == id_name || number == Some(id_number as u32)
+        })
+    }
+
+    // logs & resets the error count of the frame just recorded, presented or not, returns it,
+    // panics if fatal & there was an error
+    pub fn end_frame(&self) -> u32 {
+        let frame = self.frame.fetch_add(1, Ordering::Relaxed);
+        let errors = self.errors.swap(0, Ordering::Relaxed);
+
+        if errors > 0 {
+            log::warn!(target: LOG_TARGET, "{} validation errors in frame {}", errors, frame);
+        }
+
+        self.check_fatal();
+
+        errors
+    }
+
+    // panics if fatal & an error was reported since the last check, called after every frame &
+    // once startup & teardown are done
+    pub fn check_fatal(&self) {
+        let first_error = self
+            .first_error
+            .lock()
+            .expect("Failed to lock first validation error!")
+            .take();
+        if let Some(id_name) = first_error {
+            panic!("Validation error {} with --validation-fatal!", id_name);
+        }
+    }
+}
+
+// extra checks VK_EXT_validation_features enables in the validation layer, all slow things down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationFeature {
+    // read/write hazards between commands, missing or wrong barriers
+    Synchronization,
+    // instruments shaders to check descriptor indexing & buffer access out of bounds
+    GpuAssisted,
+    // warnings for valid but slow usage
+    BestPractices,
+}
+
+impl ValidationFeature {
+    pub fn get_enables(self) -> &'static [vk::ValidationFeatureEnableEXT] {
+        match self {
+            ValidationFeature::Synchronization => {
+                &[vk::ValidationFeatureEnableEXT::SYNCHRONIZATION_VALIDATION_EXT]
+            }
+            // the reserved slot is the one the layer binds its output buffer to
+            ValidationFeature::GpuAssisted => &[
+                vk::ValidationFeatureEnableEXT::GPU_ASSISTED_EXT,
+                vk::ValidationFeatureEnableEXT::GPU_ASSISTED_RESERVE_BINDING_SLOT_EXT,
+            ],
+            ValidationFeature::BestPractices => {
+                &[vk::ValidationFeatureEnableEXT::BEST_PRACTICES_EXT]
+            }
+        }
+    }
+}
+
+impl FromStr for ValidationFeature {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "sync" | "synchronization" => Ok(ValidationFeature::Synchronization),
+            "gpu" | "gpu-assisted" => Ok(ValidationFeature::GpuAssisted),
+            "best-practices" => Ok(ValidationFeature::BestPractices),
+            _ => Err(format!(
+                "Unknown validation feature {:?}, expected sync, gpu-assisted or best-practices",
+                name
+            )),
+        }
+    }
+}
+
+unsafe fn to_str<'a>(string: *const c_char) -> Option<std::borrow::Cow<'a, str>> {
+    if string.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(string).to_string_lossy())
+    }
+}
+
+fn get_level(severity: vk::DebugUtilsMessageSeverityFlagBitsEXT) -> log::Level {
+    match severity {
+        vk::DebugUtilsMessageSeverityFlagBitsEXT::ERROR_EXT => log::Level::Error,
+        vk::DebugUtilsMessageSeverityFlagBitsEXT::WARNING_EXT => log::Level::Warn,
+        vk::DebugUtilsMessageSeverityFlagBitsEXT::INFO_EXT => log::Level::Info,
+        _ => log::Level::Trace,
+    }
+}
+
+fn get_target(message_types: vk::DebugUtilsMessageTypeFlagsEXT) -> &'static str {
+    if message_types.contains(vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION_EXT) {
+        "vulkan::validation"
+    } else if message_types.contains(vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE_EXT) {
+        "vulkan::performance"
+    } else {
+        "vulkan::general"
+    }
+}
+
+// objects the message refers to, with the names given by debug::set_object_name
+unsafe fn format_objects(callback_data: &vk::DebugUtilsMessengerCallbackDataEXT) -> String {
+    if callback_data.p_objects.is_null() || callback_data.object_count == 0 {
+        return String::new();
+    }
+
+    let objects =
+        slice::from_raw_parts(callback_data.p_objects, callback_data.object_count as usize);
+    let objects: Vec<_> = objects
+        .iter()
+        .map(|object| match to_str(object.p_object_name) {
+            Some(name) => format!(
+                "{:?} {:#x} \"{}\"",
+                object.object_type, object.object_handle, name
+            ),
+            None => format!("{:?} {:#x}", object.object_type, object.object_handle),
+        })
+        .collect();
+
+    format!(" [{}]", objects.join(", "))
+}
+
+// debug callback signature
+pub unsafe extern "system" fn debug_callback(
+    message_severity: vk::DebugUtilsMessageSeverityFlagBitsEXT,
+    message_types: vk::DebugUtilsMessageTypeFlagsEXT,
+    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+    p_user_data: *mut c_void,
+) -> vk::Bool32 {
+    let validation = &*(p_user_data as *const Validation);
+    let callback_data = &*p_callback_data;
+
+    let id_name = to_str(callback_data.p_message_id_name).unwrap_or_default();
+    if validation.is_suppressed(&id_name, callback_data.message_id_number) {
+        return vk::FALSE;
+    }
+
+    let level = get_level(message_severity);
+    let is_error = level == log::Level::Error
+        && message_types.contains(vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION_EXT);
+    if is_error {
+        validation.errors.fetch_add(1, Ordering::Relaxed);
+
+        // panicked on by end_frame, unwinding out of here would be undefined behaviour
+        if validation.fatal {
+            if let Ok(mut first_error) = validation.first_error.lock() {
+                first_error.get_or_insert_with(|| id_name.to_string());
+            }
+        }
+    }
+
+    let message = to_str(callback_data.p_message).unwrap_or_default();
+    let objects = format_objects(callback_data);
+
+    log::log!(
+        target: get_target(message_types),
+        level,
+        "{} ({:#x}){}: {}",
+        id_name,
+        callback_data.message_id_number as u32,
+        objects,
+        message
+    );
+
+    vk::FALSE
+}
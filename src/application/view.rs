@@ -0,0 +1,49 @@
+use winit::event::VirtualKeyCode;
+
+// ways of drawing the scene for inspecting meshes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ViewMode {
+    #[default]
+    Shaded,
+    // shaded with lines drawn over each triangle edge
+    Wireframe,
+    Points,
+    Normals,
+    UvChecker,
+    Depth,
+    // colored by level of detail
+    Lod,
+}
+
+impl ViewMode {
+    // F1-F7 select view mode
+    pub fn from_keycode(keycode: VirtualKeyCode) -> Option<ViewMode> {
+        match keycode {
+            VirtualKeyCode::F1 => Some(ViewMode::Shaded),
+            VirtualKeyCode::F2 => Some(ViewMode::Wireframe),
+            VirtualKeyCode::F3 => Some(ViewMode::Points),
+            VirtualKeyCode::F4 => Some(ViewMode::Normals),
+            VirtualKeyCode::F5 => Some(ViewMode::UvChecker),
+            VirtualKeyCode::F6 => Some(ViewMode::Depth),
+            VirtualKeyCode::F7 => Some(ViewMode::Lod),
+            _ => None,
+        }
+    }
+
+    // modes that light the mesh & so sample the shadow maps
+    pub fn is_lit(self) -> bool {
+        matches!(self, ViewMode::Shaded | ViewMode::Wireframe)
+    }
+
+    // value read by the fragment shader to pick what to output
+    // (must match the VIEW_MODE_* constants in shader.frag)
+    pub fn shader_index(self) -> u32 {
+        match self {
+            ViewMode::Shaded | ViewMode::Wireframe | ViewMode::Points => 0,
+            ViewMode::Normals => 1,
+            ViewMode::UvChecker => 2,
+            ViewMode::Depth => 3,
+            ViewMode::Lod => 4,
+        }
+    }
+}
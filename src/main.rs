@@ -3,6 +3,9 @@ use application::Application;
 use erupt::EntryLoader;
 
 fn main() {
+    // validation messages are logged, RUST_LOG=vulkan=info shows more than warnings & errors
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
     println!("Program Starting!");
 
     let entry = EntryLoader::new().unwrap();